@@ -0,0 +1,199 @@
+// 比较arena语法树和每个节点一个Box的语法树的内存分配次数
+// 运行: cargo bench --bench ast_alloc
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use compiler_rust::compiler_core::ast::{Ast, ExprId, StmtId};
+use compiler_rust::compiler_core::expr::{Expr, LiteralValue, TemplatePart};
+use compiler_rust::compiler_core::lexer::Lexer;
+use compiler_rust::compiler_core::parser::Parser;
+use compiler_rust::compiler_core::stmt::Stmt;
+use compiler_rust::compiler_core::token::Token;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// 以前的语法树：子节点都是Box，和arena里的节点一一对应
+#[allow(dead_code)]
+enum BoxedExpr {
+    Binary(Box<BoxedExpr>, Token, Box<BoxedExpr>),
+    Grouping(Box<BoxedExpr>),
+    Literal(LiteralValue),
+    Unary(Token, Box<BoxedExpr>),
+    Interpolation(Vec<BoxedExpr>),
+    Variable(Token),
+    Assign(Token, Box<BoxedExpr>),
+    CompoundAssign(Token, Token, Box<BoxedExpr>),
+    Logical(Box<BoxedExpr>, Token, Box<BoxedExpr>),
+    Call(Box<BoxedExpr>, Token, Vec<BoxedExpr>),
+    Function(Vec<Token>, Vec<BoxedStmt>),
+    List(Token, Vec<BoxedExpr>),
+    Index(Box<BoxedExpr>, Token, Box<BoxedExpr>),
+}
+
+#[allow(dead_code)]
+enum BoxedStmt {
+    Expression(Box<BoxedExpr>),
+    Print(Box<BoxedExpr>),
+    Let(Token, Option<Box<BoxedExpr>>),
+    Block(Vec<BoxedStmt>),
+    If(Box<BoxedExpr>, Box<BoxedStmt>, Option<Box<BoxedStmt>>),
+    While(Box<BoxedExpr>, Box<BoxedStmt>),
+    For(Option<Box<BoxedStmt>>, Option<Box<BoxedExpr>>, Option<Box<BoxedExpr>>, Box<BoxedStmt>),
+    Function(Option<Token>, Vec<Token>, Vec<BoxedStmt>),
+    Return(Option<Box<BoxedExpr>>),
+    Break,
+}
+
+// 按arena里的树建一棵同样的Box树，token和Parser一样clone一份(不包括类型标注，生成的源码里没有)
+// 这样分配的是Box语法树的节点和token，再加上词法分析的分配，就是直接解析成Box树的分配
+fn boxed_expr(ast: &Ast, id: ExprId) -> BoxedExpr {
+    match &ast[id] {
+        Expr::Binary(expr) => BoxedExpr::Binary(boxed(ast, expr.left), expr.op.clone(), boxed(ast, expr.right)),
+        Expr::Grouping(expr) => BoxedExpr::Grouping(boxed(ast, expr.expr)),
+        Expr::Literal(expr) => BoxedExpr::Literal(expr.value.clone()),
+        Expr::Unary(expr) => BoxedExpr::Unary(expr.op.clone(), boxed(ast, expr.right)),
+        Expr::Interpolation(expr) => BoxedExpr::Interpolation(
+            expr.parts
+                .iter()
+                .map(|part| match part {
+                    TemplatePart::Chunk(chunk) => BoxedExpr::Literal(LiteralValue::String(chunk.clone())),
+                    TemplatePart::Expr(expr) => boxed_expr(ast, *expr),
+                })
+                .collect(),
+        ),
+        Expr::Variable(expr) => BoxedExpr::Variable(expr.name.clone()),
+        Expr::Assign(expr) => BoxedExpr::Assign(expr.name.clone(), boxed(ast, expr.value)),
+        Expr::CompoundAssign(expr) => BoxedExpr::CompoundAssign(expr.name.clone(), expr.op.clone(), boxed(ast, expr.value)),
+        Expr::Logical(expr) => BoxedExpr::Logical(boxed(ast, expr.left), expr.op.clone(), boxed(ast, expr.right)),
+        Expr::Call(expr) => BoxedExpr::Call(
+            boxed(ast, expr.callee),
+            expr.paren.clone(),
+            expr.arguments.iter().map(|&argument| boxed_expr(ast, argument)).collect(),
+        ),
+        Expr::Function(function) => BoxedExpr::Function(function.params.clone(), boxed_stmts(ast, &function.body)),
+        Expr::List(expr) => BoxedExpr::List(
+            expr.bracket.clone(),
+            expr.elements.iter().map(|&element| boxed_expr(ast, element)).collect(),
+        ),
+        Expr::Index(expr) => BoxedExpr::Index(boxed(ast, expr.object), expr.bracket.clone(), boxed(ast, expr.index)),
+    }
+}
+
+fn boxed(ast: &Ast, id: ExprId) -> Box<BoxedExpr> {
+    Box::new(boxed_expr(ast, id))
+}
+
+fn boxed_stmt(ast: &Ast, id: StmtId) -> BoxedStmt {
+    match &ast[id] {
+        Stmt::Expression(stmt) => BoxedStmt::Expression(boxed(ast, stmt.expr)),
+        Stmt::Print(stmt) => BoxedStmt::Print(boxed(ast, stmt.expr)),
+        Stmt::Let(stmt) => BoxedStmt::Let(stmt.name.clone(), stmt.initializer.map(|expr| boxed(ast, expr))),
+        Stmt::Block(stmt) => BoxedStmt::Block(boxed_stmts(ast, &stmt.stmts)),
+        Stmt::If(stmt) => BoxedStmt::If(
+            boxed(ast, stmt.condition),
+            Box::new(boxed_stmt(ast, stmt.then_branch)),
+            stmt.else_branch.map(|stmt| Box::new(boxed_stmt(ast, stmt))),
+        ),
+        Stmt::While(stmt) => BoxedStmt::While(boxed(ast, stmt.condition), Box::new(boxed_stmt(ast, stmt.body))),
+        Stmt::For(stmt) => BoxedStmt::For(
+            stmt.initializer.map(|stmt| Box::new(boxed_stmt(ast, stmt))),
+            stmt.condition.map(|expr| boxed(ast, expr)),
+            stmt.increment.map(|expr| boxed(ast, expr)),
+            Box::new(boxed_stmt(ast, stmt.body)),
+        ),
+        Stmt::Function(function) => {
+            BoxedStmt::Function(function.name.clone(), function.params.clone(), boxed_stmts(ast, &function.body))
+        }
+        Stmt::Return(stmt) => BoxedStmt::Return(stmt.value.map(|expr| boxed(ast, expr))),
+        Stmt::Break(_) => BoxedStmt::Break,
+    }
+}
+
+fn boxed_stmts(ast: &Ast, stmts: &[StmtId]) -> Vec<BoxedStmt> {
+    stmts.iter().map(|&stmt| boxed_stmt(ast, stmt)).collect()
+}
+
+// 生成大约1MB的源码
+fn generate_source() -> String {
+    let mut source = String::new();
+    let mut i = 0;
+    while source.len() < 1024 * 1024 {
+        source.push_str(&format!(
+            "fn func(a, b) {{ let x = (a + {i}) * b - 1; if x > 10 and !done {{ return g(x, \"s ${{x}}\"); }} return -x; }}\n"
+        ));
+        i += 1;
+    }
+    source
+}
+
+struct Measurement {
+    elapsed: Duration,
+    allocations: usize,
+    bytes: usize,
+}
+
+fn measure<T>(build: impl FnOnce() -> T) -> (T, Measurement) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let result = build();
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let bytes = BYTES.load(Ordering::Relaxed) - bytes;
+    (result, Measurement { elapsed, allocations, bytes })
+}
+
+fn report(name: &str, nodes: usize, measurement: &Measurement) {
+    println!(
+        "{name:>6}: {nodes} nodes in {:?}, {} allocations ({:.2} per node), {} bytes",
+        measurement.elapsed,
+        measurement.allocations,
+        measurement.allocations as f64 / nodes as f64,
+        measurement.bytes,
+    );
+}
+
+fn main() {
+    let source = generate_source();
+    println!("input: {} bytes", source.len());
+
+    // 先解析一次得到节点数
+    let mut parser = Parser::new(&source);
+    parser.parse_program();
+    let nodes = parser.ast().exprs.len() + parser.ast().stmts.len();
+
+    // 两种语法树都要先做词法分析，token的lexeme两边一样要分配，差别在于节点本身
+    let (_, lex) = measure(|| Lexer::new(&source).count());
+    let ((ast, program), arena) = measure(|| {
+        let mut parser = Parser::new(&source);
+        let program = parser.parse_program();
+        (parser.into_ast(), program)
+    });
+    let (_, convert) = measure(|| boxed_stmts(&ast, &program));
+    let boxed = Measurement {
+        elapsed: lex.elapsed + convert.elapsed,
+        allocations: lex.allocations + convert.allocations,
+        bytes: lex.bytes + convert.bytes,
+    };
+    report("arena", nodes, &arena);
+    report("boxed", nodes, &boxed);
+}
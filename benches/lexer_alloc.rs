@@ -0,0 +1,60 @@
+// 比较两种token流在大文件上的内存分配次数
+// 运行: cargo bench --bench lexer_alloc
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use compiler_rust::compiler_core::lexer::Lexer;
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// 生成大约4MB的源码
+fn generate_source() -> String {
+    let mut source = String::new();
+    let mut i = 0;
+    while source.len() < 4 * 1024 * 1024 {
+        source.push_str(&format!(
+            "let value{i} = (count + {i}) * 2.5 >= limit; // line {i}\nprint \"item {i}\";\n"
+        ));
+        i += 1;
+    }
+    source
+}
+
+fn measure(name: &str, source: &str, lex: impl Fn(&str) -> usize) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let count = lex(source);
+    let elapsed = start.elapsed();
+    println!(
+        "{name:>8}: {count} tokens in {elapsed:?}, {} allocations, {} bytes",
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        BYTES.load(Ordering::Relaxed) - bytes,
+    );
+}
+
+fn main() {
+    let source = generate_source();
+    println!("input: {} bytes", source.len());
+    measure("owned", &source, |source| Lexer::new(source).count());
+    measure("borrowed", &source, |source| Lexer::new(source).borrowed().count());
+}
@@ -0,0 +1,71 @@
+// 关键字查找和lexer吞吐量
+// 运行: cargo bench --bench lexer_throughput
+use std::collections::HashMap;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use compiler_rust::compiler_core::lexer::Lexer;
+use compiler_rust::compiler_core::token::{loopkup_ident, TokenType};
+
+// 原来的实现：每遇到一个标识符都重新构造一次哈希表，留在这里做对比
+fn hashmap_lookup(ident: &str) -> TokenType {
+    let keywords = HashMap::from([
+        ("fn", TokenType::FUNCTION),
+        ("class", TokenType::CLASS),
+        ("let", TokenType::LET),
+        ("if", TokenType::IF),
+        ("else", TokenType::ELSE),
+        ("return", TokenType::RETURN),
+        ("true", TokenType::TRUE),
+        ("false", TokenType::FALSE),
+        ("and", TokenType::AND),
+        ("or", TokenType::OR),
+        ("for", TokenType::FOR),
+        ("while", TokenType::WHILE),
+        ("break", TokenType::BREAK),
+        ("null", TokenType::NULL),
+        ("print", TokenType::PRINT),
+    ]);
+    keywords.get(ident).copied().unwrap_or(TokenType::IDENT)
+}
+
+// 生成大约4MB、以标识符和关键字为主的源码
+fn generate_source() -> String {
+    let names = ["alpha", "beta", "gamma", "delta", "epsilon", "zeta", "counter", "total"];
+    let mut source = String::new();
+    let mut i = 0;
+    while source.len() < 4 * 1024 * 1024 {
+        let a = names[i % names.len()];
+        let b = names[(i * 7 + 3) % names.len()];
+        source.push_str(&format!(
+            "let {a} = {b} and {a} or {b};\nif ({a} != {b}) {{ return {a}; }} else {{ print {b}; }}\n"
+        ));
+        i += 1;
+    }
+    source
+}
+
+fn time<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let start = Instant::now();
+    let result = f();
+    (result, start.elapsed())
+}
+
+fn main() {
+    let source = generate_source();
+    let idents: Vec<&str> = source
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .collect();
+    println!("input: {} bytes, {} identifiers and keywords", source.len(), idents.len());
+
+    let (_, hashmap) = time(|| idents.iter().map(|ident| hashmap_lookup(black_box(ident))).filter(|t| *t == TokenType::IDENT).count());
+    let (_, matched) = time(|| idents.iter().map(|ident| loopkup_ident(black_box(ident))).filter(|t| *t == TokenType::IDENT).count());
+    println!("keyword lookup: hashmap {hashmap:?}, match {matched:?} ({:.1}x)", hashmap.as_secs_f64() / matched.as_secs_f64());
+
+    let mb = source.len() as f64 / (1024.0 * 1024.0);
+    let (count, owned) = time(|| Lexer::new(black_box(&source)).count());
+    println!("owned lexer:    {count} tokens in {owned:?} ({:.1} MB/s)", mb / owned.as_secs_f64());
+    let (count, borrowed) = time(|| Lexer::new(black_box(&source)).borrowed().count());
+    println!("borrowed lexer: {count} tokens in {borrowed:?} ({:.1} MB/s)", mb / borrowed.as_secs_f64());
+}
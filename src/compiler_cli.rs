@@ -1,9 +1,21 @@
-use compiler_core::lexer::Lexer;
-use compiler_core::parser::Parser;
-use compiler_core::visitors::AstPrinter;
-use std::{env, io::{self, Write}};
-
-mod compiler_core;
+use compiler_rust::compiler_core::desugar::desugar;
+use compiler_rust::compiler_core::dump::AstFormat;
+use compiler_rust::compiler_core::dump::TokenFormat;
+use compiler_rust::compiler_core::formatter::format_source;
+use compiler_rust::compiler_core::interpreter::Interpreter;
+use compiler_rust::compiler_core::interpreter::STACK_SIZE;
+use compiler_rust::compiler_core::lint::Linter;
+use compiler_rust::compiler_core::lint::Severity;
+use compiler_rust::compiler_core::optimizer::optimize;
+use compiler_rust::compiler_core::parser::Parser;
+use compiler_rust::compiler_core::resolver::resolve;
+use compiler_rust::compiler_core::stream::StreamLexer;
+use compiler_rust::compiler_core::token::Token;
+use compiler_rust::compiler_core::typecheck::check;
+use std::fs::{self, File};
+use std::process;
+use std::thread;
+use std::{env, io::{self, BufReader, Read, Write}};
 
 static PROMPT: &str = ">> ";
 static HELP_MSG: &str = "
@@ -15,28 +27,131 @@ The commands are:
 
 	lexer/lex       show the lexer structure
 	parser/ast      show the ast structure
-	[default]       evaluate the expression
+	fmt             format source files
+	lint            check source files for common mistakes
+	check           infer the types of a program and report type errors
+	[default]       evaluate the statements
+
+The options of lexer/lex are:
+
+	--format json|table     token output format, default is table
+	<file>                  lex the whole file instead of starting a REPL
+
+The options of parser/ast are:
+
+	--format sexpr|json|tree|dot
+	                        ast output format, default is sexpr
+	--desugar               lower for loops and compound assignments to core syntax before printing
+	--optimize              fold constants and simplify expressions before printing,
+	                        report errors such as division by a constant zero
+	<file>                  parse the whole file instead of starting a REPL
+
+The options of fmt are:
+
+	--check                 only check, exit with 1 if any file is not formatted
+	<file>                  format the file in place, without files read stdin and write stdout
+
+The options of lint are:
+
+	--allow <rule>          turn off a rule, by code (L001) or name (unused-variable)
+	--warn <rule>           report the rule as a warning
+	--deny <rule>           report the rule as an error, exit with 1 if it is found
+	<file>                  lint the file, without files read stdin
+	                        rules: L001 unused-variable, L002 unreachable-code, L003 shadowing,
+	                               L004 self-comparison, L005 constant-condition
+
+The options of check are:
+
+	<file>                  check the file and print the types of its top-level declarations,
+	                        without files read stdin, exit with 1 if there is any error
 	
 ";
 
+// 命令后面的参数
+#[derive(Default)]
+struct Options {
+    format: Option<String>,
+    check: bool,
+    desugar: bool,
+    optimize: bool,
+    // lint的--allow/--warn/--deny和规则
+    levels: Vec<(String, String)>,
+    files: Vec<String>,
+}
+
+fn parse_options(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--format" => match args.next() {
+                Some(format) => options.format = Some(format.clone()),
+                None => return Err("--format needs a value".to_string()),
+            },
+            "--check" => options.check = true,
+            "--desugar" => options.desugar = true,
+            "--optimize" => options.optimize = true,
+            "--allow" | "--warn" | "--deny" => match args.next() {
+                Some(rule) => options.levels.push((arg.clone(), rule.clone())),
+                None => return Err(format!("{arg} needs a rule")),
+            },
+            _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+            _ => options.files.push(arg.clone()),
+        }
+    }
+    Ok(options)
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        // 如果没有带参数，暂时报错
-        // FIXME 默认行为是求值
-        print_help_msg();
+    if args.len() == 1 {
+        // 没有带参数，默认行为是求值，解释器在栈足够大的线程里执行
+        let eval = thread::Builder::new().stack_size(STACK_SIZE).spawn(eval_begin).expect("failed to spawn the interpreter thread");
+        if eval.join().is_err() {
+            process::exit(1);
+        }
         return;
     }
+    let options = match parse_options(&args[2..]) {
+        Ok(options) => options,
+        Err(error) => {
+            println!("error: {error}");
+            print_help_msg();
+            return;
+        }
+    };
     
     match args[1].as_str() {
-        "lexer" | "lex" => lexer_begin(),
-        "parser" | "ast" => parser_begin(),
+        "lexer" | "lex" => lexer_begin(&options),
+        "parser" | "ast" => parser_begin(&options),
+        "fmt" => fmt_begin(&options),
+        "lint" => lint_begin(&options),
+        "check" => check_begin(&options),
         _ => print_help_msg()
     };
 }
 
-fn lexer_begin() {
-    let mut in_buf = "".to_string();
+fn lexer_begin(options: &Options) {
+    let format = match options.format.as_deref().map(TokenFormat::from_name) {
+        None => TokenFormat::Table,
+        Some(Some(format)) => format,
+        Some(None) => {
+            println!("error: unknown format {}", options.format.as_deref().unwrap_or_default());
+            print_help_msg();
+            return;
+        }
+    };
+    if !options.files.is_empty() {
+        for path in &options.files {
+            if let Err(error) = lex_file(path, format) {
+                println!("error: {path}: {error}");
+            }
+        }
+        return;
+    }
+
+    // 整个REPL共用一个lexer，没有结束的字符串和注释可以接着在下一行输入
+    let mut lexer = StreamLexer::new(io::stdin().lock());
     loop {
         // 进入无限循环，持续解析每个输入
         print!("{PROMPT}");
@@ -44,21 +159,57 @@ fn lexer_begin() {
         // 这会导致stdin完成后才会统一输出(将两次输出合并为一次)
         // 我们需要在每次输入前打印出promt，所以这里需要刷新stdout
         let _ = io::stdout().flush();
-        match io::stdin().read_line(&mut in_buf) {
-            Ok(_) => {
-                let lexer = Lexer::new(&in_buf);
-                for tok in lexer {
-                    println!("{:?}", tok);
+        match lexer.read_chunk() {
+            Ok(true) => {
+                let tokens = lexer.buffered_tokens();
+                if !tokens.is_empty() {
+                    print!("{}", format.format(&tokens));
                 }
-                // 因为read_line会在buf后面append，所以每次执行完后需要clear
-                in_buf.clear();
             }
-            Err(error) => println!("error: {error}")
+            // 输入结束(Ctrl-D)，剩下的token和EOF
+            Ok(false) => {
+                match lexer.collect::<io::Result<Vec<Token>>>() {
+                    Ok(tokens) => print!("{}", format.format(&tokens)),
+                    Err(error) => println!("error: {error}"),
+                }
+                return;
+            }
+            Err(error) => {
+                println!("error: {error}");
+                return;
+            }
         }
     }
 }
 
-fn parser_begin() {
+// 文件可能很大，用StreamLexer分块读取
+fn lex_file(path: &str, format: TokenFormat) -> io::Result<()> {
+    let reader = BufReader::new(File::open(path)?);
+    let tokens = StreamLexer::new(reader).collect::<io::Result<Vec<Token>>>()?;
+    print!("{}", format.format(&tokens));
+    Ok(())
+}
+
+fn parser_begin(options: &Options) {
+    let format = match options.format.as_deref().map(AstFormat::from_name) {
+        None => AstFormat::SExpr,
+        Some(Some(format)) => format,
+        Some(None) => {
+            println!("error: unknown format {}", options.format.as_deref().unwrap_or_default());
+            print_help_msg();
+            return;
+        }
+    };
+    if !options.files.is_empty() {
+        for path in &options.files {
+            match fs::read_to_string(path) {
+                Ok(source) => print!("{}", parse_source(&source, format, options)),
+                Err(error) => println!("error: {path}: {error}"),
+            }
+        }
+        return;
+    }
+
     let mut in_buf = "".to_string();
     loop {
         // 进入无限循环，持续解析每个输入
@@ -68,11 +219,10 @@ fn parser_begin() {
         // 我们需要在每次输入前打印出promt，所以这里需要刷新stdout
         let _ = io::stdout().flush();
         match io::stdin().read_line(&mut in_buf) {
+            // 输入结束(Ctrl-D)
+            Ok(0) => return,
             Ok(_) => {
-                let mut parser = Parser::new(&in_buf);
-                let expression = parser.parse();
-                let mut printer = AstPrinter::new();
-                println!("{}", expression.accept(&mut printer));
+                print!("{}", parse_source(&in_buf, format, options));
                 
                 // 因为read_line会在buf后面append，所以每次执行完后需要clear
                 in_buf.clear();
@@ -82,6 +232,199 @@ fn parser_begin() {
     }
 }
 
+// 语法错误由parser直接打印，出错的语句不影响其他语句的输出
+// --desugar在--optimize之前，折叠的是降低之后的树
+fn parse_source(source: &str, format: AstFormat, options: &Options) -> String {
+    let mut parser = Parser::new(source);
+    let mut program = parser.parse_program();
+    let mut ast = parser.into_ast();
+    if options.desugar {
+        (ast, program) = desugar(&ast, &program);
+    }
+    if options.optimize {
+        for error in optimize(&mut ast, &program) {
+            println!("error: {error}");
+        }
+    }
+    format.format(&ast, &program)
+}
+
+fn fmt_begin(options: &Options) {
+    let mut ok = true;
+    if options.files.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = io::stdin().read_to_string(&mut source) {
+            println!("error: {error}");
+            process::exit(1);
+        }
+        match format_source(&source) {
+            Some(formatted) if options.check => ok = formatted == source,
+            Some(formatted) => print!("{formatted}"),
+            None => ok = false,
+        }
+    }
+    for path in &options.files {
+        ok &= fmt_file(path, options.check);
+    }
+    if !ok {
+        process::exit(1);
+    }
+}
+
+// 有语法错误的文件不改写
+fn fmt_file(path: &str, check: bool) -> bool {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(error) => {
+            println!("error: {path}: {error}");
+            return false;
+        }
+    };
+    let Some(formatted) = format_source(&source) else {
+        println!("error: {path}: syntax error");
+        return false;
+    };
+    if formatted == source {
+        return true;
+    }
+    if check {
+        println!("{path} is not formatted");
+        return false;
+    }
+    if let Err(error) = fs::write(path, formatted) {
+        println!("error: {path}: {error}");
+        return false;
+    }
+    true
+}
+
+fn lint_begin(options: &Options) {
+    let mut linter = Linter::new();
+    for (flag, rule) in &options.levels {
+        let level = match flag.as_str() {
+            "--allow" => None,
+            "--warn" => Some(Severity::Warning),
+            _ => Some(Severity::Error),
+        };
+        if let Err(error) = linter.set_level(rule, level) {
+            println!("error: {error}");
+            process::exit(1);
+        }
+    }
+    let mut ok = true;
+    if options.files.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = io::stdin().read_to_string(&mut source) {
+            println!("error: {error}");
+            process::exit(1);
+        }
+        ok = lint_source(&linter, "<stdin>", &source);
+    }
+    for path in &options.files {
+        ok &= match fs::read_to_string(path) {
+            Ok(source) => lint_source(&linter, path, &source),
+            Err(error) => {
+                println!("error: {path}: {error}");
+                false
+            }
+        };
+    }
+    if !ok {
+        process::exit(1);
+    }
+}
+
+// 有语法错误或者error级别的问题时返回false
+fn lint_source(linter: &Linter, path: &str, source: &str) -> bool {
+    let mut parser = Parser::new(source);
+    let program = parser.parse_program();
+    if parser.had_error() {
+        println!("error: {path}: syntax error");
+        return false;
+    }
+    let diagnostics = linter.lint(parser.ast(), &program);
+    for diagnostic in &diagnostics {
+        println!("{path}:{}", diagnostic.render(source));
+    }
+    diagnostics.iter().all(|diagnostic| diagnostic.severity == Severity::Warning)
+}
+
+fn check_begin(options: &Options) {
+    let mut ok = true;
+    if options.files.is_empty() {
+        let mut source = String::new();
+        if let Err(error) = io::stdin().read_to_string(&mut source) {
+            println!("error: {error}");
+            process::exit(1);
+        }
+        ok = check_source("<stdin>", &source);
+    }
+    for path in &options.files {
+        ok &= match fs::read_to_string(path) {
+            Ok(source) => check_source(path, &source),
+            Err(error) => {
+                println!("error: {path}: {error}");
+                false
+            }
+        };
+    }
+    if !ok {
+        process::exit(1);
+    }
+}
+
+// 作用域的错误和类型错误都算
+fn check_source(path: &str, source: &str) -> bool {
+    let mut parser = Parser::new(source);
+    let program = parser.parse_program();
+    if parser.had_error() {
+        println!("error: {path}: syntax error");
+        return false;
+    }
+    let resolve_errors = resolve(parser.ast(), &program).errors;
+    let typing = check(parser.ast(), &program);
+    for (name, scheme) in &typing.declarations {
+        println!("{}: {scheme}", name.lexeme);
+    }
+    for error in &resolve_errors {
+        println!("error: {path}: {error}");
+    }
+    for error in &typing.errors {
+        println!("error: {path}: {error}");
+    }
+    resolve_errors.is_empty() && typing.errors.is_empty()
+}
+
+fn eval_begin() {
+    // 整个REPL共用一个解释器，前面定义的变量后面还能用
+    let mut interpreter = Interpreter::new();
+    let mut in_buf = "".to_string();
+    loop {
+        print!("{PROMPT}");
+        let _ = io::stdout().flush();
+        match io::stdin().read_line(&mut in_buf) {
+            // 输入结束(Ctrl-D)
+            Ok(0) => return,
+            Ok(_) => {
+                let mut parser = Parser::new(&in_buf);
+                let program = parser.parse_program();
+                let errors = resolve(parser.ast(), &program).errors;
+                for error in &errors {
+                    println!("error: {error}");
+                }
+                if !parser.had_error() && errors.is_empty() {
+                    match interpreter.interpret(parser.ast(), &program) {
+                        Ok(Some(value)) => println!("{value}"),
+                        Ok(None) => {}
+                        Err(error) => println!("runtime error: {error}"),
+                    }
+                }
+                in_buf.clear();
+            }
+            Err(error) => println!("error: {error}")
+        }
+    }
+}
 
 fn print_help_msg() {
     print!("{}", HELP_MSG);
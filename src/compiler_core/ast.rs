@@ -0,0 +1,402 @@
+use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::marker::PhantomData;
+use std::ops::Index;
+use std::ops::IndexMut;
+
+use super::expr::Expr;
+use super::stmt::Stmt;
+use super::token::Span;
+use super::token::Token;
+
+// 语法树节点都放在Ast的arena里，节点之间用NodeId互相引用
+// 整棵树只有几个Vec，不需要给每个节点单独分配内存；
+// 节点的id在解析时分配，之后的pass可以用它把作用域、类型等信息放在SideTable里
+
+// 指向Arena<T>中一个节点，T只用来区分不同种类的节点，防止把ExprId当成StmtId用
+pub struct NodeId<T> {
+    index: u32,
+    marker: PhantomData<fn() -> T>,
+}
+
+pub type ExprId = NodeId<Expr>;
+pub type StmtId = NodeId<Stmt>;
+
+impl<T> NodeId<T> {
+    pub fn new(index: usize) -> Self {
+        Self { index: index as u32, marker: PhantomData }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+}
+
+// derive会要求T也实现这些trait，所以手动实现
+impl<T> Clone for NodeId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NodeId<T> {}
+
+impl<T> PartialEq for NodeId<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for NodeId<T> {}
+
+impl<T> Hash for NodeId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for NodeId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
+
+// 节点分块存放，第k块的容量是FIRST_CHUNK << k
+// Vec增长的时候要重新分配并复制所有元素，节点多的时候前后分配的字节数是实际需要的好几倍；
+// 分块之后已经放好的节点不再移动，多分配的空间不超过用到的空间
+const FIRST_CHUNK: usize = 16;
+
+#[derive(Debug, PartialEq, Clone)]
+struct Chunks<T> {
+    chunks: Vec<Vec<T>>,
+    len: usize,
+}
+
+impl<T> Default for Chunks<T> {
+    fn default() -> Self {
+        Self { chunks: vec![], len: 0 }
+    }
+}
+
+impl<T> Chunks<T> {
+    // 下标所在的块和块内的位置
+    fn locate(index: usize) -> (usize, usize) {
+        let chunk = (usize::BITS - 1 - (index / FIRST_CHUNK + 1).leading_zeros()) as usize;
+        (chunk, index - FIRST_CHUNK * ((1 << chunk) - 1))
+    }
+
+    fn push(&mut self, value: T) -> usize {
+        let (chunk, _) = Self::locate(self.len);
+        if chunk == self.chunks.len() {
+            self.chunks.push(Vec::with_capacity(FIRST_CHUNK << chunk));
+        }
+        self.chunks[chunk].push(value);
+        self.len += 1;
+        self.len - 1
+    }
+
+    fn get(&self, index: usize) -> Option<&T> {
+        let (chunk, offset) = Self::locate(index);
+        self.chunks.get(chunk).and_then(|chunk| chunk.get(offset))
+    }
+
+    fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let (chunk, offset) = Self::locate(index);
+        self.chunks.get_mut(chunk).and_then(|chunk| chunk.get_mut(offset))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        self.chunks.iter().flatten()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Arena<T> {
+    nodes: Chunks<T>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self { nodes: Chunks::default() }
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn alloc(&mut self, node: T) -> NodeId<T> {
+        NodeId::new(self.nodes.push(node))
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.len == 0
+    }
+
+    // 按分配的顺序遍历所有节点，子节点总是在父节点之前
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId<T>, &T)> {
+        self.nodes.iter().enumerate().map(|(i, node)| (NodeId::new(i), node))
+    }
+}
+
+impl<T> Index<NodeId<T>> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: NodeId<T>) -> &T {
+        self.nodes.get(id.index()).expect("node id out of range")
+    }
+}
+
+impl<T> IndexMut<NodeId<T>> for Arena<T> {
+    fn index_mut(&mut self, id: NodeId<T>) -> &mut T {
+        self.nodes.get_mut(id.index()).expect("node id out of range")
+    }
+}
+
+// 按节点id保存的附加信息，没有设置过的节点返回None
+#[derive(Debug, PartialEq, Clone)]
+pub struct SideTable<K, V> {
+    values: Chunks<Option<V>>,
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<K, V> Default for SideTable<K, V> {
+    fn default() -> Self {
+        Self { values: Chunks::default(), marker: PhantomData }
+    }
+}
+
+impl<K, V> SideTable<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: NodeId<K>, value: V) {
+        let index = id.index();
+        while index >= self.values.len {
+            self.values.push(None);
+        }
+        *self.values.get_mut(index).unwrap() = Some(value);
+    }
+
+    pub fn get(&self, id: NodeId<K>) -> Option<&V> {
+        self.values.get(id.index()).and_then(Option::as_ref)
+    }
+
+    pub fn get_mut(&mut self, id: NodeId<K>) -> Option<&mut V> {
+        self.values.get_mut(id.index()).and_then(Option::as_mut)
+    }
+
+    pub fn contains(&self, id: NodeId<K>) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId<K>, &V)> {
+        self.values
+            .iter()
+            .enumerate()
+            .filter_map(|(i, value)| value.as_ref().map(|value| (NodeId::new(i), value)))
+    }
+}
+
+// 一次解析得到的所有节点，以及每个节点在源码中的区间
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Ast {
+    pub exprs: Arena<Expr>,
+    pub stmts: Arena<Stmt>,
+    pub expr_spans: SideTable<Expr, Span>,
+    pub stmt_spans: SideTable<Stmt, Span>,
+}
+
+impl Ast {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_expr(&mut self, expr: Expr, span: Span) -> ExprId {
+        let id = self.exprs.alloc(expr);
+        self.expr_spans.insert(id, span);
+        id
+    }
+
+    pub fn add_stmt(&mut self, stmt: Stmt, span: Span) -> StmtId {
+        let id = self.stmts.alloc(stmt);
+        self.stmt_spans.insert(id, span);
+        id
+    }
+
+    // 手工构造的节点没有span，返回空区间
+    pub fn expr_span(&self, id: ExprId) -> Span {
+        self.expr_spans.get(id).copied().unwrap_or_default()
+    }
+
+    pub fn stmt_span(&self, id: StmtId) -> Span {
+        self.stmt_spans.get(id).copied().unwrap_or_default()
+    }
+}
+
+impl Index<ExprId> for Ast {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        &self.exprs[id]
+    }
+}
+
+impl Index<StmtId> for Ast {
+    type Output = Stmt;
+
+    fn index(&self, id: StmtId) -> &Stmt {
+        &self.stmts[id]
+    }
+}
+
+// 任意一个节点的id
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Node {
+    Expr(ExprId),
+    Stmt(StmtId),
+}
+
+// 按顺序访问直接的子节点，节点的实现由define_ast!生成
+// 字段的类型也要实现它：id本身就是子节点，Option和Vec访问里面的每一个，Token这样的叶子什么也不做
+pub trait Walk {
+    fn walk(&self, f: &mut dyn FnMut(Node));
+
+    // 复制一份，子节点换成map返回的id，重建语法树的时候用，见walk.rs的Fold
+    fn map(&self, map: &mut dyn MapNodes) -> Self
+    where
+        Self: Sized;
+}
+
+// 给Walk::map用，把旧树里的子节点换成新树里的节点
+pub trait MapNodes {
+    fn map_expr(&mut self, id: ExprId) -> ExprId;
+    fn map_stmt(&mut self, id: StmtId) -> StmtId;
+
+    // 节点里的token默认原样复制
+    fn map_token(&mut self, token: &Token) -> Token {
+        token.clone()
+    }
+}
+
+impl Walk for ExprId {
+    fn walk(&self, f: &mut dyn FnMut(Node)) {
+        f(Node::Expr(*self))
+    }
+
+    fn map(&self, map: &mut dyn MapNodes) -> Self {
+        map.map_expr(*self)
+    }
+}
+
+impl Walk for StmtId {
+    fn walk(&self, f: &mut dyn FnMut(Node)) {
+        f(Node::Stmt(*self))
+    }
+
+    fn map(&self, map: &mut dyn MapNodes) -> Self {
+        map.map_stmt(*self)
+    }
+}
+
+impl<T: Walk> Walk for Option<T> {
+    fn walk(&self, f: &mut dyn FnMut(Node)) {
+        if let Some(value) = self {
+            value.walk(f);
+        }
+    }
+
+    fn map(&self, map: &mut dyn MapNodes) -> Self {
+        self.as_ref().map(|value| value.map(map))
+    }
+}
+
+impl<T: Walk> Walk for Vec<T> {
+    fn walk(&self, f: &mut dyn FnMut(Node)) {
+        for value in self {
+            value.walk(f);
+        }
+    }
+
+    fn map(&self, map: &mut dyn MapNodes) -> Self {
+        self.iter().map(|value| value.map(map)).collect()
+    }
+}
+
+impl<T: Walk> Walk for Box<T> {
+    fn walk(&self, f: &mut dyn FnMut(Node)) {
+        (**self).walk(f);
+    }
+
+    fn map(&self, map: &mut dyn MapNodes) -> Self {
+        Box::new((**self).map(map))
+    }
+}
+
+impl Walk for Token {
+    fn walk(&self, _f: &mut dyn FnMut(Node)) {}
+
+    fn map(&self, map: &mut dyn MapNodes) -> Self {
+        map.map_token(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::parser::Parser;
+
+    use super::*;
+
+    #[test]
+    fn side_table_test() {
+        let mut arena = Arena::default();
+        let a = arena.alloc("a");
+        let b = arena.alloc("b");
+        assert_eq!((arena[a], arena[b], arena.len()), ("a", "b", 2));
+
+        let mut table = SideTable::new();
+        table.insert(b, 2);
+        assert_eq!(table.get(a), None);
+        assert_eq!(table.get(b), Some(&2));
+        *table.get_mut(b).unwrap() += 1;
+        assert_eq!(table.iter().collect::<Vec<_>>(), vec![(b, &3)]);
+    }
+
+    #[test]
+    fn chunks_test() {
+        assert_eq!(Chunks::<()>::locate(0), (0, 0));
+        assert_eq!(Chunks::<()>::locate(FIRST_CHUNK - 1), (0, FIRST_CHUNK - 1));
+        assert_eq!(Chunks::<()>::locate(FIRST_CHUNK), (1, 0));
+        assert_eq!(Chunks::<()>::locate(3 * FIRST_CHUNK), (2, 0));
+
+        // 跨过好几块之后节点还在原来的位置，按分配的顺序遍历
+        let mut arena = Arena::default();
+        let ids: Vec<_> = (0..1000).map(|i| arena.alloc(i)).collect();
+        assert!(ids.iter().enumerate().all(|(i, &id)| arena[id] == i));
+        assert!(arena.iter().map(|(_, &i)| i).eq(0..1000));
+
+        let mut table = SideTable::new();
+        table.insert(ids[500], "x");
+        assert_eq!(table.get(ids[500]), Some(&"x"));
+        assert_eq!(table.get(ids[999]), None);
+        assert_eq!(table.iter().count(), 1);
+    }
+
+    #[test]
+    fn node_ids_and_spans_test() {
+        let source = "let x = (1 + 2) * y;\nprint x;";
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        let ast = parser.into_ast();
+        // 每个节点都有自己的id和span
+        assert_eq!(ast.exprs.len(), 7);
+        assert_eq!(ast.stmts.len(), 2);
+        let spans: Vec<&str> = ast.exprs.iter().map(|(id, _)| &source[ast.expr_span(id).start..ast.expr_span(id).end]).collect();
+        assert_eq!(spans, ["1", "2", "1 + 2", "(1 + 2)", "y", "(1 + 2) * y", "x"]);
+        let spans: Vec<&str> = program.iter().map(|&id| &source[ast.stmt_span(id).start..ast.stmt_span(id).end]).collect();
+        assert_eq!(spans, ["let x = (1 + 2) * y;", "print x;"]);
+    }
+}
@@ -0,0 +1,208 @@
+// 根据声明生成语法树，每个节点一行：
+//
+//     define_ast! {
+//         Expr(ExprId) visited by Visitor, walked by ExprWalker::visit_expr {
+//             Binary { left: ExprId, op: Token, right: ExprId } => visit_binary,
+//             Function(Box<Function>) => visit_function,
+//         }
+//     }
+//
+// 会生成：
+// - 每个`Name { 字段 }`节点的结构体(derive Debug/PartialEq/Clone)、按字段顺序的`new`和`Walk`实现，
+//   `Name(类型)`表示直接使用已经定义好的类型，比如Expr和Stmt共用的Function；
+//   `Name(Box { 字段 })`和`Name(Box<类型>)`在enum里存Box，比别的变体大很多的节点这样放，
+//   不然arena里每个节点都要按最大的变体占空间
+// - 包含所有节点的enum，以及它的`Walk`实现和返回变体名的`kind`
+// - 在id类型上的`accept`，按节点种类分派到visitor的方法
+// - visitor trait，每个节点一个方法，参数是整个Ast、节点id和节点本身
+// - walker trait，方法和visitor一样但没有返回值，默认访问所有子节点，见walk.rs
+macro_rules! define_ast {
+    (
+        $(#[$enum_meta:meta])*
+        $enum:ident($id:ty) visited by $visitor:ident, walked by $walker:ident::$walk:ident {
+            $(
+                $(#[$meta:meta])*
+                $variant:ident $body:tt => $visit:ident
+            ),* $(,)?
+        }
+    ) => {
+        $(
+            $crate::compiler_core::ast_macro::define_ast!(@struct $(#[$meta])* $variant $body);
+        )*
+
+        $(#[$enum_meta])*
+        #[derive(Debug, PartialEq, Clone)]
+        pub enum $enum {
+            $( $variant($crate::compiler_core::ast_macro::define_ast!(@type $variant $body)), )*
+        }
+
+        impl $enum {
+            // 节点种类的名字，就是变体名
+            pub fn kind(&self) -> &'static str {
+                match self {
+                    $( $enum::$variant(_) => stringify!($variant), )*
+                }
+            }
+        }
+
+        impl $id {
+            // 只有accept是泛型的，返回值类型由传进来的visitor决定
+            pub fn accept<T>(self, ast: &$crate::compiler_core::ast::Ast, visitor: &mut dyn $visitor<T>) -> T {
+                match &ast[self] {
+                    $( $enum::$variant(node) => visitor.$visit(ast, self, node), )*
+                }
+            }
+        }
+
+        // 每个方法都拿到整个Ast(用来访问子节点)和节点的id(用来查SideTable)
+        pub trait $visitor<T> {
+            $(
+                fn $visit(
+                    &mut self,
+                    ast: &$crate::compiler_core::ast::Ast,
+                    id: $id,
+                    node: &$crate::compiler_core::ast_macro::define_ast!(@node $variant $body),
+                ) -> T;
+            )*
+        }
+
+        // 默认的实现访问所有子节点，pass只需要覆盖关心的节点，
+        // 覆盖的方法里调用walk::walk_children继续往下走，不调用就跳过这棵子树
+        // 子节点可能是另一种节点(比如函数体里的语句)，所以要求同时实现Walker
+        pub trait $walker {
+            fn $walk(&mut self, ast: &$crate::compiler_core::ast::Ast, id: $id)
+            where
+                Self: $crate::compiler_core::walk::Walker + Sized,
+            {
+                match &ast[id] {
+                    $( $enum::$variant(node) => self.$visit(ast, id, node), )*
+                }
+            }
+
+            $(
+                fn $visit(
+                    &mut self,
+                    ast: &$crate::compiler_core::ast::Ast,
+                    _id: $id,
+                    node: &$crate::compiler_core::ast_macro::define_ast!(@node $variant $body),
+                ) where
+                    Self: $crate::compiler_core::walk::Walker + Sized,
+                {
+                    $crate::compiler_core::walk::walk_children(self, ast, node)
+                }
+            )*
+        }
+
+        impl $crate::compiler_core::ast::Walk for $enum {
+            fn walk(&self, f: &mut dyn FnMut($crate::compiler_core::ast::Node)) {
+                match self {
+                    $( $enum::$variant(node) => $crate::compiler_core::ast::Walk::walk(node, f), )*
+                }
+            }
+
+            fn map(&self, map: &mut dyn $crate::compiler_core::ast::MapNodes) -> Self {
+                match self {
+                    $( $enum::$variant(node) => $enum::$variant($crate::compiler_core::ast::Walk::map(node, map)), )*
+                }
+            }
+        }
+    };
+
+    (@struct $(#[$meta:meta])* $name:ident { $( $field:ident : $ty:ty ),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, PartialEq, Clone)]
+        pub struct $name {
+            $( pub $field: $ty, )*
+        }
+
+        impl $name {
+            pub fn new($( $field: $ty ),*) -> Self {
+                Self { $( $field ),* }
+            }
+        }
+
+        // 按字段声明的顺序访问子节点
+        impl $crate::compiler_core::ast::Walk for $name {
+            fn walk(&self, _f: &mut dyn FnMut($crate::compiler_core::ast::Node)) {
+                $( $crate::compiler_core::ast::Walk::walk(&self.$field, _f); )*
+            }
+
+            fn map(&self, _map: &mut dyn $crate::compiler_core::ast::MapNodes) -> Self {
+                Self { $( $field: $crate::compiler_core::ast::Walk::map(&self.$field, _map) ),* }
+            }
+        }
+    };
+    (@struct $(#[$meta:meta])* $name:ident ( Box { $( $body:tt )* } )) => {
+        $crate::compiler_core::ast_macro::define_ast!(@struct $(#[$meta])* $name { $( $body )* });
+    };
+    (@struct $(#[$meta:meta])* $name:ident ( $ty:ty )) => {};
+
+    (@type $name:ident { $( $body:tt )* }) => { $name };
+    (@type $name:ident ( Box { $( $body:tt )* } )) => { Box<$name> };
+    (@type $name:ident ( $ty:ty )) => { $ty };
+
+    // visitor拿到的是Box里面的节点
+    (@node $name:ident { $( $body:tt )* }) => { $name };
+    (@node $name:ident ( Box { $( $body:tt )* } )) => { $name };
+    (@node $name:ident ( Box<$ty:ty> )) => { $ty };
+    (@node $name:ident ( $ty:ty )) => { $ty };
+}
+
+pub(crate) use define_ast;
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::ast::Ast;
+    use crate::compiler_core::ast::Node;
+    use crate::compiler_core::ast::Walk;
+    use crate::compiler_core::expr::Binary;
+    use crate::compiler_core::expr::Expr;
+    use crate::compiler_core::expr::Literal;
+    use crate::compiler_core::expr::LiteralValue;
+    use crate::compiler_core::parser::Parser;
+    use crate::compiler_core::stmt::Stmt;
+    use crate::compiler_core::token::Token;
+    use crate::compiler_core::token::TokenType;
+
+    fn kind(ast: &Ast, node: Node) -> &'static str {
+        match node {
+            Node::Expr(id) => ast[id].kind(),
+            Node::Stmt(id) => ast[id].kind(),
+        }
+    }
+
+    #[test]
+    fn generated_struct_test() {
+        let mut ast = Ast::new();
+        let left = ast.exprs.alloc(Expr::Literal(Literal::new(LiteralValue::Null)));
+        let right = ast.exprs.alloc(Expr::Literal(Literal::new(LiteralValue::Null)));
+        // new的参数就是字段的声明顺序
+        let binary = Binary::new(left, Token::new(TokenType::PLUS, "+".to_string(), 1), right);
+        assert_eq!((binary.left, binary.right), (left, right));
+        assert_eq!(binary.clone(), binary);
+        assert!(format!("{:?}", binary).starts_with("Binary { left: #0, op: Token"));
+    }
+
+    #[test]
+    fn generated_walk_test() {
+        let mut parser = Parser::new("fn f(a) { if a { return g(1 + 2, \"${a}\"); } } print -f(null);");
+        let program = parser.parse_program();
+        let ast = parser.into_ast();
+
+        // 子节点按字段声明的顺序访问，Token、字符串片段这些不是节点的字段被跳过
+        let mut kinds = vec![];
+        for (_, expr) in ast.exprs.iter() {
+            if let Expr::Call(call) = expr {
+                call.walk(&mut |node| kinds.push(kind(&ast, node)));
+            }
+        }
+        assert_eq!(kinds, ["Variable", "Binary", "Interpolation", "Variable", "Literal"]);
+
+        let mut kinds = vec![];
+        for &stmt in &program {
+            ast[stmt].walk(&mut |node| kinds.push(kind(&ast, node)));
+        }
+        assert_eq!(kinds, ["If", "Unary"]);
+        assert!(matches!(ast[program[0]], Stmt::Function(_)));
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use super::ast::Ast;
+use super::ast::Node;
+use super::ast::SideTable;
+use super::ast::StmtId;
+use super::expr::Expr;
+use super::parser::Parser;
+use super::stmt::Stmt;
+use super::token::Span;
+use super::token::TokenType;
+use super::trivia::TriviaKind;
+use super::trivia::TriviaLexer;
+
+// 具体语法树：语法树加上解析时丢掉的注释和空行，格式化的时候再把它们输出
+//
+// 注释只挂在语句列表(整个程序、块、函数体)里的语句上：
+// - leading: 语句前面单独成行的注释
+// - trailing: 语句结束的那一行后面的注释 `x = 1; // 说明`
+// - dangling: 列表最后一个语句之后、右括号之前的注释，空块里的注释也是
+// 出现在语句内部的注释(比如表达式中间的`/* */`)没有合适的位置，移到这个语句前面
+//
+// 空行只记录有没有，连续的多个空行格式化之后只保留一个
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    // 注释原文，包括`//`、`/* */`或`///`
+    pub text: String,
+    // 和前面的token或注释之间有空行
+    pub blank_line_before: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct StmtTrivia {
+    pub leading: Vec<Comment>,
+    // 语句(或者它前面的注释)和前面的内容之间有空行
+    pub blank_line_before: bool,
+    pub trailing: Vec<Comment>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cst {
+    pub ast: Ast,
+    pub program: Vec<StmtId>,
+    pub trivia: SideTable<Stmt, StmtTrivia>,
+    // Block、函数声明(Node::Stmt)和匿名函数(Node::Expr)里的dangling注释
+    pub dangling: HashMap<Node, Vec<Comment>>,
+    // 文件末尾的注释
+    pub end: Vec<Comment>,
+}
+
+// 一个语句列表，inside是括号里面的区间
+struct Container {
+    node: Option<Node>,
+    inside: Span,
+    stmts: Vec<StmtId>,
+}
+
+impl Cst {
+    // 有语法错误的时候返回None，错误已经由parser打印出来了
+    pub fn parse(source: &str) -> Option<Cst> {
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        if parser.had_error() {
+            return None;
+        }
+        let mut cst = Cst {
+            ast: parser.into_ast(),
+            program,
+            trivia: SideTable::new(),
+            dangling: HashMap::new(),
+            end: vec![],
+        };
+        let (comments, braces) = scan(source);
+        for stmt in cst.all_list_stmts() {
+            let start = cst.ast.stmt_span(stmt).start;
+            cst.trivia.insert(stmt, StmtTrivia { blank_line_before: blank_line_before(source, start), ..Default::default() });
+        }
+        let containers = cst.containers(source.len(), &braces);
+        for (span, text) in comments {
+            cst.attach(source, &containers, span, text);
+        }
+        Some(cst)
+    }
+
+    fn all_list_stmts(&self) -> Vec<StmtId> {
+        let mut stmts = self.program.clone();
+        for (_, stmt) in self.ast.stmts.iter() {
+            match stmt {
+                Stmt::Block(block) => stmts.extend(&block.stmts),
+                Stmt::Function(function) => stmts.extend(&function.body),
+                _ => {}
+            }
+        }
+        for (_, expr) in self.ast.exprs.iter() {
+            if let Expr::Function(function) = expr {
+                stmts.extend(&function.body);
+            }
+        }
+        stmts
+    }
+
+    fn containers(&self, len: usize, braces: &[usize]) -> Vec<Container> {
+        // 函数体从参数列表后面的第一个左括号开始
+        let body = |span: Span| {
+            let open = braces.iter().find(|&&brace| brace >= span.start).copied().unwrap_or(span.start);
+            Span::new(open + 1, span.end.saturating_sub(1))
+        };
+        let mut containers = vec![Container { node: None, inside: Span::new(0, len), stmts: self.program.clone() }];
+        for (id, stmt) in self.ast.stmts.iter() {
+            let span = self.ast.stmt_span(id);
+            let (inside, stmts) = match stmt {
+                Stmt::Block(block) => (Span::new(span.start + 1, span.end.saturating_sub(1)), &block.stmts),
+                Stmt::Function(function) => (body(span), &function.body),
+                _ => continue,
+            };
+            containers.push(Container { node: Some(Node::Stmt(id)), inside, stmts: stmts.clone() });
+        }
+        for (id, expr) in self.ast.exprs.iter() {
+            if let Expr::Function(function) = expr {
+                let inside = body(self.ast.expr_span(id));
+                containers.push(Container { node: Some(Node::Expr(id)), inside, stmts: function.body.clone() });
+            }
+        }
+        containers
+    }
+
+    fn attach(&mut self, source: &str, containers: &[Container], span: Span, text: String) {
+        let comment = Comment { text, blank_line_before: blank_line_before(source, span.start) };
+        // 包含这个注释的最里面的列表
+        let Some(container) = containers
+            .iter()
+            .filter(|container| container.inside.start <= span.start && span.end <= container.inside.end)
+            .min_by_key(|container| container.inside.len())
+        else {
+            self.end.push(comment);
+            return;
+        };
+        let spans: Vec<Span> = container.stmts.iter().map(|&stmt| self.ast.stmt_span(stmt)).collect();
+        // 语句内部的注释
+        if let Some(i) = spans.iter().position(|stmt| stmt.start <= span.start && span.start < stmt.end) {
+            self.trivia_mut(container.stmts[i]).leading.push(comment);
+            return;
+        }
+        let next = spans.iter().position(|stmt| stmt.start >= span.end);
+        let previous = next.unwrap_or(spans.len()).checked_sub(1);
+        if let Some(previous) = previous {
+            if !source[spans[previous].end..span.start].contains('\n') {
+                self.trivia_mut(container.stmts[previous]).trailing.push(comment);
+                return;
+            }
+        }
+        match (next, container.node) {
+            (Some(next), _) => self.trivia_mut(container.stmts[next]).leading.push(comment),
+            (None, Some(node)) => self.dangling.entry(node).or_default().push(comment),
+            (None, None) => self.end.push(comment),
+        }
+    }
+
+    fn trivia_mut(&mut self, stmt: StmtId) -> &mut StmtTrivia {
+        if !self.trivia.contains(stmt) {
+            self.trivia.insert(stmt, StmtTrivia::default());
+        }
+        self.trivia.get_mut(stmt).unwrap()
+    }
+}
+
+// 源码里所有的注释和左括号的位置
+fn scan(source: &str) -> (Vec<(Span, String)>, Vec<usize>) {
+    let mut comments = vec![];
+    let mut braces = vec![];
+    let mut pos = 0;
+    for token in TriviaLexer::new(source) {
+        for trivia in &token.leading {
+            if matches!(trivia.kind, TriviaKind::LineComment | TriviaKind::BlockComment) {
+                comments.push((Span::new(pos, pos + trivia.text.len()), trivia.text.clone()));
+            }
+            pos += trivia.text.len();
+        }
+        // 文档注释是token，parser把它跳过了，这里当成普通的注释
+        match token.token.token_type {
+            TokenType::DOC_COMMENT => comments.push((token.token.span, token.text.clone())),
+            TokenType::LBRACE => braces.push(token.token.span.start),
+            _ => {}
+        }
+        pos += token.text.len();
+        for trivia in &token.trailing {
+            if matches!(trivia.kind, TriviaKind::LineComment | TriviaKind::BlockComment) {
+                comments.push((Span::new(pos, pos + trivia.text.len()), trivia.text.clone()));
+            }
+            pos += trivia.text.len();
+        }
+    }
+    (comments, braces)
+}
+
+// pos前面的空白里有两个以上的换行符
+fn blank_line_before(source: &str, pos: usize) -> bool {
+    let whitespace = source[..pos].len() - source[..pos].trim_end().len();
+    source[pos - whitespace..pos].matches('\n').count() >= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(comments: &[Comment]) -> Vec<&str> {
+        comments.iter().map(|comment| comment.text.as_str()).collect()
+    }
+
+    #[test]
+    fn attach_test() {
+        let source = "// head\n\nlet x = 1; // x\n/* before y */ let y = /* inner */ 2;\nfn f() {\n    // nothing\n}\n// end";
+        let cst = Cst::parse(source).unwrap();
+        let [x, y, f] = cst.program[..] else { panic!() };
+
+        let x = cst.trivia.get(x).unwrap();
+        assert_eq!(texts(&x.leading), ["// head"]);
+        assert!(x.blank_line_before);
+        assert_eq!(texts(&x.trailing), ["// x"]);
+
+        let y = cst.trivia.get(y).unwrap();
+        assert_eq!(texts(&y.leading), ["/* before y */", "/* inner */"]);
+        assert!(!y.blank_line_before);
+
+        assert_eq!(texts(&cst.dangling[&Node::Stmt(f)]), ["// nothing"]);
+        assert_eq!(texts(&cst.end), ["// end"]);
+    }
+
+    #[test]
+    fn nested_test() {
+        let source = "while x {\n    /// doc\n    print 1; // one\n\n    // two\n    print 2;\n    // last\n}";
+        let cst = Cst::parse(source).unwrap();
+        let Stmt::While(stmt) = &cst.ast[cst.program[0]] else { panic!() };
+        let Stmt::Block(block) = &cst.ast[stmt.body] else { panic!() };
+        let one = cst.trivia.get(block.stmts[0]).unwrap();
+        assert_eq!((texts(&one.leading), texts(&one.trailing)), (vec!["/// doc"], vec!["// one"]));
+        let two = cst.trivia.get(block.stmts[1]).unwrap();
+        assert_eq!(texts(&two.leading), ["// two"]);
+        assert!(two.leading[0].blank_line_before);
+        assert_eq!(texts(&cst.dangling[&Node::Stmt(stmt.body)]), ["// last"]);
+        assert!(cst.end.is_empty());
+    }
+}
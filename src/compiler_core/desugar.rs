@@ -0,0 +1,177 @@
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::StmtId;
+use super::expr::Assign;
+use super::expr::Binary;
+use super::expr::Expr;
+use super::expr::Literal;
+use super::expr::LiteralValue;
+use super::expr::Variable;
+use super::stmt::Block;
+use super::stmt::Expression;
+use super::stmt::Stmt;
+use super::stmt::While;
+use super::token::Token;
+use super::token::TokenType;
+use super::walk::fold_expr;
+use super::walk::fold_program;
+use super::walk::fold_stmt;
+use super::walk::Fold;
+
+// 把parser的语法树降低到更小的核心语法，后端只需要实现核心语法：
+// - `for init; cond; inc { body }` → `{ init; while cond { { body } inc; } }`，省略的条件是true
+// - `x += v` → `x = x + v`，其他复合赋值一样
+// - 去掉Grouping，括号只影响解析，括号里的表达式用带括号的span
+//
+// 用Fold重建到新的Ast里，原来的树不变，格式化这样要还原源码的pass继续用原来的树
+// 新节点都带着原来的span，token也是源码里的(`+=`变成的`+`保留`+=`的位置)，报错还能对应到源码；
+// 合成出来的节点(while、外面的块、省略的条件)用整个for语句的span
+//
+// 解释器和lint在内部先desugar；resolver和typecheck直接处理for和复合赋值，
+// 它们的结果按节点id记录，要和调用者手里的树对应
+
+pub fn desugar(ast: &Ast, stmts: &[StmtId]) -> (Ast, Vec<StmtId>) {
+    let mut desugar = Desugar { output: Ast::new() };
+    let program = fold_program(&mut desugar, ast, stmts);
+    (desugar.output, program)
+}
+
+// 单独降低一个表达式
+pub fn desugar_expr(ast: &Ast, expr: ExprId) -> (Ast, ExprId) {
+    let mut desugar = Desugar { output: Ast::new() };
+    let expr = fold_expr(&mut desugar, ast, expr);
+    (desugar.output, expr)
+}
+
+struct Desugar {
+    output: Ast,
+}
+
+impl Fold for Desugar {
+    fn output(&mut self) -> &mut Ast {
+        &mut self.output
+    }
+
+    fn fold_expr(&mut self, ast: &Ast, id: ExprId) -> ExprId {
+        match &ast[id] {
+            // 去掉括号之后的表达式用带括号的span，和原来的树里同一个位置的节点一样
+            Expr::Grouping(grouping) => {
+                let expr = self.fold_expr(ast, grouping.expr);
+                self.output.expr_spans.insert(expr, ast.expr_span(id));
+                expr
+            }
+            Expr::CompoundAssign(assign) => {
+                let span = ast.expr_span(id);
+                let variable = self.output.add_expr(Expr::Variable(Variable::new(assign.name.clone())), assign.name.span);
+                let value = self.fold_expr(ast, assign.value);
+                let binary = Binary::new(variable, binary_operator(&assign.op), value);
+                let binary = self.output.add_expr(Expr::Binary(binary), span);
+                self.output.add_expr(Expr::Assign(Assign::new(assign.name.clone(), binary)), span)
+            }
+            _ => fold_expr(self, ast, id),
+        }
+    }
+
+    fn fold_stmt(&mut self, ast: &Ast, id: StmtId) -> StmtId {
+        let Stmt::For(stmt) = &ast[id] else {
+            return fold_stmt(self, ast, id);
+        };
+        let span = ast.stmt_span(id);
+        let initializer = stmt.initializer.map(|initializer| self.fold_stmt(ast, initializer));
+        let condition = match stmt.condition {
+            Some(condition) => self.fold_expr(ast, condition),
+            None => self.output.add_expr(Expr::Literal(Literal::new(LiteralValue::Bool(true))), span),
+        };
+        let mut body = self.fold_stmt(ast, stmt.body);
+        // 循环体是单独的一层作用域，里面的变量在inc里看不到
+        if let Some(increment) = stmt.increment {
+            let increment_span = ast.expr_span(increment);
+            let increment = self.fold_expr(ast, increment);
+            let increment = self.output.add_stmt(Stmt::Expression(Expression::new(increment)), increment_span);
+            body = self.output.add_stmt(Stmt::Block(Block::new(vec![body, increment])), ast.stmt_span(stmt.body));
+        }
+        let loop_stmt = self.output.add_stmt(Stmt::While(While::new(condition, body)), span);
+        // init声明的变量只在循环里可见
+        match initializer {
+            Some(initializer) => self.output.add_stmt(Stmt::Block(Block::new(vec![initializer, loop_stmt])), span),
+            None => loop_stmt,
+        }
+    }
+}
+
+// `+=`对应的`+`，位置还是`+=`的，typecheck也用它检查复合赋值
+pub fn binary_operator(op: &Token) -> Token {
+    let token_type = match op.token_type {
+        TokenType::PLUS_ASSIGN => TokenType::PLUS,
+        TokenType::MINUS_ASSIGN => TokenType::MINUS,
+        TokenType::ASTERISK_ASSIGN => TokenType::ASTERISK,
+        _ => TokenType::SLASH,
+    };
+    let lexeme = op.lexeme.trim_end_matches('=').to_string();
+    Token { token_type, lexeme, ..op.clone() }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::interpreter::Interpreter;
+    use crate::compiler_core::parser::Parser;
+    use crate::compiler_core::token::Span;
+    use crate::compiler_core::visitors::AstPrinter;
+
+    use super::*;
+
+    fn desugared(source: &str) -> (Ast, Vec<StmtId>) {
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        assert!(!parser.had_error(), "{}", source);
+        desugar(parser.ast(), &program)
+    }
+
+    #[test]
+    fn lower_test() {
+        let inputs = [
+            ("x += (1 + 2) * 3;", "(= x (+ x (* (+ 1 2) 3)))"),
+            ("a -= b *= 2; c /= 2;", "(= a (- a (= b (* b 2))))\n(= c (/ c 2))"),
+            (
+                "for let i = 0; i < 3; i += 1 { print i; }",
+                "(block (let i 0) (while (< i 3) (block (block (print i)) (= i (+ i 1)))))",
+            ),
+            ("for ;; { break; }", "(while true (block (break)))"),
+            ("for i = 0; ; { i = i + 1; }", "(block (= i 0) (while true (block (= i (+ i 1)))))"),
+            // 函数体里面也会降低
+            ("fn f(n) { for ; n > 0; n -= 1 { } }", "(fn f (n) (while (> n 0) (block (block) (= n (- n 1)))))"),
+        ];
+        for (source, expect) in inputs {
+            let (ast, program) = desugared(source);
+            assert_eq!(AstPrinter::new().print_program(&ast, &program), expect, "{}", source);
+        }
+    }
+
+    #[test]
+    fn span_test() {
+        let source = "for let i = 0; i < 3; i += 1 { }";
+        let (ast, program) = desugared(source);
+        let text = |span: Span| &source[span.start..span.end];
+        assert_eq!(text(ast.stmt_span(program[0])), source);
+        let Stmt::Block(block) = &ast[program[0]] else { panic!() };
+        assert_eq!(text(ast.stmt_span(block.stmts[0])), "let i = 0;");
+        let Stmt::While(stmt) = &ast[block.stmts[1]] else { panic!() };
+        assert_eq!(text(ast.expr_span(stmt.condition)), "i < 3");
+        // `+`的位置是源码里的`+=`
+        let Stmt::Block(body) = &ast[stmt.body] else { panic!() };
+        let Stmt::Expression(increment) = &ast[body.stmts[1]] else { panic!() };
+        let Expr::Assign(assign) = &ast[increment.expr] else { panic!() };
+        let Expr::Binary(binary) = &ast[assign.value] else { panic!() };
+        assert_eq!((binary.op.token_type, binary.op.lexeme.as_str(), text(binary.op.span)), (TokenType::PLUS, "+", "+="));
+        assert_eq!(text(ast.expr_span(increment.expr)), "i += 1");
+    }
+
+    #[test]
+    fn run_test() {
+        let source = "let s = 0; for let i = 1; i <= 4; i += 1 { if i == 4 { break; } s += i; } let i = \"i\"; i += \"!\"; s";
+        let (ast, program) = desugared(source);
+        let mut interpreter = Interpreter::new();
+        let value = interpreter.interpret(&ast, &program).unwrap();
+        assert_eq!(value.map(|value| value.to_string()), Some("6".to_string()));
+    }
+}
@@ -0,0 +1,178 @@
+use super::ast::Ast;
+use super::ast::StmtId;
+use super::token::Token;
+use super::visitors::AstPrinter;
+use super::visitors::DotPrinter;
+use super::visitors::JsonPrinter;
+use super::visitors::TreePrinter;
+
+// token流和语法树的文本格式，给编辑器等外部工具使用
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TokenFormat {
+    Json,
+    Table,
+}
+
+impl TokenFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(TokenFormat::Json),
+            "table" => Some(TokenFormat::Table),
+            _ => None,
+        }
+    }
+
+    pub fn format(&self, tokens: &[Token]) -> String {
+        match self {
+            TokenFormat::Json => tokens_to_json(tokens),
+            TokenFormat::Table => tokens_to_table(tokens),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AstFormat {
+    // AstPrinter的S表达式，每个语句一行
+    SExpr,
+    Json,
+    // 带框线的缩进树，每个节点一行
+    Tree,
+    // Graphviz的digraph
+    Dot,
+}
+
+impl AstFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sexpr" => Some(AstFormat::SExpr),
+            "json" => Some(AstFormat::Json),
+            "tree" => Some(AstFormat::Tree),
+            "dot" => Some(AstFormat::Dot),
+            _ => None,
+        }
+    }
+
+    pub fn format(&self, ast: &Ast, stmts: &[StmtId]) -> String {
+        match self {
+            AstFormat::SExpr => format!("{}\n", AstPrinter::new().print_program(ast, stmts)),
+            AstFormat::Json => format!("{}\n", JsonPrinter::new().print_program(ast, stmts).pretty()),
+            AstFormat::Tree => format!("{}\n", TreePrinter::new().print_program(ast, stmts)),
+            AstFormat::Dot => format!("{}\n", DotPrinter::new().print_program(ast, stmts)),
+        }
+    }
+}
+
+// 每个token一行的JSON数组
+// {"type": "LET", "lexeme": "let", "line": 1, "column": 1, "span": {"start": 0, "end": 3}}
+pub fn tokens_to_json(tokens: &[Token]) -> String {
+    let mut result = "[".to_string();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 {
+            result.push(',');
+        }
+        result.push_str(&format!(
+            "\n  {{\"type\": {}, \"lexeme\": {}, \"line\": {}, \"column\": {}, \"span\": {{\"start\": {}, \"end\": {}}}}}",
+            json_string(&token.token_type.to_string()),
+            json_string(&token.lexeme),
+            token.line,
+            token.column,
+            token.span.start,
+            token.span.end,
+        ));
+    }
+    if !tokens.is_empty() {
+        result.push('\n');
+    }
+    result.push_str("]\n");
+    result
+}
+
+// 对齐的表格，lexeme用引号括起来，方便看清空白字符
+pub fn tokens_to_table(tokens: &[Token]) -> String {
+    let mut result = format!("{:<16} {:<24} {:>5} {:>6}  {}\n", "TYPE", "LEXEME", "LINE", "COLUMN", "SPAN");
+    for token in tokens {
+        result.push_str(&format!(
+            "{:<16} {:<24} {:>5} {:>6}  {}..{}\n",
+            token.token_type.to_string(),
+            format!("{:?}", token.lexeme),
+            token.line,
+            token.column,
+            token.span.start,
+            token.span.end,
+        ));
+    }
+    result
+}
+
+// 转义成JSON字符串，包括两边的引号
+pub fn json_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::json::read_program;
+    use crate::compiler_core::lexer::Lexer;
+    use crate::compiler_core::parser::Parser;
+
+    use super::*;
+
+    #[test]
+    fn json_test() {
+        let tokens: Vec<Token> = Lexer::new("let s =\n r#\"say \"hi\"\\\t\"#").collect();
+        assert_eq!(
+            tokens_to_json(&tokens),
+            r#"[
+  {"type": "LET", "lexeme": "let", "line": 1, "column": 1, "span": {"start": 0, "end": 3}},
+  {"type": "IDENT", "lexeme": "s", "line": 1, "column": 5, "span": {"start": 4, "end": 5}},
+  {"type": "ASSIGN", "lexeme": "=", "line": 1, "column": 7, "span": {"start": 6, "end": 7}},
+  {"type": "STRING", "lexeme": "say \"hi\"\\\t", "line": 2, "column": 2, "span": {"start": 9, "end": 24}},
+  {"type": "EOF", "lexeme": "", "line": 2, "column": 17, "span": {"start": 24, "end": 24}}
+]
+"#
+        );
+        assert_eq!(tokens_to_json(&[]), "[]\n");
+    }
+
+    #[test]
+    fn table_test() {
+        let tokens: Vec<Token> = Lexer::new("x\t+ 1").collect();
+        assert_eq!(
+            tokens_to_table(&tokens),
+            "TYPE             LEXEME                    LINE COLUMN  SPAN
+IDENT            \"x\"                          1      1  0..1
+PLUS             \"+\"                          1      3  2..3
+NUMBER           \"1\"                          1      5  4..5
+EOF              \"\"                           1      6  5..5
+"
+        );
+    }
+
+    #[test]
+    fn ast_format_test() {
+        assert_eq!(AstFormat::from_name("xml"), None);
+        let mut parser = Parser::new("let x = 1;\nprint x;");
+        let program = parser.parse_program();
+        assert_eq!(AstFormat::SExpr.format(parser.ast(), &program), "(let x 1)\n(print x)\n");
+        let json = AstFormat::Json.format(parser.ast(), &program);
+        let (ast, read) = read_program(&json).unwrap();
+        assert_eq!(AstFormat::SExpr.format(&ast, &read), "(let x 1)\n(print x)\n");
+        assert_eq!(AstFormat::from_name("tree"), Some(AstFormat::Tree));
+        assert!(AstFormat::Dot.format(parser.ast(), &program).ends_with("n0 -> n3;\n}\n"));
+    }
+}
@@ -1,90 +1,124 @@
-use std::any::Any;
-use std::rc::Rc;
+use super::ast::ExprId;
+use super::ast::MapNodes;
+use super::ast::Node;
+use super::ast::StmtId;
+use super::ast::Walk;
+use std::fmt;
 
+use super::ast_macro::define_ast;
 use super::token::Token;
 
-pub trait Expr<T> {
-    fn accept(&self, visitor: &mut dyn Visitor<T>) -> T;
-}
-
-pub struct Binary<R> {
-    pub left: Box<dyn Expr<R>>,
-    pub op: Token,
-    pub right: Box<dyn Expr<R>>,
+// 语法树和遍历它的visitor无关，同一棵树可以交给打印、求值、类型检查等多个pass
+// 子节点都是Ast里的id，见ast.rs；结构体、构造函数、Visitor和ExprWalker由define_ast!生成，见ast_macro.rs
+define_ast! {
+    Expr(ExprId) visited by Visitor, walked by ExprWalker::visit_expr {
+        Binary { left: ExprId, op: Token, right: ExprId } => visit_binary,
+        Grouping { expr: ExprId } => visit_grouping,
+        Literal { value: LiteralValue } => visit_literal,
+        Unary { op: Token, right: ExprId } => visit_unary,
+        // 插值字符串 "hello ${name}"
+        Interpolation { parts: Vec<TemplatePart> } => visit_interpolation,
+        Variable { name: Token } => visit_variable,
+        Assign { name: Token, value: ExprId } => visit_assign,
+        // `x += 1`，op是复合赋值的运算符，desugar之后变成`x = x + 1`
+        CompoundAssign { name: Token, op: Token, value: ExprId } => visit_compound_assign,
+        // and/or，和Binary分开是因为右边的表达式不一定求值
+        Logical { left: ExprId, op: Token, right: ExprId } => visit_logical,
+        // paren是右括号，运行时出错的时候用来报告位置
+        Call { callee: ExprId, paren: Token, arguments: Vec<ExprId> } => visit_call,
+        // 函数声明 `fn add(a, b) { ... }` 和匿名函数 `fn(a, b) { ... }` 共用
+        // param_annotations和params一一对应，`fn add(a: number, b): number`
+        // 比别的节点大很多，放在Box里
+        Function(Box {
+            name: Option<Token>,
+            params: Vec<Token>,
+            param_annotations: Vec<Option<TypeAnnotation>>,
+            return_annotation: Option<TypeAnnotation>,
+            body: Vec<StmtId>,
+        }) => visit_function,
+        // 列表 `[1, 2, 3]`，bracket是左括号
+        List { bracket: Token, elements: Vec<ExprId> } => visit_list,
+        // 下标 `xs[0]`，bracket是左括号，运行时出错的时候用来报告位置
+        Index { object: ExprId, bracket: Token, index: ExprId } => visit_index,
+    }
 }
 
-pub struct Grouping<R> {
-    pub expr: Box<dyn Expr<R>>
+#[derive(Debug, PartialEq, Clone)]
+pub enum LiteralValue {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Null,
 }
 
-pub struct Literal {
-    pub value: Box<dyn Any>
+#[derive(Debug, PartialEq, Clone)]
+pub enum TemplatePart {
+    // 字符串片段
+    Chunk(String),
+    // `${}`里的表达式
+    Expr(ExprId),
 }
 
-pub struct Unary<R> {
-    pub op: Token,
-    pub right: Box<dyn Expr<R>>
+// 类型标注，运行时忽略，只有类型检查使用
+#[derive(Debug, PartialEq, Clone)]
+pub enum TypeAnnotation {
+    // number、string、bool、null，其他的名字是类型变量
+    Name(Token),
+    // list<number>、map<string, number>
+    Generic(Token, Vec<TypeAnnotation>),
+    // fn(number, string): bool
+    Function(Vec<TypeAnnotation>, Box<TypeAnnotation>),
 }
 
-impl<R> Binary<R> {
-    pub fn new(left: Box<dyn Expr<R>>, op: Token, right: Box<dyn Expr<R>>) -> Self {
-        Self {
-            left,
-            op,
-            right
+// 源码里的写法
+impl fmt::Display for TypeAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeAnnotation::Name(name) => write!(f, "{}", name.lexeme),
+            TypeAnnotation::Generic(name, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+                write!(f, "{}<{}>", name.lexeme, arguments.join(", "))
+            }
+            TypeAnnotation::Function(params, ret) => {
+                let params: Vec<String> = params.iter().map(|param| param.to_string()).collect();
+                write!(f, "fn({}): {}", params.join(", "), ret)
+            }
         }
     }
 }
 
-impl<R> Grouping<R> {
-    pub fn new(expr: Box<dyn Expr<R>>) -> Self {
-        Self {
-            expr
-        }
+impl Walk for LiteralValue {
+    fn walk(&self, _f: &mut dyn FnMut(Node)) {}
+
+    fn map(&self, _map: &mut dyn MapNodes) -> Self {
+        self.clone()
     }
 }
 
-impl Literal {
-    pub fn new(value: Box<dyn Any>) -> Self {
-        Self {
-            value
+// 没有子节点，只复制token
+impl Walk for TypeAnnotation {
+    fn walk(&self, _f: &mut dyn FnMut(Node)) {}
+
+    fn map(&self, map: &mut dyn MapNodes) -> Self {
+        match self {
+            TypeAnnotation::Name(name) => TypeAnnotation::Name(name.map(map)),
+            TypeAnnotation::Generic(name, arguments) => TypeAnnotation::Generic(name.map(map), arguments.map(map)),
+            TypeAnnotation::Function(params, ret) => TypeAnnotation::Function(params.map(map), ret.map(map)),
         }
     }
 }
 
-impl<R> Unary<R> {
-    pub fn new(op: Token, right: Box<dyn Expr<R>>) -> Self {
-        Self {
-            op,
-            right
+impl Walk for TemplatePart {
+    fn walk(&self, f: &mut dyn FnMut(Node)) {
+        if let TemplatePart::Expr(expr) = self {
+            expr.walk(f);
         }
     }
-}
 
-impl<R> Expr<R> for Binary<R> {
-    fn accept(&self, visitor: &mut dyn Visitor<R>) -> R {
-        visitor.visit_binary(self)
-    }
-}
-impl<R> Expr<R> for Grouping<R> {
-    fn accept(&self, visitor: &mut dyn Visitor<R>) -> R {
-        visitor.visit_grouping(self)
-    }
-}
-impl<R> Expr<R> for Literal {
-    fn accept(&self, visitor: &mut dyn Visitor<R>) -> R {
-        visitor.visit_literal(self)
-    }
-}
-impl<R> Expr<R> for Unary<R> {
-    fn accept(&self, visitor: &mut dyn Visitor<R>) -> R {
-        visitor.visit_unary(self)
+    fn map(&self, map: &mut dyn MapNodes) -> Self {
+        match self {
+            TemplatePart::Chunk(chunk) => TemplatePart::Chunk(chunk.clone()),
+            TemplatePart::Expr(expr) => TemplatePart::Expr(expr.map(map)),
+        }
     }
 }
-
-pub trait Visitor<T> {
-    fn visit_binary(&mut self, t: &Binary<T>) -> T;    
-    fn visit_grouping(&mut self, t: &Grouping<T>) -> T;    
-    fn visit_literal(&mut self, t: &Literal) -> T;    
-    fn visit_unary(&mut self, t: &Unary<T>) -> T;    
-}
\ No newline at end of file
@@ -0,0 +1,565 @@
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::Node;
+use super::ast::StmtId;
+use super::cst::Comment;
+use super::cst::Cst;
+use super::expr::Assign;
+use super::expr::Binary;
+use super::expr::Call;
+use super::expr::CompoundAssign;
+use super::expr::Expr;
+use super::expr::Function;
+use super::expr::Grouping;
+use super::expr::Index;
+use super::expr::Interpolation;
+use super::expr::List;
+use super::expr::Literal;
+use super::expr::LiteralValue;
+use super::expr::Logical;
+use super::expr::TemplatePart;
+use super::expr::TypeAnnotation;
+use super::expr::Unary;
+use super::expr::Variable;
+use super::expr::Visitor;
+use super::stmt::Block;
+use super::stmt::Break;
+use super::stmt::Expression;
+use super::stmt::For;
+use super::stmt::If;
+use super::stmt::Let;
+use super::stmt::Print;
+use super::stmt::Return;
+use super::stmt::Stmt;
+use super::stmt::StmtVisitor;
+use super::stmt::While;
+use super::token::TokenType;
+
+// 把语法树打印回源码，格式是固定的：
+// - 缩进四个空格，每个语句一行，块的左括号不换行
+// - 二元运算符两边有空格，逗号后面有空格
+// - 源码里的括号不保留，按照优先级只在需要的地方加括号
+// - 顶层的函数声明前后空一行，其他地方保留源码里的空行(最多一个)
+// 用with_comments创建的时候按照Cst输出注释，见cst.rs
+
+const INDENT: &str = "    ";
+
+// 和parser里从低到高的层次一一对应
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Precedence {
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Addition,
+    Multiplication,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Precedence {
+    // 左结合的运算符，右边的操作数要比自己高一级
+    fn next(self) -> Precedence {
+        match self {
+            Precedence::Assignment => Precedence::Or,
+            Precedence::Or => Precedence::And,
+            Precedence::And => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::Addition,
+            Precedence::Addition => Precedence::Multiplication,
+            Precedence::Multiplication => Precedence::Unary,
+            Precedence::Unary => Precedence::Call,
+            Precedence::Call | Precedence::Primary => Precedence::Primary,
+        }
+    }
+
+    fn of_operator(token_type: TokenType) -> Precedence {
+        match token_type {
+            TokenType::OR => Precedence::Or,
+            TokenType::AND => Precedence::And,
+            TokenType::EQ | TokenType::NOT_EQ => Precedence::Equality,
+            TokenType::GT | TokenType::GE | TokenType::LT | TokenType::LE => Precedence::Comparison,
+            TokenType::PLUS | TokenType::MINUS => Precedence::Addition,
+            _ => Precedence::Multiplication,
+        }
+    }
+
+    // 括号去掉之后表达式的优先级
+    fn of(ast: &Ast, id: ExprId) -> Precedence {
+        match &ast[id] {
+            Expr::Assign(_) | Expr::CompoundAssign(_) => Precedence::Assignment,
+            Expr::Logical(expr) => Precedence::of_operator(expr.op.token_type),
+            Expr::Binary(expr) => Precedence::of_operator(expr.op.token_type),
+            Expr::Unary(_) => Precedence::Unary,
+            Expr::Call(_) | Expr::Index(_) => Precedence::Call,
+            Expr::Grouping(expr) => Precedence::of(ast, expr.expr),
+            // 负数常量(比如常量折叠的结果)打印出来是一元运算
+            Expr::Literal(Literal { value: LiteralValue::Number(value) }) if value.is_sign_negative() => Precedence::Unary,
+            _ => Precedence::Primary,
+        }
+    }
+}
+
+// 格式化源码，保留注释，有语法错误的时候返回None，错误已经由parser打印出来了
+pub fn format_source(source: &str) -> Option<String> {
+    let cst = Cst::parse(source)?;
+    Some(SourcePrinter::with_comments(&cst).print_program(&cst.ast, &cst.program))
+}
+
+#[derive(Default)]
+pub struct SourcePrinter<'a> {
+    // 当前语句的缩进层数
+    indent: usize,
+    cst: Option<&'a Cst>,
+}
+
+impl<'a> SourcePrinter<'a> {
+    pub fn new() -> SourcePrinter<'a> {
+        SourcePrinter { indent: 0, cst: None }
+    }
+
+    // 输出cst里的注释和空行，打印的树必须是cst.ast
+    pub fn with_comments(cst: &'a Cst) -> SourcePrinter<'a> {
+        SourcePrinter { indent: 0, cst: Some(cst) }
+    }
+
+    pub fn print_expr(&mut self, ast: &Ast, expr: ExprId) -> String {
+        expr.accept(ast, self)
+    }
+
+    // 每个语句一行，最后有换行符
+    pub fn print_program(&mut self, ast: &Ast, stmts: &[StmtId]) -> String {
+        let end = self.cst.map_or(&[][..], |cst| &cst.end[..]);
+        let lines = self.statements(ast, stmts, end, true);
+        if lines.is_empty() {
+            return String::new();
+        }
+        format!("{}\n", lines.join("\n"))
+    }
+
+    // 语句列表，每一项前面加上当前的缩进，空行是空字符串
+    fn statements(&mut self, ast: &Ast, stmts: &[StmtId], dangling: &[Comment], top_level: bool) -> Vec<String> {
+        let padding = INDENT.repeat(self.indent);
+        let mut lines: Vec<String> = vec![];
+        for (i, &stmt) in stmts.iter().enumerate() {
+            let trivia = self.cst.and_then(|cst| cst.trivia.get(stmt));
+            let is_function = matches!(ast[stmt], Stmt::Function(_));
+            let after_function = i > 0 && matches!(ast[stmts[i - 1]], Stmt::Function(_));
+            if top_level && (is_function || after_function) {
+                blank_line(&mut lines);
+            }
+            if let Some(trivia) = trivia {
+                for comment in &trivia.leading {
+                    if comment.blank_line_before {
+                        blank_line(&mut lines);
+                    }
+                    lines.push(format!("{}{}", padding, comment.text));
+                }
+                if trivia.blank_line_before {
+                    blank_line(&mut lines);
+                }
+            }
+            let mut line = format!("{}{}", padding, stmt.accept(ast, self));
+            for comment in trivia.map_or(&[][..], |trivia| &trivia.trailing[..]) {
+                line.push(' ');
+                line.push_str(&comment.text);
+            }
+            lines.push(line);
+        }
+        for comment in dangling {
+            if comment.blank_line_before {
+                blank_line(&mut lines);
+            }
+            lines.push(format!("{}{}", padding, comment.text));
+        }
+        lines
+    }
+
+    // 优先级比min低的时候加上括号
+    fn expr(&mut self, ast: &Ast, expr: ExprId, min: Precedence) -> String {
+        let text = expr.accept(ast, self);
+        if Precedence::of(ast, expr) < min {
+            format!("({})", text)
+        } else {
+            text
+        }
+    }
+
+    fn binary(&mut self, ast: &Ast, left: ExprId, op: &str, right: ExprId, precedence: Precedence) -> String {
+        let left = self.expr(ast, left, precedence);
+        let right = self.expr(ast, right, precedence.next());
+        format!("{} {} {}", left, op, right)
+    }
+
+    // `{`到`}`，里面的语句多缩进一层，右括号和当前语句对齐
+    // node是块或者函数，用来找右括号前面的注释
+    fn block(&mut self, ast: &Ast, node: Option<Node>, stmts: &[StmtId]) -> String {
+        let dangling = match (self.cst, node) {
+            (Some(cst), Some(node)) => cst.dangling.get(&node).map_or(&[][..], |comments| &comments[..]),
+            _ => &[],
+        };
+        if stmts.is_empty() && dangling.is_empty() {
+            return "{}".to_string();
+        }
+        self.indent += 1;
+        let lines = self.statements(ast, stmts, dangling, false);
+        self.indent -= 1;
+        format!("{{\n{}\n{}}}", lines.join("\n"), INDENT.repeat(self.indent))
+    }
+
+    // if和while的分支必须是块，手工构造的树可能不是
+    fn body(&mut self, ast: &Ast, stmt: StmtId) -> String {
+        match &ast[stmt] {
+            Stmt::Block(block) => self.block(ast, Some(Node::Stmt(stmt)), &block.stmts),
+            _ => self.block(ast, None, &[stmt]),
+        }
+    }
+
+    fn function(&mut self, ast: &Ast, node: Node, function: &Function) -> String {
+        let params: Vec<String> = function
+            .params
+            .iter()
+            .zip(&function.param_annotations)
+            .map(|(param, annotation)| format!("{}{}", param.lexeme, annotation_text(annotation)))
+            .collect();
+        let name = function.name.as_ref().map_or("", |name| name.lexeme.as_str());
+        let head = if name.is_empty() { "fn".to_string() } else { format!("fn {}", name) };
+        let ret = annotation_text(&function.return_annotation);
+        format!("{}({}){} {}", head, params.join(", "), ret, self.block(ast, Some(node), &function.body))
+    }
+}
+
+// `: number`，没有标注就是空字符串
+fn annotation_text(annotation: &Option<TypeAnnotation>) -> String {
+    annotation.as_ref().map_or(String::new(), |annotation| format!(": {}", annotation))
+}
+
+// 连续的空行只保留一个，列表开头不要空行
+fn blank_line(lines: &mut Vec<String>) {
+    if lines.last().is_some_and(|line| !line.is_empty()) {
+        lines.push(String::new());
+    }
+}
+
+// 内容里有双引号、`${`或者`$$`的时候用原始字符串，#的个数比内容里最长的`"#...`多一个
+fn string_literal(value: &str) -> String {
+    if !value.contains('"') && !value.contains("${") && !value.contains("$$") {
+        return format!("\"{}\"", value);
+    }
+    let mut hashes = 0;
+    for (i, _) in value.match_indices('"') {
+        hashes = hashes.max(value[i + 1..].chars().take_while(|&c| c == '#').count() + 1);
+    }
+    let hashes = "#".repeat(hashes);
+    format!("r{}\"{}\"{}", hashes, value, hashes)
+}
+
+// 插值字符串里的片段，会被当成`$$`或者`${`开头的`$`写成`$$`
+fn template_chunk(chunk: &str, before_expr: bool) -> String {
+    let mut result = String::new();
+    let mut chars = chunk.chars().peekable();
+    while let Some(c) = chars.next() {
+        result.push(c);
+        let escape = match chars.peek() {
+            Some(next) => *next == '$' || *next == '{',
+            None => before_expr,
+        };
+        if c == '$' && escape {
+            result.push('$');
+        }
+    }
+    result
+}
+
+impl Visitor<String> for SourcePrinter<'_> {
+    fn visit_binary(&mut self, ast: &Ast, _id: ExprId, expr: &Binary) -> String {
+        let precedence = Precedence::of_operator(expr.op.token_type);
+        self.binary(ast, expr.left, &expr.op.lexeme, expr.right, precedence)
+    }
+
+    // 括号由外面的表达式按照优先级决定
+    fn visit_grouping(&mut self, ast: &Ast, _id: ExprId, expr: &Grouping) -> String {
+        expr.expr.accept(ast, self)
+    }
+
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, expr: &Literal) -> String {
+        match &expr.value {
+            LiteralValue::String(value) => string_literal(value),
+            LiteralValue::Number(value) => value.to_string(),
+            LiteralValue::Bool(value) => value.to_string(),
+            LiteralValue::Null => "null".to_string(),
+        }
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, _id: ExprId, expr: &Unary) -> String {
+        format!("{}{}", expr.op.lexeme, self.expr(ast, expr.right, Precedence::Unary))
+    }
+
+    fn visit_interpolation(&mut self, ast: &Ast, _id: ExprId, expr: &Interpolation) -> String {
+        let mut result = "\"".to_string();
+        for (i, part) in expr.parts.iter().enumerate() {
+            match part {
+                TemplatePart::Chunk(chunk) => result.push_str(&template_chunk(chunk, i + 1 < expr.parts.len())),
+                TemplatePart::Expr(expr) => {
+                    let text = self.expr(ast, *expr, Precedence::Assignment);
+                    result.push_str(&format!("${{{}}}", text));
+                }
+            }
+        }
+        result.push('"');
+        result
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, _id: ExprId, expr: &Variable) -> String {
+        expr.name.lexeme.clone()
+    }
+
+    fn visit_assign(&mut self, ast: &Ast, _id: ExprId, expr: &Assign) -> String {
+        // 右结合，右边可以是另一个赋值
+        format!("{} = {}", expr.name.lexeme, self.expr(ast, expr.value, Precedence::Assignment))
+    }
+
+    fn visit_compound_assign(&mut self, ast: &Ast, _id: ExprId, expr: &CompoundAssign) -> String {
+        format!("{} {} {}", expr.name.lexeme, expr.op.lexeme, self.expr(ast, expr.value, Precedence::Assignment))
+    }
+
+    fn visit_logical(&mut self, ast: &Ast, _id: ExprId, expr: &Logical) -> String {
+        let precedence = Precedence::of_operator(expr.op.token_type);
+        self.binary(ast, expr.left, &expr.op.lexeme, expr.right, precedence)
+    }
+
+    fn visit_call(&mut self, ast: &Ast, _id: ExprId, expr: &Call) -> String {
+        let callee = self.expr(ast, expr.callee, Precedence::Call);
+        let arguments: Vec<String> =
+            expr.arguments.iter().map(|argument| self.expr(ast, *argument, Precedence::Assignment)).collect();
+        format!("{}({})", callee, arguments.join(", "))
+    }
+
+    fn visit_function(&mut self, ast: &Ast, id: ExprId, expr: &Function) -> String {
+        self.function(ast, Node::Expr(id), expr)
+    }
+
+    fn visit_list(&mut self, ast: &Ast, _id: ExprId, expr: &List) -> String {
+        let elements: Vec<String> =
+            expr.elements.iter().map(|element| self.expr(ast, *element, Precedence::Assignment)).collect();
+        format!("[{}]", elements.join(", "))
+    }
+
+    fn visit_index(&mut self, ast: &Ast, _id: ExprId, expr: &Index) -> String {
+        let object = self.expr(ast, expr.object, Precedence::Call);
+        format!("{}[{}]", object, self.expr(ast, expr.index, Precedence::Assignment))
+    }
+}
+
+impl StmtVisitor<String> for SourcePrinter<'_> {
+    fn visit_expression_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Expression) -> String {
+        let text = self.expr(ast, stmt.expr, Precedence::Assignment);
+        // 以fn开头的语句会被当成函数声明，匿名函数要放在括号里
+        if text.starts_with("fn(") {
+            format!("({});", text)
+        } else {
+            format!("{};", text)
+        }
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Print) -> String {
+        format!("print {};", self.expr(ast, stmt.expr, Precedence::Assignment))
+    }
+
+    fn visit_let_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Let) -> String {
+        let annotation = annotation_text(&stmt.annotation);
+        match stmt.initializer {
+            Some(initializer) => {
+                format!("let {}{} = {};", stmt.name.lexeme, annotation, self.expr(ast, initializer, Precedence::Assignment))
+            }
+            None => format!("let {}{};", stmt.name.lexeme, annotation),
+        }
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Block) -> String {
+        self.block(ast, Some(Node::Stmt(id)), &stmt.stmts)
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &If) -> String {
+        let condition = self.expr(ast, stmt.condition, Precedence::Assignment);
+        let mut result = format!("if {} {}", condition, self.body(ast, stmt.then_branch));
+        if let Some(else_branch) = stmt.else_branch {
+            // else if写在同一行
+            let else_branch = match &ast[else_branch] {
+                Stmt::If(_) => else_branch.accept(ast, self),
+                _ => self.body(ast, else_branch),
+            };
+            result.push_str(&format!(" else {}", else_branch));
+        }
+        result
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &While) -> String {
+        let condition = self.expr(ast, stmt.condition, Precedence::Assignment);
+        format!("while {} {}", condition, self.body(ast, stmt.body))
+    }
+
+    // `for let i = 0; i < n; i += 1 {`，省略的部分只留下分号: `for ;; {`
+    fn visit_for_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &For) -> String {
+        let mut result = "for ".to_string();
+        match stmt.initializer {
+            // 初始化语句自己带着分号
+            Some(initializer) => result.push_str(&initializer.accept(ast, self)),
+            None => result.push(';'),
+        }
+        match stmt.condition {
+            Some(condition) => result.push_str(&format!(" {};", self.expr(ast, condition, Precedence::Assignment))),
+            None => result.push(';'),
+        }
+        if let Some(increment) = stmt.increment {
+            result.push(' ');
+            result.push_str(&self.expr(ast, increment, Precedence::Assignment));
+        }
+        format!("{} {}", result, self.body(ast, stmt.body))
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Function) -> String {
+        self.function(ast, Node::Stmt(id), stmt)
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Return) -> String {
+        match stmt.value {
+            Some(value) => format!("return {};", self.expr(ast, value, Precedence::Assignment)),
+            None => "return;".to_string(),
+        }
+    }
+
+    fn visit_break_stmt(&mut self, _ast: &Ast, _id: StmtId, _stmt: &Break) -> String {
+        "break;".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::parser::Parser;
+    use crate::compiler_core::parser::tests::CORPUS;
+    use crate::compiler_core::sexpr::read_program;
+    use crate::compiler_core::walk::fold_expr;
+    use crate::compiler_core::walk::fold_program;
+    use crate::compiler_core::walk::same_program;
+    use crate::compiler_core::walk::Fold;
+
+    use super::*;
+
+    // 格式化会去掉多余的括号，比较语义的时候两边的括号都去掉
+    struct RemoveGrouping {
+        output: Ast,
+    }
+
+    impl Fold for RemoveGrouping {
+        fn output(&mut self) -> &mut Ast {
+            &mut self.output
+        }
+
+        fn fold_expr(&mut self, ast: &Ast, id: ExprId) -> ExprId {
+            match &ast[id] {
+                Expr::Grouping(grouping) => self.fold_expr(ast, grouping.expr),
+                _ => fold_expr(self, ast, id),
+            }
+        }
+    }
+
+    fn without_grouping(source: &str) -> (Ast, Vec<StmtId>) {
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        assert!(!parser.had_error(), "{}", source);
+        let mut folder = RemoveGrouping { output: Ast::new() };
+        let program = fold_program(&mut folder, parser.ast(), &program);
+        (folder.output, program)
+    }
+
+    #[test]
+    fn format_test() {
+        let inputs = [
+            ("let   x=1;print x+y*2 ;", "let x = 1;\nprint x + y * 2;\n"),
+            ("print ((1 + 1));(1 + 3) * 2;", "print 1 + 1;\n(1 + 3) * 2;\n"),
+            ("print 1 - (2 - 3); print (1 - 2) - 3; print -(-x); print -(a + b);", "print 1 - (2 - 3);\nprint 1 - 2 - 3;\nprint --x;\nprint -(a + b);\n"),
+            ("a = b = (c or d) and e;", "a = b = (c or d) and e;\n"),
+            ("print (f)(1, (2))(g(3));", "print f(1, 2)(g(3));\n"),
+            ("print [ 1,(2) ,[]][0] ;print (-x)[0];", "print [1, 2, []][0];\nprint (-x)[0];\n"),
+            (
+                "fn add(a,b){return a+b;} print add(1,2);",
+                "fn add(a, b) {\n    return a + b;\n}\n\nprint add(1, 2);\n",
+            ),
+            (
+                "if x>1{print 1;}else if x{while true{break;}}else{}",
+                "if x > 1 {\n    print 1;\n} else if x {\n    while true {\n        break;\n    }\n} else {}\n",
+            ),
+            (
+                "let f = fn(a) { return fn() { return a; }; };",
+                "let f = fn(a) {\n    return fn() {\n        return a;\n    };\n};\n",
+            ),
+            ("(fn() { print 1; })();", "(fn() {\n    print 1;\n}());\n"),
+            ("print \"a ${x + 1} b ${\"c\"}\";", "print \"a ${x + 1} b ${\"c\"}\";\n"),
+            ("print r#\"say \"hi\"\"#; print r\"${x}\";", "print r#\"say \"hi\"\"#;\nprint r\"${x}\";\n"),
+            ("print \"$$${x}$$ $${\"; print \"$$$$\";", "print \"$$${x}$ $${\";\nprint r\"$$\";\n"),
+            (
+                "for let i=0;i<3;i+=1{x*=(2+1);} for;;{} for i=0;;{}",
+                "for let i = 0; i < 3; i += 1 {\n    x *= 2 + 1;\n}\nfor ;; {}\nfor i = 0;; {}\n",
+            ),
+        ];
+        for (input, expect) in inputs {
+            assert_eq!(format_source(input).unwrap(), expect, "{}", input);
+        }
+        assert_eq!(format_source("let = 1;"), None);
+    }
+
+    #[test]
+    fn idempotency_test() {
+        let inputs = CORPUS.iter().copied().chain([
+            "fn fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); } print fib(10);",
+            "let i = 0; while i < 10 and !(i == 5) { i = i + 1; { let j = -(i * (2 + 3)); } }",
+            "print (a or b) and (c or d); print !(a and b); print (1 + 2) / (3 - 4) * 5;",
+            "let x = \"${\"${1 + 2}\"}\"; print fn(a, b) { return; };",
+        ]);
+        for input in inputs {
+            let formatted = format_source(input).unwrap();
+            assert_eq!(format_source(&formatted).unwrap(), formatted, "{}", input);
+            // 只改变了格式和多余的括号
+            let (before, before_program) = without_grouping(input);
+            let (after, after_program) = without_grouping(&formatted);
+            assert!(same_program(&before, &before_program, &after, &after_program), "{}", formatted);
+        }
+    }
+
+    #[test]
+    fn comment_test() {
+        let inputs = [
+            // leading和trailing
+            ("// head\nlet x=1;   // x\n/* y */ let y=2;", "// head\nlet x = 1; // x\n/* y */\nlet y = 2;\n"),
+            // 空行最多保留一个，文件末尾的注释
+            ("print 1;\n\n\n\nprint 2;\n\n// end\n", "print 1;\n\nprint 2;\n\n// end\n"),
+            // dangling: 空块和最后一个语句后面的注释
+            ("if x {\n// nothing\n} else { print 1;\n  // last\n}", "if x {\n    // nothing\n} else {\n    print 1;\n    // last\n}\n"),
+            (
+                "fn f() { /// doc\n  return 1; // one\n} // after\nlet g = fn() { /* empty */ };",
+                "fn f() {\n    /// doc\n    return 1; // one\n} // after\n\nlet g = fn() {\n    /* empty */\n};\n",
+            ),
+            // 语句中间的注释移到语句前面
+            ("let z = 1 + // one\n    2;", "// one\nlet z = 1 + 2;\n"),
+            ("// only", "// only\n"),
+        ];
+        for (input, expect) in inputs {
+            let formatted = format_source(input).unwrap();
+            assert_eq!(formatted, expect, "{}", input);
+            assert_eq!(format_source(&formatted).unwrap(), formatted, "{}", input);
+        }
+    }
+
+    #[test]
+    fn hand_built_tree_test() {
+        // if的分支不是块、字符串里有引号
+        let (ast, program) = read_program("(if x (print \"say \\\"hi\\\"\") (print (* (+ 1 2) (- 3))))").unwrap();
+        assert_eq!(
+            SourcePrinter::new().print_program(&ast, &program),
+            "if x {\n    print r#\"say \"hi\"\"#;\n} else {\n    print (1 + 2) * -3;\n}\n"
+        );
+    }
+}
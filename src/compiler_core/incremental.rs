@@ -0,0 +1,209 @@
+use super::lexer::Lexer;
+use super::lexer::LexerState;
+use super::token::Span;
+use super::token::Token;
+use super::token::TokenType;
+
+// 增量扫描：编辑器每次修改之后，只重新扫描受影响的部分
+
+// 一次文本修改，把旧源码中range的部分替换成replacement
+#[derive(Debug, PartialEq, Clone)]
+pub struct TextEdit {
+    pub range: Span,
+    pub replacement: String,
+}
+
+impl TextEdit {
+    pub fn new(range: Span, replacement: &str) -> Self {
+        Self { range, replacement: replacement.to_string() }
+    }
+
+    // 对旧源码应用这次修改
+    pub fn apply(&self, source: &str) -> String {
+        let mut result = String::with_capacity(source.len() + self.replacement.len());
+        result.push_str(&source[..self.range.start]);
+        result.push_str(&self.replacement);
+        result.push_str(&source[self.range.end..]);
+        result
+    }
+}
+
+// 根据旧的token和一次修改，得到新源码的token，结果和完整扫描new_source完全一样
+// old_tokens必须是完整扫描旧源码的结果(以EOF结尾)，new_source是修改之后的源码
+//
+// 从修改所在行之前的一个token开始重新扫描：lexer的向前查看不会越过换行符，
+// 所以这个token之前的部分不受影响。扫描到修改之后的部分时，
+// 如果新token的开始位置刚好是某个旧token的开始位置，并且两边都不在插值字符串里，
+// lexer的状态就完全一样了，后面的token直接复用旧的，只需要平移位置。
+pub fn relex(old_tokens: &[Token], new_source: &str, edit: &TextEdit) -> Vec<Token> {
+    // 不在插值字符串里的token，从它开始扫描时lexer的templates是空的
+    let mut stable = Vec::with_capacity(old_tokens.len());
+    let mut depth = 0;
+    for token in old_tokens {
+        stable.push(depth == 0);
+        match token.token_type {
+            TokenType::TEMPLATE_HEAD => depth += 1,
+            TokenType::TEMPLATE_TAIL => depth -= 1,
+            _ => {}
+        }
+    }
+
+    // 修改所在行的开始位置，这之前的源码在新旧两边都一样
+    let line_start = new_source[..edit.range.start].rfind('\n').map_or(0, |i| i + 1);
+    let restart = (0..old_tokens.len())
+        .rev()
+        .find(|&i| stable[i] && old_tokens[i].span.end < line_start);
+    let (mut result, state) = match restart {
+        Some(i) => {
+            let token = &old_tokens[i];
+            let state = LexerState {
+                offset: token.span.start,
+                line: token.line,
+                column: token.column,
+                templates: vec![],
+            };
+            (old_tokens[..i].to_vec(), state)
+        }
+        None => (vec![], LexerState::default()),
+    };
+
+    let removed = edit.range.end - edit.range.start;
+    let inserted = edit.replacement.len();
+    let edit_end = edit.range.start + inserted;
+    let mut lexer = Lexer::with_state(&new_source[state.offset..], state);
+    loop {
+        let in_template = !lexer.state().templates.is_empty();
+        let token = match lexer.next() {
+            Some(token) => token,
+            None => return result,
+        };
+        if token.span.start >= edit_end && !in_template {
+            // 新token在旧源码中的位置
+            let old_start = token.span.start + removed - inserted;
+            if let Ok(j) = old_tokens.binary_search_by_key(&old_start, |t| t.span.start) {
+                if stable[j] {
+                    result.extend(shift(&old_tokens[j..], &token));
+                    return result;
+                }
+            }
+        }
+        result.push(token);
+    }
+}
+
+// 把旧token平移到新的位置，first是重新扫描得到的、和tokens[0]对应的新token
+fn shift<'t>(tokens: &'t [Token], first: &Token) -> impl Iterator<Item = Token> + 't {
+    let anchor = &tokens[0];
+    let offset = first.span.start as isize - anchor.span.start as isize;
+    let lines = first.line - anchor.line;
+    let columns = first.column - anchor.column;
+    let anchor_line = anchor.line;
+    tokens.iter().map(move |token| {
+        let mut token = token.clone();
+        // 和第一个token在同一行的token，列号也要平移
+        if token.line == anchor_line {
+            token.column += columns;
+        }
+        token.line += lines;
+        token.span = Span::new(
+            (token.span.start as isize + offset) as usize,
+            (token.span.end as isize + offset) as usize,
+        );
+        token
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_relex(source: &str, edit: &TextEdit) {
+        let old_tokens: Vec<Token> = Lexer::new(source).collect();
+        let new_source = edit.apply(source);
+        let expects: Vec<Token> = Lexer::new(&new_source).collect();
+        assert_eq!(
+            relex(&old_tokens, &new_source, edit),
+            expects,
+            "source {:?}, edit {:?}",
+            source,
+            edit
+        );
+    }
+
+    #[test]
+    fn simple_edit_test() {
+        let source = "let five = 5;\nlet ten = 10;\nprint five + ten;\n";
+        // 把five改成fifty
+        assert_relex(source, &TextEdit::new(Span::new(4, 8), "fifty"));
+        // 在第二行插入新的一行
+        assert_relex(source, &TextEdit::new(Span::new(14, 14), "let x = \"a\nb\";\n"));
+        // 打开一个块注释，后面的token都变成注释
+        assert_relex(source, &TextEdit::new(Span::new(14, 14), "/*"));
+        // 删除所有内容
+        assert_relex(source, &TextEdit::new(Span::new(0, source.len()), ""));
+        // 标识符接上插入的字符
+        assert_relex(source, &TextEdit::new(Span::new(8, 8), "ty"));
+        // 把r##变成原始字符串的开头
+        assert_relex("r## x\ny \"# z", &TextEdit::new(Span::new(3, 3), "\""));
+    }
+
+    #[test]
+    fn reuses_unchanged_tokens_test() {
+        let source = "let a = 1;\nlet b = 2;\nlet c = 3;\n";
+        let old_tokens: Vec<Token> = Lexer::new(source).collect();
+        let edit = TextEdit::new(Span::new(19, 20), "20");
+        let new_source = edit.apply(source);
+        let tokens = relex(&old_tokens, &new_source, &edit);
+        let expects: Vec<Token> = Lexer::new(&new_source).collect();
+        assert_eq!(tokens, expects);
+        assert_eq!(tokens[8].lexeme, "20");
+        assert_eq!(tokens[13].span, Span::new(31, 32));
+    }
+
+    // 简单的xorshift随机数，测试不需要依赖外部crate
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            self.next() % n
+        }
+    }
+
+    #[test]
+    fn randomized_edits_test() {
+        let sources = [
+            "let five = 5;\nlet ten = 10;\n\nlet add = fn(x, y) {\n    x + y; // sum\n};\n",
+            "/// doc\nprint \"a ${b} c ${ {1: \"in ${x}\"} }\" /* c /* d */ */ >= 3.14;\n",
+            "r##\"raw \"# string\"## \"\"\"\n    multi\n      line\n    \"\"\" let 名字 = \"中文\";\n",
+        ];
+        let fragments = [
+            "", "x", "let", " ", "\n", "\"", "${", "}", "{", "/*", "*/", "//", "///",
+            "r#\"", "\"#", "\"\"\"", "1.5", "==", "名", "\r\n", "fn(a) { a; }",
+        ];
+        let mut random = Random(0x2545F4914F6CDD1D);
+        for source in sources {
+            let boundaries: Vec<usize> = source
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([source.len()])
+                .collect();
+            for _ in 0..300 {
+                let a = boundaries[random.below(boundaries.len())];
+                let b = boundaries[random.below(boundaries.len())];
+                let mut replacement = String::new();
+                for _ in 0..random.below(3) {
+                    replacement.push_str(fragments[random.below(fragments.len())]);
+                }
+                let edit = TextEdit::new(Span::new(a.min(b), a.max(b)), &replacement);
+                assert_relex(source, &edit);
+            }
+        }
+    }
+}
@@ -0,0 +1,954 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::zip;
+use std::rc::Rc;
+use std::rc::Weak;
+
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::SideTable;
+use super::ast::StmtId;
+use super::desugar::binary_operator;
+use super::desugar::desugar;
+use super::desugar::desugar_expr;
+use super::expr::Assign;
+use super::expr::Binary;
+use super::expr::Call;
+use super::expr::CompoundAssign;
+use super::expr::Expr;
+use super::expr::Function;
+use super::expr::Grouping;
+use super::expr::Index;
+use super::expr::Interpolation;
+use super::expr::List;
+use super::expr::Literal;
+use super::expr::LiteralValue;
+use super::expr::Logical;
+use super::expr::TemplatePart;
+use super::expr::Unary;
+use super::expr::Variable;
+use super::expr::Visitor;
+use super::resolver::resolve;
+use super::resolver::resolve_expr;
+use super::resolver::Binding;
+use super::stmt::Block;
+use super::stmt::Break;
+use super::stmt::Expression;
+use super::stmt::For;
+use super::stmt::If;
+use super::stmt::Let;
+use super::stmt::Print;
+use super::stmt::Return;
+use super::stmt::Stmt;
+use super::stmt::StmtVisitor;
+use super::stmt::While;
+use super::token::Token;
+use super::token::TokenType;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Null,
+    Function(Rc<Closure>),
+    // 列表创建之后不能修改，复制的时候共用
+    List(Rc<Vec<Value>>),
+}
+
+impl Value {
+    // null和false为假，其他都为真
+    fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Null | Value::Bool(false))
+    }
+}
+
+// 值转换成字符串，插值字符串和print都用这个
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(number) => write!(f, "{}", number),
+            Value::String(string) => write!(f, "{}", string),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Null => write!(f, "null"),
+            Value::Function(closure) => write!(f, "{:?}", closure),
+            Value::List(elements) => {
+                let elements: Vec<String> = elements.iter().map(|element| element.to_string()).collect();
+                write!(f, "[{}]", elements.join(", "))
+            }
+        }
+    }
+}
+
+// 函数和定义它时所在的环境，以及函数体所在的Ast和它的变量绑定
+pub struct Closure {
+    pub function: Function,
+    ast: Rc<Ast>,
+    bindings: Rc<SideTable<Expr, Binding>>,
+    env: Rc<RefCell<Environment>>,
+}
+
+// 环境里可能有这个闭包自己(递归函数)，不能打印环境
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function.name {
+            Some(name) => write!(f, "<fn {}>", name.lexeme),
+            None => write!(f, "<fn>"),
+        }
+    }
+}
+
+// 同一次求值得到的闭包才相等
+impl PartialEq for Closure {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.ast, &other.ast) && self.function == other.function && Rc::ptr_eq(&self.env, &other.env)
+    }
+}
+
+// 一层作用域，找不到的变量到外层去找
+#[derive(Default)]
+pub struct Environment {
+    values: HashMap<String, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self { values: HashMap::new(), enclosing: Some(enclosing) }
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref().and_then(|enclosing| enclosing.borrow().get(name)),
+        }
+    }
+
+    // 只在往外数depth层的环境里找，depth是resolver算出来的
+    pub fn get_at(&self, depth: usize, name: &str) -> Option<Value> {
+        if depth == 0 {
+            return self.values.get(name).cloned();
+        }
+        self.enclosing.as_ref().and_then(|enclosing| enclosing.borrow().get_at(depth - 1, name))
+    }
+
+    // 变量不存在时返回false
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        if let Some(slot) = self.values.get_mut(name) {
+            *slot = value;
+            return true;
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+
+    pub fn assign_at(&mut self, depth: usize, name: &str, value: Value) -> bool {
+        if depth == 0 {
+            return match self.values.get_mut(name) {
+                Some(slot) => {
+                    *slot = value;
+                    true
+                }
+                None => false,
+            };
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign_at(depth - 1, name, value),
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RuntimeError {
+    // 出错的运算符
+    pub token: Token,
+    pub message: String,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: at token \"{}\", {}", self.token.line, self.token.lexeme, self.message)
+    }
+}
+
+pub type EvalResult = Result<Value, RuntimeError>;
+
+// 语句执行完之后接下来怎么走
+#[derive(Debug, PartialEq, Clone)]
+pub enum Flow {
+    Normal,
+    Return(Value),
+    Break,
+}
+
+pub type ExecResult = Result<Flow, RuntimeError>;
+
+pub struct Interpreter {
+    environment: Rc<RefCell<Environment>>,
+    // 最外层的环境，绑定是Global的变量在这里找
+    globals: Rc<RefCell<Environment>>,
+    // 正在执行的Ast和resolver给它算出的变量绑定，创建闭包的时候要带上它们
+    ast: Rc<Ast>,
+    bindings: Rc<SideTable<Expr, Binding>>,
+    heap: Heap,
+    // 正在执行的函数调用有几层
+    depth: usize,
+}
+
+// 函数调用最多嵌套这么多层，再深就报错，不能让解释器自己的栈溢出
+const MAX_CALL_DEPTH: usize = 1000;
+
+// 嵌套MAX_CALL_DEPTH层调用需要的栈，debug构建里每层要用好几KB，比主线程默认的8MB多
+// 调用者要在有这么大栈的线程里执行解释器
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+// 至少有这么多环境和闭包的时候才回收
+const MIN_COLLECT_THRESHOLD: usize = 1024;
+
+// 创建过的环境和闭包，用来回收引用计数释放不了的环
+// 闭包引用定义它的环境，环境里又保存着闭包(比如函数声明)，这样的环只靠Rc永远不会释放
+#[derive(Default)]
+struct Heap {
+    environments: Vec<Weak<RefCell<Environment>>>,
+    closures: Vec<Weak<Closure>>,
+    // 数量超过这个值时回收，回收之后是活着的个数的两倍
+    threshold: usize,
+}
+
+impl Heap {
+    fn environment(&mut self, environment: Environment) -> Rc<RefCell<Environment>> {
+        let environment = Rc::new(RefCell::new(environment));
+        self.environments.push(Rc::downgrade(&environment));
+        environment
+    }
+
+    fn closure(&mut self, closure: Closure) -> Rc<Closure> {
+        let closure = Rc::new(closure);
+        self.closures.push(Rc::downgrade(&closure));
+        closure
+    }
+
+    fn should_collect(&self) -> bool {
+        self.environments.len() + self.closures.len() >= self.threshold.max(MIN_COLLECT_THRESHOLD)
+    }
+
+    // 试探删除：先数出每个环境和闭包被其他环境、闭包引用了几次，
+    // 引用计数比这个多的说明还有外面的引用(解释器的当前环境、Rust栈上的临时值、列表)，是根；
+    // 从根出发标记，没被标记到的环境只被环里的引用保持着，清空它们就释放了整个环。
+    // 不知道的引用都当成根，所以执行到一半的时候回收也是安全的，代价是经过列表的环不会被回收
+    fn collect(&mut self) {
+        let environments: Vec<Rc<RefCell<Environment>>> = self.environments.iter().filter_map(Weak::upgrade).collect();
+        let closures: Vec<Rc<Closure>> = self.closures.iter().filter_map(Weak::upgrade).collect();
+        let environment_index: HashMap<*const RefCell<Environment>, usize> =
+            environments.iter().enumerate().map(|(i, environment)| (Rc::as_ptr(environment), i)).collect();
+        let closure_index: HashMap<*const Closure, usize> =
+            closures.iter().enumerate().map(|(i, closure)| (Rc::as_ptr(closure), i)).collect();
+
+        let mut environment_refs = vec![0; environments.len()];
+        let mut closure_refs = vec![0; closures.len()];
+        for environment in &environments {
+            let environment = environment.borrow();
+            for value in environment.values.values() {
+                if let Value::Function(closure) = value {
+                    if let Some(&i) = closure_index.get(&Rc::as_ptr(closure)) {
+                        closure_refs[i] += 1;
+                    }
+                }
+            }
+            if let Some(&i) = environment.enclosing.as_ref().and_then(|enclosing| environment_index.get(&Rc::as_ptr(enclosing))) {
+                environment_refs[i] += 1;
+            }
+        }
+        for closure in &closures {
+            if let Some(&i) = environment_index.get(&Rc::as_ptr(&closure.env)) {
+                environment_refs[i] += 1;
+            }
+        }
+
+        // 减去上面upgrade得到的那一个引用
+        let mut environment_marks: Vec<bool> =
+            environments.iter().zip(&environment_refs).map(|(environment, &refs)| Rc::strong_count(environment) - 1 > refs).collect();
+        let mut closure_marks: Vec<bool> =
+            closures.iter().zip(&closure_refs).map(|(closure, &refs)| Rc::strong_count(closure) - 1 > refs).collect();
+        let mut pending: Vec<usize> = (0..environments.len()).filter(|&i| environment_marks[i]).collect();
+        let mark = |environment: &Rc<RefCell<Environment>>, marks: &mut Vec<bool>, pending: &mut Vec<usize>| {
+            if let Some(&i) = environment_index.get(&Rc::as_ptr(environment)) {
+                if !marks[i] {
+                    marks[i] = true;
+                    pending.push(i);
+                }
+            }
+        };
+        for (closure, _) in closures.iter().zip(&closure_marks).filter(|(_, &marked)| marked) {
+            mark(&closure.env, &mut environment_marks, &mut pending);
+        }
+        while let Some(i) = pending.pop() {
+            let environment = environments[i].borrow();
+            for value in environment.values.values() {
+                let Value::Function(closure) = value else {
+                    continue;
+                };
+                if let Some(&j) = closure_index.get(&Rc::as_ptr(closure)) {
+                    if !closure_marks[j] {
+                        closure_marks[j] = true;
+                        mark(&closure.env, &mut environment_marks, &mut pending);
+                    }
+                }
+            }
+            if let Some(enclosing) = &environment.enclosing {
+                mark(enclosing, &mut environment_marks, &mut pending);
+            }
+        }
+
+        for (environment, _) in environments.iter().zip(&environment_marks).filter(|(_, &marked)| !marked) {
+            // 先拿出来再释放，释放的时候不能借用着环境
+            let (values, enclosing) = {
+                let mut environment = environment.borrow_mut();
+                (std::mem::take(&mut environment.values), environment.enclosing.take())
+            };
+            drop(values);
+            drop(enclosing);
+        }
+        self.environments = zip(&environments, &environment_marks).filter(|(_, &marked)| marked).map(|(environment, _)| Rc::downgrade(environment)).collect();
+        self.closures = zip(&closures, &closure_marks).filter(|(_, &marked)| marked).map(|(closure, _)| Rc::downgrade(closure)).collect();
+        self.threshold = (self.environments.len() + self.closures.len()) * 2;
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        let mut heap = Heap::default();
+        let globals = heap.environment(Environment::default());
+        Interpreter {
+            environment: globals.clone(),
+            globals,
+            ast: Rc::new(Ast::new()),
+            bindings: Rc::new(SideTable::new()),
+            heap,
+            depth: 0,
+        }
+    }
+
+    // 执行整个程序，最后一个语句是表达式语句时返回它的值，REPL用来显示结果
+    // 同一个Interpreter多次调用时，前面定义的变量仍然有效
+    // 每次调用可以是不同的Ast，先desugar成核心语法再执行，闭包会记住降低之后的Ast
+    // 变量按resolver的绑定查找，resolver的错误由调用者在执行之前报告
+    pub fn interpret(&mut self, ast: &Ast, stmts: &[StmtId]) -> Result<Option<Value>, RuntimeError> {
+        let (ast, stmts) = desugar(ast, stmts);
+        self.bindings = Rc::new(resolve(&ast, &stmts).bindings);
+        let ast = Rc::new(ast);
+        self.ast = ast.clone();
+        let mut last = None;
+        for stmt in stmts {
+            last = None;
+            if let Stmt::Expression(stmt) = &ast[stmt] {
+                last = Some(stmt.expr.accept(&ast, self)?);
+            } else if stmt.accept(&ast, self)? != Flow::Normal {
+                // 顶层的return和break直接结束程序
+                break;
+            }
+        }
+        Ok(last)
+    }
+
+    // 单独求值一个表达式
+    pub fn evaluate(&mut self, ast: &Ast, expr: ExprId) -> EvalResult {
+        let (ast, expr) = desugar_expr(ast, expr);
+        self.bindings = Rc::new(resolve_expr(&ast, expr).bindings);
+        let ast = Rc::new(ast);
+        self.ast = ast.clone();
+        expr.accept(&ast, self)
+    }
+
+    fn error(token: &Token, message: &str) -> RuntimeError {
+        RuntimeError { token: token.clone(), message: message.to_string() }
+    }
+
+    fn number_operands(op: &Token, left: Value, right: Value) -> Result<(f64, f64), RuntimeError> {
+        match (left, right) {
+            (Value::Number(left), Value::Number(right)) => Ok((left, right)),
+            _ => Err(Self::error(op, "Operands must be numbers.")),
+        }
+    }
+
+    // Binary和复合赋值共用，两边的值都已经求好了
+    fn binary(op: &Token, left: Value, right: Value) -> EvalResult {
+        match op.token_type {
+            TokenType::PLUS => match (left, right) {
+                (Value::Number(left), Value::Number(right)) => Ok(Value::Number(left + right)),
+                (Value::String(left), Value::String(right)) => Ok(Value::String(left + &right)),
+                _ => Err(Self::error(op, "Operands must be two numbers or two strings.")),
+            },
+            TokenType::MINUS => {
+                let (left, right) = Self::number_operands(op, left, right)?;
+                Ok(Value::Number(left - right))
+            }
+            TokenType::ASTERISK => {
+                let (left, right) = Self::number_operands(op, left, right)?;
+                Ok(Value::Number(left * right))
+            }
+            TokenType::SLASH => {
+                let (left, right) = Self::number_operands(op, left, right)?;
+                Ok(Value::Number(left / right))
+            }
+            TokenType::GT => {
+                let (left, right) = Self::number_operands(op, left, right)?;
+                Ok(Value::Bool(left > right))
+            }
+            TokenType::GE => {
+                let (left, right) = Self::number_operands(op, left, right)?;
+                Ok(Value::Bool(left >= right))
+            }
+            TokenType::LT => {
+                let (left, right) = Self::number_operands(op, left, right)?;
+                Ok(Value::Bool(left < right))
+            }
+            TokenType::LE => {
+                let (left, right) = Self::number_operands(op, left, right)?;
+                Ok(Value::Bool(left <= right))
+            }
+            TokenType::EQ => Ok(Value::Bool(left == right)),
+            TokenType::NOT_EQ => Ok(Value::Bool(left != right)),
+            _ => Err(Self::error(op, "Unknown binary operator.")),
+        }
+    }
+
+    // 按resolver的绑定找变量，局部变量往外数层数，其他的在全局环境里按名字找
+    fn lookup(&self, id: ExprId, name: &Token) -> EvalResult {
+        let value = match self.bindings.get(id) {
+            Some(Binding::Local { depth, .. }) => self.environment.borrow().get_at(*depth, &name.lexeme),
+            _ => self.globals.borrow().get(&name.lexeme),
+        };
+        value.ok_or_else(|| Self::error(name, "Undefined variable."))
+    }
+
+    fn assign(&mut self, id: ExprId, name: &Token, value: Value) -> Result<(), RuntimeError> {
+        let assigned = match self.bindings.get(id) {
+            Some(Binding::Local { depth, .. }) => self.environment.borrow_mut().assign_at(*depth, &name.lexeme, value),
+            _ => self.globals.borrow_mut().assign(&name.lexeme, value),
+        };
+        if assigned {
+            Ok(())
+        } else {
+            Err(Self::error(name, "Undefined variable."))
+        }
+    }
+
+    // 在新的环境里执行一串语句，出错的时候也要恢复原来的环境
+    fn execute_block(&mut self, ast: &Ast, stmts: &[StmtId], environment: Environment) -> ExecResult {
+        if self.heap.should_collect() {
+            self.heap.collect();
+        }
+        let environment = self.heap.environment(environment);
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let mut result = Ok(Flow::Normal);
+        for stmt in stmts {
+            result = stmt.accept(ast, self);
+            if result != Ok(Flow::Normal) {
+                break;
+            }
+        }
+        self.environment = previous;
+        result
+    }
+
+    fn execute_for(&mut self, ast: &Ast, stmt: &For) -> ExecResult {
+        if let Some(initializer) = stmt.initializer {
+            initializer.accept(ast, self)?;
+        }
+        loop {
+            if let Some(condition) = stmt.condition {
+                if !condition.accept(ast, self)?.is_truthy() {
+                    break;
+                }
+            }
+            match stmt.body.accept(ast, self)? {
+                Flow::Normal => {}
+                Flow::Break => break,
+                flow => return Ok(flow),
+            }
+            if let Some(increment) = stmt.increment {
+                increment.accept(ast, self)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn closure(&mut self, function: &Function) -> Value {
+        Value::Function(self.heap.closure(Closure {
+            function: function.clone(),
+            ast: self.ast.clone(),
+            bindings: self.bindings.clone(),
+            env: self.environment.clone(),
+        }))
+    }
+}
+
+// 全局环境里的函数和全局环境互相引用，解释器不用了之后也要回收
+impl Drop for Interpreter {
+    fn drop(&mut self) {
+        self.environment = Rc::new(RefCell::new(Environment::default()));
+        self.globals = self.environment.clone();
+        self.heap.collect();
+    }
+}
+
+impl Visitor<EvalResult> for Interpreter {
+
+    fn visit_binary(&mut self, ast: &Ast, _id: ExprId, expr: &Binary) -> EvalResult {
+        let left = expr.left.accept(ast, self)?;
+        let right = expr.right.accept(ast, self)?;
+        Self::binary(&expr.op, left, right)
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, _id: ExprId, expr: &Grouping) -> EvalResult {
+        expr.expr.accept(ast, self)
+    }
+
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, expr: &Literal) -> EvalResult {
+        match &expr.value {
+            LiteralValue::String(value) => Ok(Value::String(value.clone())),
+            LiteralValue::Number(value) => Ok(Value::Number(*value)),
+            LiteralValue::Bool(value) => Ok(Value::Bool(*value)),
+            LiteralValue::Null => Ok(Value::Null),
+        }
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, _id: ExprId, expr: &Unary) -> EvalResult {
+        let right = expr.right.accept(ast, self)?;
+        match expr.op.token_type {
+            TokenType::MINUS => match right {
+                Value::Number(number) => Ok(Value::Number(-number)),
+                _ => Err(Self::error(&expr.op, "Operand must be a number.")),
+            },
+            TokenType::EXCLAMATION => Ok(Value::Bool(!right.is_truthy())),
+            _ => Err(Self::error(&expr.op, "Unknown unary operator.")),
+        }
+    }
+
+    fn visit_interpolation(&mut self, ast: &Ast, _id: ExprId, expr: &Interpolation) -> EvalResult {
+        // 每一部分都转换成字符串再拼起来
+        let mut result = String::new();
+        for part in &expr.parts {
+            match part {
+                TemplatePart::Chunk(chunk) => result.push_str(chunk),
+                TemplatePart::Expr(expr) => result.push_str(&expr.accept(ast, self)?.to_string()),
+            }
+        }
+        Ok(Value::String(result))
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, id: ExprId, expr: &Variable) -> EvalResult {
+        self.lookup(id, &expr.name)
+    }
+
+    fn visit_assign(&mut self, ast: &Ast, id: ExprId, expr: &Assign) -> EvalResult {
+        let value = expr.value.accept(ast, self)?;
+        self.assign(id, &expr.name, value.clone())?;
+        Ok(value)
+    }
+
+    // interpret和evaluate会先desugar，直接执行没有desugar的树也和desugar之后一样：
+    // 先读变量再求右边的值，出错的时候报告去掉`=`的运算符
+    fn visit_compound_assign(&mut self, ast: &Ast, id: ExprId, expr: &CompoundAssign) -> EvalResult {
+        let left = self.lookup(id, &expr.name)?;
+        let right = expr.value.accept(ast, self)?;
+        let value = Self::binary(&binary_operator(&expr.op), left, right)?;
+        self.assign(id, &expr.name, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_logical(&mut self, ast: &Ast, _id: ExprId, expr: &Logical) -> EvalResult {
+        // 短路求值，返回决定结果的那个操作数
+        let left = expr.left.accept(ast, self)?;
+        let short_circuit = match expr.op.token_type {
+            TokenType::OR => left.is_truthy(),
+            _ => !left.is_truthy(),
+        };
+        if short_circuit {
+            Ok(left)
+        } else {
+            expr.right.accept(ast, self)
+        }
+    }
+
+    fn visit_call(&mut self, ast: &Ast, _id: ExprId, expr: &Call) -> EvalResult {
+        let callee = expr.callee.accept(ast, self)?;
+        let mut arguments = Vec::with_capacity(expr.arguments.len());
+        for argument in &expr.arguments {
+            arguments.push(argument.accept(ast, self)?);
+        }
+        let closure = match callee {
+            Value::Function(closure) => closure,
+            _ => return Err(Self::error(&expr.paren, "Can only call functions.")),
+        };
+        let params = &closure.function.params;
+        if params.len() != arguments.len() {
+            let message = format!("Expected {} arguments but got {}.", params.len(), arguments.len());
+            return Err(Self::error(&expr.paren, &message));
+        }
+        let mut environment = Environment::new(closure.env.clone());
+        for (param, argument) in params.iter().zip(arguments) {
+            environment.define(&param.lexeme, argument);
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(Self::error(&expr.paren, "Stack overflow."));
+        }
+        // 函数体在定义它的Ast里执行
+        let previous = std::mem::replace(&mut self.ast, closure.ast.clone());
+        let previous_bindings = std::mem::replace(&mut self.bindings, closure.bindings.clone());
+        self.depth += 1;
+        let result = self.execute_block(&closure.ast, &closure.function.body, environment);
+        self.depth -= 1;
+        self.ast = previous;
+        self.bindings = previous_bindings;
+        match result? {
+            Flow::Return(value) => Ok(value),
+            // 函数体里循环外面的break由resolver报错
+            Flow::Normal | Flow::Break => Ok(Value::Null),
+        }
+    }
+
+    fn visit_function(&mut self, _ast: &Ast, _id: ExprId, expr: &Function) -> EvalResult {
+        Ok(self.closure(expr))
+    }
+
+    fn visit_list(&mut self, ast: &Ast, _id: ExprId, expr: &List) -> EvalResult {
+        let mut elements = Vec::with_capacity(expr.elements.len());
+        for element in &expr.elements {
+            elements.push(element.accept(ast, self)?);
+        }
+        Ok(Value::List(Rc::new(elements)))
+    }
+
+    fn visit_index(&mut self, ast: &Ast, _id: ExprId, expr: &Index) -> EvalResult {
+        let object = expr.object.accept(ast, self)?;
+        let index = expr.index.accept(ast, self)?;
+        let Value::List(elements) = object else {
+            return Err(Self::error(&expr.bracket, "Can only index lists."));
+        };
+        match index {
+            Value::Number(index) if index.fract() == 0.0 && index >= 0.0 && (index as usize) < elements.len() => {
+                Ok(elements[index as usize].clone())
+            }
+            Value::Number(_) => Err(Self::error(&expr.bracket, "Index out of range.")),
+            _ => Err(Self::error(&expr.bracket, "Index must be a number.")),
+        }
+    }
+
+}
+
+impl StmtVisitor<ExecResult> for Interpreter {
+
+    fn visit_expression_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Expression) -> ExecResult {
+        stmt.expr.accept(ast, self)?;
+        Ok(Flow::Normal)
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Print) -> ExecResult {
+        let value = stmt.expr.accept(ast, self)?;
+        println!("{value}");
+        Ok(Flow::Normal)
+    }
+
+    fn visit_let_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Let) -> ExecResult {
+        let value = match stmt.initializer {
+            Some(initializer) => initializer.accept(ast, self)?,
+            None => Value::Null,
+        };
+        self.environment.borrow_mut().define(&stmt.name.lexeme, value);
+        Ok(Flow::Normal)
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Block) -> ExecResult {
+        let environment = Environment::new(self.environment.clone());
+        self.execute_block(ast, &stmt.stmts, environment)
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &If) -> ExecResult {
+        if stmt.condition.accept(ast, self)?.is_truthy() {
+            stmt.then_branch.accept(ast, self)
+        } else if let Some(else_branch) = stmt.else_branch {
+            else_branch.accept(ast, self)
+        } else {
+            Ok(Flow::Normal)
+        }
+    }
+
+    // 和desugar之后一样，init在单独的一层环境里，increment在循环体后面
+    fn visit_for_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &For) -> ExecResult {
+        let environment = self.heap.environment(Environment::new(self.environment.clone()));
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let result = self.execute_for(ast, stmt);
+        self.environment = previous;
+        result
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &While) -> ExecResult {
+        while stmt.condition.accept(ast, self)?.is_truthy() {
+            match stmt.body.accept(ast, self)? {
+                Flow::Normal => {}
+                Flow::Break => break,
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn visit_function_stmt(&mut self, _ast: &Ast, _id: StmtId, stmt: &Function) -> ExecResult {
+        // 闭包捕获的是当前环境本身，函数体执行的时候才查找名字，所以函数可以递归调用自己
+        let closure = self.closure(stmt);
+        if let Some(name) = &stmt.name {
+            self.environment.borrow_mut().define(&name.lexeme, closure);
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Return) -> ExecResult {
+        let value = match stmt.value {
+            Some(value) => value.accept(ast, self)?,
+            None => Value::Null,
+        };
+        Ok(Flow::Return(value))
+    }
+
+    fn visit_break_stmt(&mut self, _ast: &Ast, _id: StmtId, _stmt: &Break) -> ExecResult {
+        Ok(Flow::Break)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter::zip;
+
+    use crate::compiler_core::parser::Parser;
+    use crate::compiler_core::visitors::AstPrinter;
+
+    use super::*;
+
+    fn eval(input: &str) -> EvalResult {
+        let mut parser = Parser::new(input);
+        let expr = parser.parse();
+        Interpreter::new().evaluate(parser.ast(), expr)
+    }
+
+    #[test]
+    fn arithmetic_test() {
+        let inputs = [
+            "(1 + 3) * 2",
+            "-5 / 2",
+            "1 < 2",
+            "\"a\" + \"b\"",
+            "!null",
+            "1 == 1 != false",
+        ];
+        let expects = [
+            Value::Number(8.0),
+            Value::Number(-2.5),
+            Value::Bool(true),
+            Value::String("ab".to_string()),
+            Value::Bool(true),
+            Value::Bool(true),
+        ];
+        for (input, expect) in zip(inputs.iter(), expects.iter()) {
+            assert_eq!(eval(input), Ok(expect.clone()));
+        }
+    }
+
+    #[test]
+    fn interpolation_test() {
+        let inputs = [
+            "\"hello ${\"world\"}\"",
+            "\"${1 + 2} = ${3}, ${1 < 2} ${null}\"",
+            "\"outer ${\"inner ${2 * 2}\"}!\"",
+        ];
+        let expects = [
+            "hello world",
+            "3 = 3, true null",
+            "outer inner 4!",
+        ];
+        for (input, expect) in zip(inputs.iter(), expects.iter()) {
+            assert_eq!(eval(input), Ok(Value::String(expect.to_string())));
+        }
+    }
+
+    #[test]
+    fn runtime_error_test() {
+        let error = eval("\"a\" - 1").unwrap_err();
+        assert_eq!(error.token.lexeme, "-");
+        assert_eq!(error.message, "Operands must be numbers.");
+    }
+
+    fn run(input: &str) -> Result<Option<Value>, RuntimeError> {
+        let mut parser = Parser::new(input);
+        let program = parser.parse_program();
+        assert!(!parser.had_error(), "{}", input);
+        Interpreter::new().interpret(parser.ast(), &program)
+    }
+
+    #[test]
+    fn statement_test() {
+        let inputs = [
+            "let x = 1; { let x = 2; x = x + 1; } x",
+            "let i = 0; let sum = 0; while i < 5 { i = i + 1; if i == 4 { break; } sum = sum + i; } sum",
+            "fn fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); } fib(10)",
+            "fn counter() { let n = 0; return fn() { n = n + 1; return n; }; } let c = counter(); c(); c()",
+            "null or \"default\"",
+            "false and undefined",
+            "let x; x",
+            "let xs = [1, \"a\", [true]]; \"${xs} ${xs[2][0]}\"",
+        ];
+        let expects = [
+            Value::Number(1.0),
+            Value::Number(6.0),
+            Value::Number(55.0),
+            Value::Number(2.0),
+            Value::String("default".to_string()),
+            Value::Bool(false),
+            Value::Null,
+            Value::String("[1, a, [true]] true".to_string()),
+        ];
+        for (input, expect) in zip(inputs.iter(), expects.iter()) {
+            assert_eq!(run(input), Ok(Some(expect.clone())), "{}", input);
+        }
+    }
+
+    #[test]
+    fn static_scope_test() {
+        // show里的x在resolver看来是全局变量，后面块里声明的同名局部变量不影响它
+        let source = "let x = \"global\"; let seen = \"\"; { fn show() { print x; seen = seen + x + \" \"; } show(); let x = \"local\"; show(); } seen";
+        assert_eq!(run(source), Ok(Some(Value::String("global global ".to_string()))));
+        // 闭包里的局部变量按层数找，中间块里的同名变量不影响它
+        let source = "fn f() { let a = 1; fn g() { return a; } { let a = 2; return g(); } } f()";
+        assert_eq!(run(source), Ok(Some(Value::Number(1.0))));
+    }
+
+    #[test]
+    fn sugar_test() {
+        // 直接执行parser的树，for和复合赋值在interpret里desugar
+        assert_eq!(run("let s = 0; for let i = 1; i <= 4; i += 1 { if i == 4 { break; } s += i; } s"), Ok(Some(Value::Number(6.0))));
+        let mut parser = Parser::new("(x -= 1)");
+        let expr = parser.parse();
+        let mut interpreter = Interpreter::new();
+        interpreter.environment.borrow_mut().define("x", Value::Number(3.0));
+        assert_eq!(interpreter.evaluate(parser.ast(), expr), Ok(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn undesugared_test() {
+        // 不经过interpret，直接执行parser的树
+        let source = "let s = \"\"; fn f() { for let i = 0; ; i += 1 { if i == 3 { return i; } s += \"${i}\"; } } let n = f(); n *= 10;";
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        let ast = Rc::new(parser.into_ast());
+        let mut interpreter = Interpreter::new();
+        interpreter.ast = ast.clone();
+        interpreter.bindings = Rc::new(resolve(&ast, &program).bindings);
+        for stmt in &program {
+            assert_eq!(stmt.accept(&ast, &mut interpreter), Ok(Flow::Normal));
+        }
+        assert_eq!(interpreter.globals.borrow().get("s"), Some(Value::String("012".to_string())));
+        assert_eq!(interpreter.globals.borrow().get("n"), Some(Value::Number(30.0)));
+
+        // 报错的是去掉`=`的运算符
+        let mut parser = Parser::new("s -= 1");
+        let expr = parser.parse();
+        interpreter.bindings = Rc::new(resolve_expr(parser.ast(), expr).bindings);
+        let error = expr.accept(parser.ast(), &mut interpreter).unwrap_err();
+        assert_eq!((error.token.lexeme.as_str(), error.message.as_str()), ("-", "Operands must be numbers."));
+    }
+
+    #[test]
+    fn statement_error_test() {
+        let inputs = [
+            ("x = 1;", "x", "Undefined variable."),
+            ("fn f(a) { } f(1, 2);", ")", "Expected 1 arguments but got 2."),
+            ("let x = 1; x();", ")", "Can only call functions."),
+            ("[1, 2][2];", "[", "Index out of range."),
+            ("1[0];", "[", "Can only index lists."),
+        ];
+        for (input, lexeme, message) in inputs {
+            let error = run(input).unwrap_err();
+            assert_eq!(error.token.lexeme, lexeme);
+            assert_eq!(error.message, message);
+        }
+    }
+
+    #[test]
+    fn call_depth_test() {
+        // 递归太深的时候报运行时错误，不会让解释器栈溢出
+        let depth = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+            let source = "fn f(n) { if n == 0 { return 0; } return f(n - 1) + 1; }";
+            assert_eq!(run(&format!("{source} f(999)")), Ok(Some(Value::Number(999.0))));
+            let error = run(&format!("{source} f(10000)")).unwrap_err();
+            assert_eq!(error.token.lexeme, ")");
+            assert_eq!(error.message, "Stack overflow.");
+            // 出错之后深度恢复，后面的调用不受影响
+            let source = format!("{source} f(10000)");
+            let mut parser = Parser::new(&source);
+            let program = parser.parse_program();
+            let mut interpreter = Interpreter::new();
+            assert!(interpreter.interpret(parser.ast(), &program).is_err());
+            let mut parser = Parser::new("f(999)");
+            let program = parser.parse_program();
+            assert_eq!(interpreter.interpret(parser.ast(), &program), Ok(Some(Value::Number(999.0))));
+        });
+        depth.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn cycle_collection_test() {
+        // 每次调用outer都会产生一个环境和helper互相引用的环，执行中间会回收很多次
+        let source = "fn outer() { fn helper() { return 1; } return helper(); }
+            fn counter() { let n = 0; fn count() { n = n + 1; return n; } return count; }
+            let c = counter(); let i = 0; while i < 5000 { outer(); c(); i = i + 1; } c()";
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        let mut interpreter = Interpreter::new();
+        let global = Rc::downgrade(&interpreter.environment);
+        assert_eq!(interpreter.interpret(parser.ast(), &program), Ok(Some(Value::Number(5001.0))));
+        // 剩下全局环境和counter的调用环境，以及outer、counter、count三个函数
+        interpreter.heap.collect();
+        assert_eq!((interpreter.heap.environments.len(), interpreter.heap.closures.len()), (2, 3));
+        drop(interpreter);
+        assert!(global.upgrade().is_none());
+    }
+
+    #[test]
+    fn one_parse_many_passes_test() {
+        // 同一棵语法树既可以打印也可以求值
+        let mut parser = Parser::new("(1 + 3) * 2");
+        let expr = parser.parse();
+        let ast = parser.ast();
+        assert_eq!(AstPrinter::new().print_expr(ast, expr), "(* (group (+ 1 3)) 2)");
+        assert_eq!(Interpreter::new().evaluate(ast, expr), Ok(Value::Number(8.0)));
+    }
+
+    #[test]
+    fn closure_across_asts_test() {
+        // REPL里每一行是一个新的Ast，前面定义的函数在后面仍然可以调用
+        let mut interpreter = Interpreter::new();
+        let mut results = vec![];
+        for line in ["fn make(n) { return fn() { return n * 2; }; }", "let f = make(21);", "f()"] {
+            let mut parser = Parser::new(line);
+            let program = parser.parse_program();
+            results.push(interpreter.interpret(parser.ast(), &program));
+        }
+        assert_eq!(results[2], Ok(Some(Value::Number(42.0))));
+    }
+}
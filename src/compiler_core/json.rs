@@ -0,0 +1,730 @@
+use std::fmt;
+
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::StmtId;
+use super::dump::json_string;
+use super::expr::Assign;
+use super::expr::Binary;
+use super::expr::Call;
+use super::expr::CompoundAssign;
+use super::expr::Expr;
+use super::expr::Function;
+use super::expr::Grouping;
+use super::expr::Index;
+use super::expr::Interpolation;
+use super::expr::List;
+use super::expr::Literal;
+use super::expr::LiteralValue;
+use super::expr::Logical;
+use super::expr::TemplatePart;
+use super::expr::TypeAnnotation;
+use super::expr::Unary;
+use super::expr::Variable;
+use super::stmt::Block;
+use super::stmt::Break;
+use super::stmt::Expression;
+use super::stmt::For;
+use super::stmt::If;
+use super::stmt::Let;
+use super::stmt::Print;
+use super::stmt::Return;
+use super::stmt::Stmt;
+use super::stmt::While;
+use super::token::Span;
+use super::token::Token;
+use super::token::TokenType;
+
+// 语法树的JSON格式，写出见visitors.rs的JsonPrinter，读回见read_program
+//
+// 整个程序: {"kind": "Program", "body": [语句...]}
+// 每个节点是一个对象，kind是节点种类，span是源码区间，其余的键就是节点的字段名:
+//     {"kind": "Binary", "span": {"start": 0, "end": 5}, "left": 节点, "op": token, "right": 节点}
+// - 表达式的kind是Expr的变体名(Binary、Call...)，语句的kind是Stmt的变体名加上Stmt(LetStmt、FunctionStmt...)
+// - 手工构造的节点没有span，这时省略span
+// - token和`lex --format json`的格式一样: {"type": "PLUS", "lexeme": "+", "line": 1, "column": 3, "span": ...}
+// - Literal的value直接是JSON的数字、字符串、true/false或null
+// - Interpolation的parts里字符串片段是JSON字符串，表达式是节点
+// - 可选的字段(Let的initializer、匿名函数的name...)没有值的时候是null
+// - 类型标注也是对象，kind是NamedType、GenericType或FunctionType:
+//     {"kind": "GenericType", "name": token, "arguments": [类型...]}
+//     {"kind": "FunctionType", "params": [类型...], "return": 类型}
+//   读的时候可以省略标注的字段，当作没有标注
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    // 保持键的顺序，输出的结果是稳定的，方便diff
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct JsonError {
+    pub message: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+fn error<T>(message: String) -> Result<T, JsonError> {
+    Err(JsonError { message })
+}
+
+// 一行能放下的数组和对象不换行
+const LINE_WIDTH: usize = 120;
+
+impl Json {
+    pub fn parse(source: &str) -> Result<Json, JsonError> {
+        let mut parser = JsonParser { source, pos: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.pos < source.len() {
+            return parser.error("unexpected trailing characters");
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    // 全部写在一行
+    pub fn compact(&self) -> String {
+        let mut result = String::new();
+        self.write_compact(&mut result);
+        result
+    }
+
+    // 缩进两个空格，能放在一行的数组和对象不换行
+    pub fn pretty(&self) -> String {
+        let mut result = String::new();
+        self.write_pretty(0, &mut result);
+        result
+    }
+
+    fn write_compact(&self, out: &mut String) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(value) => out.push_str(&value.to_string()),
+            Json::Number(value) => out.push_str(&value.to_string()),
+            Json::String(value) => out.push_str(&json_string(value)),
+            Json::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    item.write_compact(out);
+                }
+                out.push(']');
+            }
+            Json::Object(entries) => {
+                out.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    out.push_str(&json_string(key));
+                    out.push_str(": ");
+                    value.write_compact(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    fn write_pretty(&self, indent: usize, out: &mut String) {
+        let compact = self.compact();
+        let line_start = out.rfind('\n').map_or(0, |i| i + 1);
+        if out.len() - line_start + compact.len() <= LINE_WIDTH {
+            out.push_str(&compact);
+            return;
+        }
+        let (open, close) = match self {
+            Json::Array(_) => ('[', ']'),
+            Json::Object(_) => ('{', '}'),
+            _ => {
+                out.push_str(&compact);
+                return;
+            }
+        };
+        out.push(open);
+        let mut first = true;
+        let mut item = |out: &mut String, key: Option<&str>, value: &Json| {
+            if !first {
+                out.push(',');
+            }
+            first = false;
+            out.push('\n');
+            out.push_str(&" ".repeat(indent + 2));
+            if let Some(key) = key {
+                out.push_str(&json_string(key));
+                out.push_str(": ");
+            }
+            value.write_pretty(indent + 2, out);
+        };
+        match self {
+            Json::Array(items) => items.iter().for_each(|value| item(out, None, value)),
+            Json::Object(entries) => entries.iter().for_each(|(key, value)| item(out, Some(key), value)),
+            _ => unreachable!(),
+        }
+        out.push('\n');
+        out.push_str(&" ".repeat(indent));
+        out.push(close);
+    }
+}
+
+struct JsonParser<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn error<T>(&self, message: &str) -> Result<T, JsonError> {
+        error(format!("{} at byte {}", message, self.pos))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source[self.pos..].chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c @ (' ' | '\t' | '\n' | '\r')) = self.peek() {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn eat(&mut self, text: &str) -> bool {
+        if self.source[self.pos..].starts_with(text) {
+            self.pos += text.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", c))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            _ if self.eat("null") => Ok(Json::Null),
+            _ if self.eat("true") => Ok(Json::Bool(true)),
+            _ if self.eat("false") => Ok(Json::Bool(false)),
+            _ => self.error("expected a value"),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.expect('{')?;
+        let mut entries = vec![];
+        self.skip_whitespace();
+        if self.eat("}") {
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return self.error("expected a key");
+            }
+            let key = self.string()?;
+            self.expect(':')?;
+            entries.push((key, self.value()?));
+            self.skip_whitespace();
+            if self.eat("}") {
+                return Ok(Json::Object(entries));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.skip_whitespace();
+        if self.eat("]") {
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(Json::Array(items));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        while let Some(c @ ('-' | '+' | '.' | 'e' | 'E' | '0'..='9')) = self.peek() {
+            self.pos += c.len_utf8();
+        }
+        match self.source[start..self.pos].parse() {
+            Ok(number) => Ok(Json::Number(number)),
+            Err(_) => {
+                self.pos = start;
+                self.error("invalid number")
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut result = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return self.error("unterminated string");
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(result),
+                '\\' => {
+                    let Some(escape) = self.peek() else {
+                        return self.error("unterminated string");
+                    };
+                    self.pos += escape.len_utf8();
+                    match escape {
+                        '"' | '\\' | '/' => result.push(escape),
+                        'n' => result.push('\n'),
+                        'r' => result.push('\r'),
+                        't' => result.push('\t'),
+                        'b' => result.push('\u{8}'),
+                        'f' => result.push('\u{c}'),
+                        'u' => result.push(self.unicode_escape()?),
+                        _ => return self.error("invalid escape"),
+                    }
+                }
+                c => result.push(c),
+            }
+        }
+    }
+
+    // \uXXXX，码点超过0xFFFF的字符是两个\u组成的代理对
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        if (0xD800..0xDC00).contains(&high) && self.eat("\\u") {
+            let low = self.hex4()?;
+            let code = 0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF);
+            return char::from_u32(code).map_or_else(|| self.error("invalid unicode escape"), Ok);
+        }
+        char::from_u32(high).map_or_else(|| self.error("invalid unicode escape"), Ok)
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let digits = self.source.get(self.pos..self.pos + 4).and_then(|digits| u32::from_str_radix(digits, 16).ok());
+        match digits {
+            Some(value) => {
+                self.pos += 4;
+                Ok(value)
+            }
+            None => self.error("invalid unicode escape"),
+        }
+    }
+}
+
+pub fn span_to_json(span: Span) -> Json {
+    Json::Object(vec![
+        ("start".to_string(), Json::Number(span.start as f64)),
+        ("end".to_string(), Json::Number(span.end as f64)),
+    ])
+}
+
+pub fn token_to_json(token: &Token) -> Json {
+    Json::Object(vec![
+        ("type".to_string(), Json::String(token.token_type.to_string())),
+        ("lexeme".to_string(), Json::String(token.lexeme.clone())),
+        ("line".to_string(), Json::Number(token.line as f64)),
+        ("column".to_string(), Json::Number(token.column as f64)),
+        ("span".to_string(), span_to_json(token.span)),
+    ])
+}
+
+pub fn annotation_to_json(annotation: &TypeAnnotation) -> Json {
+    let node = |kind: &str, fields: Vec<(&str, Json)>| {
+        let mut entries = vec![("kind".to_string(), Json::String(kind.to_string()))];
+        entries.extend(fields.into_iter().map(|(key, value)| (key.to_string(), value)));
+        Json::Object(entries)
+    };
+    match annotation {
+        TypeAnnotation::Name(name) => node("NamedType", vec![("name", token_to_json(name))]),
+        TypeAnnotation::Generic(name, arguments) => node(
+            "GenericType",
+            vec![("name", token_to_json(name)), ("arguments", Json::Array(arguments.iter().map(annotation_to_json).collect()))],
+        ),
+        TypeAnnotation::Function(params, ret) => node(
+            "FunctionType",
+            vec![("params", Json::Array(params.iter().map(annotation_to_json).collect())), ("return", annotation_to_json(ret))],
+        ),
+    }
+}
+
+pub fn optional_annotation_to_json(annotation: &Option<TypeAnnotation>) -> Json {
+    annotation.as_ref().map_or(Json::Null, annotation_to_json)
+}
+
+// 从JSON读回整个程序，节点按照子节点在前的顺序分配，和parser一样
+pub fn read_program(source: &str) -> Result<(Ast, Vec<StmtId>), JsonError> {
+    let json = Json::parse(source)?;
+    let mut reader = AstReader { ast: Ast::new() };
+    if reader.kind(&json)? != "Program" {
+        return error("expected a Program".to_string());
+    }
+    let program = reader.stmts(&json, "body")?;
+    Ok((reader.ast, program))
+}
+
+struct AstReader {
+    ast: Ast,
+}
+
+impl AstReader {
+    fn field<'a>(&self, json: &'a Json, key: &str) -> Result<&'a Json, JsonError> {
+        match json.get(key) {
+            Some(value) => Ok(value),
+            None => error(format!("missing field \"{}\" in {}", key, self.describe(json))),
+        }
+    }
+
+    // 出错的时候告诉用户是哪个节点
+    fn describe(&self, json: &Json) -> String {
+        match json.get("kind") {
+            Some(Json::String(kind)) => kind.clone(),
+            _ => json.compact(),
+        }
+    }
+
+    fn kind<'a>(&self, json: &'a Json) -> Result<&'a str, JsonError> {
+        match self.field(json, "kind")? {
+            Json::String(kind) => Ok(kind),
+            value => error(format!("expected a string kind, got {}", value.compact())),
+        }
+    }
+
+    fn number(&self, json: &Json, key: &str) -> Result<f64, JsonError> {
+        match self.field(json, key)? {
+            Json::Number(value) => Ok(*value),
+            value => error(format!("expected a number for \"{}\", got {}", key, value.compact())),
+        }
+    }
+
+    fn string(&self, json: &Json, key: &str) -> Result<String, JsonError> {
+        match self.field(json, key)? {
+            Json::String(value) => Ok(value.clone()),
+            value => error(format!("expected a string for \"{}\", got {}", key, value.compact())),
+        }
+    }
+
+    fn array<'a>(&self, json: &'a Json, key: &str) -> Result<&'a [Json], JsonError> {
+        match self.field(json, key)? {
+            Json::Array(items) => Ok(items),
+            value => error(format!("expected an array for \"{}\", got {}", key, value.compact())),
+        }
+    }
+
+    // 没有span的节点是手工构造的
+    fn span(&self, json: &Json) -> Result<Option<Span>, JsonError> {
+        match json.get("span") {
+            None => Ok(None),
+            Some(span) => Ok(Some(Span::new(self.number(span, "start")? as usize, self.number(span, "end")? as usize))),
+        }
+    }
+
+    // 手工构造的token可以只写type和lexeme，位置都是0
+    fn token(&self, json: &Json) -> Result<Token, JsonError> {
+        let name = self.string(json, "type")?;
+        let Some(token_type) = TokenType::from_name(&name) else {
+            return error(format!("unknown token type {}", name));
+        };
+        let position = |key| if json.get(key).is_some() { self.number(json, key) } else { Ok(0.0) };
+        Ok(Token {
+            token_type,
+            lexeme: self.string(json, "lexeme")?,
+            line: position("line")? as i32,
+            column: position("column")? as i32,
+            span: self.span(json)?.unwrap_or_default(),
+        })
+    }
+
+    fn token_field(&self, json: &Json, key: &str) -> Result<Token, JsonError> {
+        self.token(self.field(json, key)?)
+    }
+
+    fn tokens(&self, json: &Json, key: &str) -> Result<Vec<Token>, JsonError> {
+        self.array(json, key)?.iter().map(|token| self.token(token)).collect()
+    }
+
+    fn expr_field(&mut self, json: &Json, key: &str) -> Result<ExprId, JsonError> {
+        self.expr(self.field(json, key)?)
+    }
+
+    fn optional_expr(&mut self, json: &Json, key: &str) -> Result<Option<ExprId>, JsonError> {
+        match self.field(json, key)? {
+            Json::Null => Ok(None),
+            expr => self.expr(expr).map(Some),
+        }
+    }
+
+    fn exprs(&mut self, json: &Json, key: &str) -> Result<Vec<ExprId>, JsonError> {
+        self.array(json, key)?.iter().map(|expr| self.expr(expr)).collect()
+    }
+
+    fn stmt_field(&mut self, json: &Json, key: &str) -> Result<StmtId, JsonError> {
+        self.stmt(self.field(json, key)?)
+    }
+
+    fn optional_stmt(&mut self, json: &Json, key: &str) -> Result<Option<StmtId>, JsonError> {
+        match self.field(json, key)? {
+            Json::Null => Ok(None),
+            stmt => self.stmt(stmt).map(Some),
+        }
+    }
+
+    fn stmts(&mut self, json: &Json, key: &str) -> Result<Vec<StmtId>, JsonError> {
+        self.array(json, key)?.iter().map(|stmt| self.stmt(stmt)).collect()
+    }
+
+    fn annotation(&self, json: &Json) -> Result<TypeAnnotation, JsonError> {
+        let annotations = |key| self.array(json, key)?.iter().map(|annotation| self.annotation(annotation)).collect::<Result<Vec<_>, _>>();
+        Ok(match self.kind(json)? {
+            "NamedType" => TypeAnnotation::Name(self.token_field(json, "name")?),
+            "GenericType" => TypeAnnotation::Generic(self.token_field(json, "name")?, annotations("arguments")?),
+            "FunctionType" => TypeAnnotation::Function(annotations("params")?, Box::new(self.annotation(self.field(json, "return")?)?)),
+            kind => return error(format!("unknown type kind {}", kind)),
+        })
+    }
+
+    // 没有这个字段和null一样
+    fn optional_annotation(&self, json: Option<&Json>) -> Result<Option<TypeAnnotation>, JsonError> {
+        match json {
+            None | Some(Json::Null) => Ok(None),
+            Some(annotation) => self.annotation(annotation).map(Some),
+        }
+    }
+
+    fn function(&mut self, json: &Json) -> Result<Box<Function>, JsonError> {
+        let name = match self.field(json, "name")? {
+            Json::Null => None,
+            name => Some(self.token(name)?),
+        };
+        let params = self.tokens(json, "params")?;
+        let param_annotations = match json.get("param_annotations") {
+            None => vec![None; params.len()],
+            Some(_) => {
+                let annotations = self.array(json, "param_annotations")?;
+                annotations.iter().map(|annotation| self.optional_annotation(Some(annotation))).collect::<Result<Vec<_>, _>>()?
+            }
+        };
+        if param_annotations.len() != params.len() {
+            return error(format!("expected {} param_annotations in {}, got {}", params.len(), self.describe(json), param_annotations.len()));
+        }
+        let return_annotation = self.optional_annotation(json.get("return_annotation"))?;
+        Ok(Box::new(Function::new(name, params, param_annotations, return_annotation, self.stmts(json, "body")?)))
+    }
+
+    fn expr(&mut self, json: &Json) -> Result<ExprId, JsonError> {
+        let expr = match self.kind(json)? {
+            "Binary" => Expr::Binary(Binary::new(
+                self.expr_field(json, "left")?,
+                self.token_field(json, "op")?,
+                self.expr_field(json, "right")?,
+            )),
+            "Grouping" => Expr::Grouping(Grouping::new(self.expr_field(json, "expr")?)),
+            "Literal" => {
+                let value = match self.field(json, "value")? {
+                    Json::Number(value) => LiteralValue::Number(*value),
+                    Json::String(value) => LiteralValue::String(value.clone()),
+                    Json::Bool(value) => LiteralValue::Bool(*value),
+                    Json::Null => LiteralValue::Null,
+                    value => return error(format!("invalid literal {}", value.compact())),
+                };
+                Expr::Literal(Literal::new(value))
+            }
+            "Unary" => Expr::Unary(Unary::new(self.token_field(json, "op")?, self.expr_field(json, "right")?)),
+            "Interpolation" => {
+                let mut parts = vec![];
+                for part in self.array(json, "parts")? {
+                    match part {
+                        Json::String(chunk) => parts.push(TemplatePart::Chunk(chunk.clone())),
+                        expr => parts.push(TemplatePart::Expr(self.expr(expr)?)),
+                    }
+                }
+                Expr::Interpolation(Interpolation::new(parts))
+            }
+            "Variable" => Expr::Variable(Variable::new(self.token_field(json, "name")?)),
+            "Assign" => Expr::Assign(Assign::new(self.token_field(json, "name")?, self.expr_field(json, "value")?)),
+            "CompoundAssign" => Expr::CompoundAssign(CompoundAssign::new(
+                self.token_field(json, "name")?,
+                self.token_field(json, "op")?,
+                self.expr_field(json, "value")?,
+            )),
+            "Logical" => Expr::Logical(Logical::new(
+                self.expr_field(json, "left")?,
+                self.token_field(json, "op")?,
+                self.expr_field(json, "right")?,
+            )),
+            "Call" => Expr::Call(Call::new(
+                self.expr_field(json, "callee")?,
+                self.token_field(json, "paren")?,
+                self.exprs(json, "arguments")?,
+            )),
+            "Function" => Expr::Function(self.function(json)?),
+            "List" => Expr::List(List::new(self.token_field(json, "bracket")?, self.exprs(json, "elements")?)),
+            "Index" => Expr::Index(Index::new(
+                self.expr_field(json, "object")?,
+                self.token_field(json, "bracket")?,
+                self.expr_field(json, "index")?,
+            )),
+            kind => return error(format!("unknown expression kind {}", kind)),
+        };
+        Ok(match self.span(json)? {
+            Some(span) => self.ast.add_expr(expr, span),
+            None => self.ast.exprs.alloc(expr),
+        })
+    }
+
+    fn stmt(&mut self, json: &Json) -> Result<StmtId, JsonError> {
+        let stmt = match self.kind(json)? {
+            "ExpressionStmt" => Stmt::Expression(Expression::new(self.expr_field(json, "expr")?)),
+            "PrintStmt" => Stmt::Print(Print::new(self.expr_field(json, "expr")?)),
+            "LetStmt" => Stmt::Let(Let::new(
+                self.token_field(json, "name")?,
+                self.optional_annotation(json.get("annotation"))?,
+                self.optional_expr(json, "initializer")?,
+            )),
+            "BlockStmt" => Stmt::Block(Block::new(self.stmts(json, "stmts")?)),
+            "IfStmt" => Stmt::If(If::new(
+                self.expr_field(json, "condition")?,
+                self.stmt_field(json, "then_branch")?,
+                self.optional_stmt(json, "else_branch")?,
+            )),
+            "WhileStmt" => Stmt::While(While::new(self.expr_field(json, "condition")?, self.stmt_field(json, "body")?)),
+            "ForStmt" => Stmt::For(For::new(
+                self.optional_stmt(json, "initializer")?,
+                self.optional_expr(json, "condition")?,
+                self.optional_expr(json, "increment")?,
+                self.stmt_field(json, "body")?,
+            )),
+            "FunctionStmt" => Stmt::Function(self.function(json)?),
+            "ReturnStmt" => Stmt::Return(Return::new(self.token_field(json, "keyword")?, self.optional_expr(json, "value")?)),
+            "BreakStmt" => Stmt::Break(Break::new(self.token_field(json, "keyword")?)),
+            kind => return error(format!("unknown statement kind {}", kind)),
+        };
+        Ok(match self.span(json)? {
+            Some(span) => self.ast.add_stmt(stmt, span),
+            None => self.ast.stmts.alloc(stmt),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::parser::tests::CORPUS;
+    use crate::compiler_core::parser::Parser;
+    use crate::compiler_core::visitors::AstPrinter;
+    use crate::compiler_core::visitors::JsonPrinter;
+
+    use super::*;
+
+    #[test]
+    fn parse_test() {
+        let json = Json::parse(r#" {"a": [1, -2.5e1, true, null], "b": "x\"é😀\n", "c": {}} "#).unwrap();
+        assert_eq!(json.get("a"), Some(&Json::Array(vec![Json::Number(1.0), Json::Number(-25.0), Json::Bool(true), Json::Null])));
+        assert_eq!(json.get("b"), Some(&Json::String("x\"é😀\n".to_string())));
+        assert_eq!(json.compact(), r#"{"a": [1, -25, true, null], "b": "x\"é😀\n", "c": {}}"#);
+
+        for input in ["", "[1,", "{\"a\" 1}", "[1] 2", "\"abc", "tru", "-"] {
+            assert!(Json::parse(input).is_err(), "{}", input);
+        }
+        assert_eq!(Json::parse("[1, }").unwrap_err().message, "expected a value at byte 4");
+    }
+
+    #[test]
+    fn pretty_test() {
+        let long = Json::String("x".repeat(110));
+        let json = Json::Object(vec![
+            ("short".to_string(), Json::Array(vec![Json::Number(1.0), Json::Number(2.0)])),
+            ("long".to_string(), Json::Array(vec![long.clone(), Json::Null])),
+        ]);
+        assert_eq!(json.pretty(), format!("{{\n  \"short\": [1, 2],\n  \"long\": [\n    {},\n    null\n  ]\n}}", long.compact()));
+    }
+
+    #[test]
+    fn serialize_test() {
+        let mut parser = Parser::new("print -x;");
+        let program = parser.parse_program();
+        let json = JsonPrinter::new().print_program(parser.ast(), &program);
+        assert_eq!(
+            json.compact(),
+            [
+                r#"{"kind": "Program", "body": [{"kind": "PrintStmt", "span": {"start": 0, "end": 9}, "expr": "#,
+                r#"{"kind": "Unary", "span": {"start": 6, "end": 8}, "#,
+                r#""op": {"type": "MINUS", "lexeme": "-", "line": 1, "column": 7, "span": {"start": 6, "end": 7}}, "#,
+                r#""right": {"kind": "Variable", "span": {"start": 7, "end": 8}, "#,
+                r#""name": {"type": "IDENT", "lexeme": "x", "line": 1, "column": 8, "span": {"start": 7, "end": 8}}}}}]}"#,
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let mut printer = AstPrinter::new();
+        for input in CORPUS {
+            let mut parser = Parser::new(input);
+            let program = parser.parse_program();
+            let json = JsonPrinter::new().print_program(parser.ast(), &program).pretty();
+            let (ast, read) = read_program(&json).unwrap();
+            // 赋值的左边先被解析成Variable，parser的arena里会多出节点，所以比较读回来的树而不是整个arena
+            assert_eq!(JsonPrinter::new().print_program(&ast, &read).pretty(), json, "{}", input);
+            assert_eq!(printer.print_program(&ast, &read), printer.print_program(parser.ast(), &program));
+            for (old, new) in program.iter().zip(&read) {
+                assert_eq!(ast.stmt_span(*new), parser.ast().stmt_span(*old));
+            }
+        }
+    }
+
+    #[test]
+    fn read_error_test() {
+        let inputs = [
+            (r#"{"kind": "Program"}"#, "missing field \"body\" in Program"),
+            (r#"{"kind": "Program", "body": [{"kind": "PrintStmt"}]}"#, "missing field \"expr\" in PrintStmt"),
+            (r#"{"kind": "Program", "body": [{"kind": "Loop"}]}"#, "unknown statement kind Loop"),
+            (r#"{"kind": "Program", "body": [{"kind": "PrintStmt", "expr": {"kind": "Literal", "value": []}}]}"#, "invalid literal []"),
+        ];
+        for (input, message) in inputs {
+            assert_eq!(read_program(input).unwrap_err().message, message);
+        }
+
+        // 手工构造的树可以省略span，token可以只写type和lexeme
+        let (ast, program) = read_program(
+            r#"{"kind": "Program", "body": [{"kind": "PrintStmt", "expr": {"kind": "Unary",
+                "op": {"type": "MINUS", "lexeme": "-"}, "right": {"kind": "Literal", "value": 1}}}]}"#,
+        )
+        .unwrap();
+        assert_eq!(AstPrinter::new().print_program(&ast, &program), "(print (- 1))");
+        assert!(!ast.stmt_spans.contains(program[0]));
+    }
+}
@@ -1,197 +1,482 @@
+use std::borrow::Cow;
 use std::iter::Peekable;
 use std::str::Chars;
 use super::token::TokenType;
 use super::token::Token;
+use super::token::BorrowedToken;
+use super::token::Span;
 use super::token::loopkup_ident;
-use std::rc::Rc;
-use std::cell::RefCell;
-use std::ops::Deref;
+
+// 从源码中间某个位置继续扫描需要的全部状态，用于流式扫描
+#[derive(Debug, PartialEq, Clone)]
+pub struct LexerState {
+    // 在整个源码中的字节偏移
+    pub offset: usize,
+    pub line: i32,
+    pub column: i32,
+    // 见Lexer::templates
+    pub templates: Vec<usize>,
+}
+
+impl Default for LexerState {
+    fn default() -> Self {
+        Self { offset: 0, line: 1, column: 1, templates: vec![] }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Lexer<'a> {
+    source: &'a str,
+    // source[0]在整个源码中的字节偏移，span要加上它
+    base: usize,
     expr: Peekable<Chars<'a>>,
     line: i32,
+    // 下一个字符的列号，从1开始，按字符计数
+    column: i32,
+    // 下一个字符在源码中的字节偏移
+    pos: usize,
+    // 当前token开始的字节偏移和行列号
+    start: usize,
+    start_line: i32,
+    start_column: i32,
+    // 每一层未结束的`${`里，还没闭合的`{`的个数
+    // 栈不为空说明当前在插值表达式里，遇到不配对的`}`要回到字符串模式
+    templates: Vec<usize>,
+    // 是否已经返回了EOF
+    done: bool,
 }
 
 
 impl<'a> Lexer<'a> {
     pub fn new(new_expr: &'a str) -> Self {
-        Lexer { expr: new_expr.chars().peekable(), line: 1 }
+        Self::with_state(new_expr, LexerState::default())
+    }
+
+    // 从state的位置继续扫描，source是整个源码从state.offset开始的部分
+    pub fn with_state(source: &'a str, state: LexerState) -> Self {
+        Lexer {
+            source,
+            base: state.offset,
+            expr: source.chars().peekable(),
+            line: state.line,
+            column: state.column,
+            pos: 0,
+            start: 0,
+            start_line: state.line,
+            start_column: state.column,
+            templates: state.templates,
+            done: false,
+        }
+    }
+
+    // 当前扫描到的位置，可以用with_state从这里继续
+    pub fn state(&self) -> LexerState {
+        LexerState {
+            offset: self.base + self.pos,
+            line: self.line,
+            column: self.column,
+            templates: self.templates.clone(),
+        }
+    }
+
+    // 不分配内存的token流，lexeme直接借用源码
+    // 适合一次性处理大文件，REPL等需要保存token的地方还是用Iterator返回的Token
+    pub fn borrowed(mut self) -> impl Iterator<Item = BorrowedToken<'a>> {
+        std::iter::from_fn(move || self.next_token())
+    }
+
+    // 最后一个token之后会返回一个EOF token，位置是源码末尾
+    fn next_token(&mut self) -> Option<BorrowedToken<'a>> {
+        if self.done {
+            return None;
+        }
+        let token = self.scan_token();
+        if token.is_none() {
+            self.done = true;
+            return Some(self.token(TokenType::EOF));
+        }
+        token
+    }
+
+    // 消费一个字符，同时更新字节偏移和行列号
+    fn advance(&mut self) -> Option<char> {
+        let c = self.expr.next()?;
+        self.pos += c.len_utf8();
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
     }
 
     fn expected(&mut self, expected: char) -> bool {
         match self.expr.peek() {
             Some(&actual) if actual == expected => {
-                self.expr.next();
+                self.advance();
                 true
             },
             _ => false
         }
     }
 
-}
+    // 用当前token的起止位置构造token，lexeme就是token的原文
+    fn token(&self, token_type: TokenType) -> BorrowedToken<'a> {
+        self.token_with(token_type, Cow::Borrowed(&self.source[self.start..self.pos]))
+    }
 
-impl<'a> Iterator for Lexer<'a> {
-    type Item = Token;
-    
-    // 跳过空格
-    fn next(&mut self) -> Option<Self::Item> {
-        
-        let next_char = self.expr.next();
-        
-        match next_char {
-            Some('+') => Some(Token::new(TokenType::PLUS, next_char?.to_string(), self.line)),
-            Some('.') => Some(Token::new(TokenType::DOT, next_char?.to_string(), self.line)),
-            Some('-') => Some(Token::new(TokenType::MINUS, next_char?.to_string(), self.line)),
-            Some('*') => Some(Token::new(TokenType::ASTERISK, next_char?.to_string(), self.line)),
-            Some('^') => Some(Token::new(TokenType::CARET, next_char?.to_string(), self.line)),
-            Some('(') => Some(Token::new(TokenType::LPAREN, next_char?.to_string(), self.line)),
-            Some(')') => Some(Token::new(TokenType::RPAREN, next_char?.to_string(), self.line)),
-            Some('{') => Some(Token::new(TokenType::LBRACE, next_char?.to_string(), self.line)),
-            Some('}') => Some(Token::new(TokenType::RBRACE, next_char?.to_string(), self.line)),
-            Some('[') => Some(Token::new(TokenType::LBRACKET, next_char?.to_string(), self.line)),
-            Some(']') => Some(Token::new(TokenType::RBRACKET, next_char?.to_string(), self.line)),
-            Some(':') => Some(Token::new(TokenType::COLON, next_char?.to_string(), self.line)),
-            Some(',') => Some(Token::new(TokenType::COMMA, next_char?.to_string(), self.line)),
-            Some(';') => Some(Token::new(TokenType::SEMICOLON,next_char?.to_string(), self.line)),
-            None => None, 
-            Some('=') => {
-                // 如果后面跟的是等号，就返回EQ 否则返回赋值号
-                if self.expected('=') {
-                    Some(Token::new(TokenType::EQ, "==".to_string(), self.line))
-                } else {
-                    Some(Token::new(TokenType::ASSIGN, "=".to_string(), self.line))
+    fn token_with(&self, token_type: TokenType, lexeme: Cow<'a, str>) -> BorrowedToken<'a> {
+        BorrowedToken {
+            token_type,
+            lexeme,
+            line: self.start_line,
+            column: self.start_column,
+            span: Span::new(self.base + self.start, self.base + self.pos),
+        }
+    }
+
+    // 跳过块注释，开头的`/*`已经被消费
+    // 块注释可以嵌套，直到所有的`/*`都有对应的`*/`才结束
+    // 如果到文件末尾还没结束，返回一个ILLEGAL token
+    fn block_comment(&mut self) -> Result<(), BorrowedToken<'a>> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.advance() {
+                Some('/') if self.expected('*') => depth += 1,
+                Some('*') if self.expected('/') => depth -= 1,
+                Some(_) => {}
+                None => return Err(self.token(TokenType::ILLEGAL)),
+            }
+        }
+        Ok(())
+    }
+
+    // 读字符串内容，直到结束的双引号或者`${`
+    // is_head为true表示从开头的双引号开始读，否则是从插值结束的`}`开始读
+    // `$$`是一个`$`，所以`$${`是字面的`${`；没有结束就到了文件末尾时返回ILLEGAL
+    fn string_part(&mut self, is_head: bool) -> BorrowedToken<'a> {
+        // 有`$$`的时候内容和源码不同，复制到escaped里，chunk_start之前的部分已经复制过
+        let mut escaped: Option<String> = None;
+        let mut chunk_start = self.pos;
+        loop {
+            let rest = &self.source[self.pos..];
+            if rest.starts_with("$$") {
+                escaped.get_or_insert_with(String::new).push_str(&self.source[chunk_start..=self.pos]);
+                self.advance();
+                self.advance();
+                chunk_start = self.pos;
+                continue;
+            }
+            let content = |lexer: &Self| -> Cow<'a, str> {
+                let chunk = &lexer.source[chunk_start..lexer.pos];
+                match &escaped {
+                    Some(escaped) => Cow::Owned(format!("{}{}", escaped, chunk)),
+                    None => Cow::Borrowed(chunk),
                 }
-            },
-            Some('!') => {
-                // 如果后面跟的是等号，就返回NOT_EQ 否则返回感叹号
-                if self.expected('=') {
-                    Some(Token::new(TokenType::NOT_EQ, "!=".to_string(), self.line))
+            };
+            if rest.starts_with("${") {
+                let content = content(self);
+                self.advance();
+                self.advance();
+                let token_type = if is_head {
+                    self.templates.push(0);
+                    TokenType::TEMPLATE_HEAD
                 } else {
-                    Some(Token::new(TokenType::EXCLAMATION, "!".to_string(), self.line))
+                    TokenType::TEMPLATE_MIDDLE
+                };
+                return self.token_with(token_type, content);
+            }
+            match self.expr.peek() {
+                Some('"') => {
+                    let content = content(self);
+                    // 跳过结束的双引号
+                    self.advance();
+                    let token_type = if is_head {
+                        TokenType::STRING
+                    } else {
+                        self.templates.pop();
+                        TokenType::TEMPLATE_TAIL
+                    };
+                    return self.token_with(token_type, content);
                 }
-            },
-            Some('<') => {
-                if self.expected('=') {
-                    Some(Token::new(TokenType::LE, "<=".to_string(), self.line))                    
-                } else {
-                    Some(Token::new(TokenType::LT, "<".to_string(), self.line))
+                None => {
+                    if !is_head {
+                        self.templates.pop();
+                    }
+                    return self.token(TokenType::ILLEGAL);
                 }
-            },
-            Some('>') => {
-                if self.expected('=') {
-                    Some(Token::new(TokenType::GE, ">=".to_string(), self.line))
-                } else {
-                    Some(Token::new(TokenType::GT, ">".to_string(), self.line))
+                Some(_) => {
+                    self.advance();
                 }
-            },
-            Some('/') => {
-                if self.expected('/') {
-                    // 如果是注释，就一直读到换行符
-                    while let Some(&next_char) = self.expr.peek() {
-                        if next_char != '\n' {
-                            self.expr.next();
-                        } else {
-                            break;
-                        }
-                    };
-                    // 返回下一个token
-                    self.next()
-                } else {
-                    Some(Token::new(TokenType::SLASH, "/".to_string(), self.line))
+            }
+        }
+    }
+
+    // 原始字符串 r"..." 或 r#"..."#，开头的r已经被消费
+    // 内容原样保留，不做插值；用#的个数区分结束的引号，这样内容里可以出现"
+    // 没有结束的引号时返回ILLEGAL
+    fn raw_string(&mut self) -> BorrowedToken<'a> {
+        let mut hashes = 0;
+        while self.expected('#') {
+            hashes += 1;
+        }
+        // 跳过开头的双引号
+        self.advance();
+        let content_start = self.pos;
+        let content_end = loop {
+            match self.expr.peek() {
+                None => return self.token(TokenType::ILLEGAL),
+                Some('"') if self.source.as_bytes()[self.pos + 1..].iter().take_while(|&&b| b == b'#').count() >= hashes => {
+                    let end = self.pos;
+                    for _ in 0..=hashes {
+                        self.advance();
+                    }
+                    break end;
                 }
-            },
-            Some('\r') => {
-                // 如果是空格，就跳过
-                self.next()
-            },
-            Some('\t') => {
-                // 如果是空格，就跳过
-                self.next()
-            },
-            Some(' ') => {
-                // 如果是空格，就跳过
-                self.next()
-            },
-            Some('\n') => {
-                // 如果是换行符，line加1，返回下一个token
-                self.line += 1;
-                self.next()
-            },
-            Some('"') => {
-                // 如果是双引号，就一直读到下一个双引号
-                let mut string = String::new();
-                while let Some(&next_char) = self.expr.peek() {
-                    if next_char != '"' {
-                        if next_char == '\n' {
-                            // 遇到换行符，line加1
-                            self.line += 1;
+                Some(_) => {
+                    self.advance();
+                }
+            }
+        };
+        let content = &self.source[content_start..content_end];
+        self.token_with(TokenType::STRING, Cow::Borrowed(content))
+    }
+
+    // 多行字符串 """..."""，开头的三个引号已经被消费
+    // 内容不做插值，会去掉公共缩进，见dedent；没有结束的引号时返回ILLEGAL
+    fn multiline_string(&mut self) -> BorrowedToken<'a> {
+        let content_start = self.pos;
+        let content_end = loop {
+            if self.source[self.pos..].starts_with("\"\"\"") {
+                let end = self.pos;
+                for _ in 0..3 {
+                    self.advance();
+                }
+                break end;
+            }
+            if self.advance().is_none() {
+                return self.token(TokenType::ILLEGAL);
+            }
+        };
+        let content = dedent(&self.source[content_start..content_end]);
+        self.token_with(TokenType::STRING, content)
+    }
+
+    // 扫描下一个token，跳过空白和注释
+    fn scan_token(&mut self) -> Option<BorrowedToken<'a>> {
+        // 注释和空白直接跳过，用循环而不是递归，连续很多行注释也不会栈溢出
+        loop {
+            self.start = self.pos;
+            self.start_line = self.line;
+            self.start_column = self.column;
+            let next_char = self.advance();
+
+            let token = match next_char {
+                Some('+') if self.expected('=') => Some(self.token(TokenType::PLUS_ASSIGN)),
+                Some('+') => Some(self.token(TokenType::PLUS)),
+                Some('.') => Some(self.token(TokenType::DOT)),
+                Some('-') if self.expected('=') => Some(self.token(TokenType::MINUS_ASSIGN)),
+                Some('-') => Some(self.token(TokenType::MINUS)),
+                Some('*') if self.expected('=') => Some(self.token(TokenType::ASTERISK_ASSIGN)),
+                Some('*') => Some(self.token(TokenType::ASTERISK)),
+                Some('^') => Some(self.token(TokenType::CARET)),
+                Some('(') => Some(self.token(TokenType::LPAREN)),
+                Some(')') => Some(self.token(TokenType::RPAREN)),
+                Some('{') => {
+                    if let Some(depth) = self.templates.last_mut() {
+                        *depth += 1;
+                    }
+                    Some(self.token(TokenType::LBRACE))
+                },
+                Some('}') => {
+                    // 插值表达式结束，回到字符串模式
+                    match self.templates.last_mut() {
+                        Some(0) => Some(self.string_part(false)),
+                        Some(depth) => {
+                            *depth -= 1;
+                            Some(self.token(TokenType::RBRACE))
                         }
-                        string.push(self.expr.next()?);
+                        None => Some(self.token(TokenType::RBRACE)),
+                    }
+                },
+                Some('[') => Some(self.token(TokenType::LBRACKET)),
+                Some(']') => Some(self.token(TokenType::RBRACKET)),
+                Some(':') => Some(self.token(TokenType::COLON)),
+                Some(',') => Some(self.token(TokenType::COMMA)),
+                Some(';') => Some(self.token(TokenType::SEMICOLON)),
+                None => None, 
+                Some('=') => {
+                    // 如果后面跟的是等号，就返回EQ 否则返回赋值号
+                    if self.expected('=') {
+                        Some(self.token(TokenType::EQ))
                     } else {
-                        break;
+                        Some(self.token(TokenType::ASSIGN))
                     }
-                }
-                // 跳过下一个双引号
-                self.expr.next();
-                Some(Token::new(
-                        TokenType::STRING,
-                        string.clone(),
-                        self.line))
-            },
-            Some('0'..='9') => {
-                // 处理数字的情况
-                let mut number = next_char?.to_string();
-                
-                while let Some(next_char) = self.expr.peek() {
-                    if next_char.is_numeric() || next_char == &'.' {
-                        number.push(self.expr.next()?);
+                },
+                Some('!') => {
+                    // 如果后面跟的是等号，就返回NOT_EQ 否则返回感叹号
+                    if self.expected('=') {
+                        Some(self.token(TokenType::NOT_EQ))
                     } else {
-                        break;
+                        Some(self.token(TokenType::EXCLAMATION))
                     }
-                }
-                
-                // 如果这个非数字字符是小数点，就继续读数字
-                if self.expr.peek() == Some(&'.') {
-                    number.push(self.expr.next()?);
+                },
+                Some('<') => {
+                    if self.expected('=') {
+                        Some(self.token(TokenType::LE))
+                    } else {
+                        Some(self.token(TokenType::LT))
+                    }
+                },
+                Some('>') => {
+                    if self.expected('=') {
+                        Some(self.token(TokenType::GE))
+                    } else {
+                        Some(self.token(TokenType::GT))
+                    }
+                },
+                Some('/') => {
+                    if self.expected('/') {
+                        // `///`是文档注释，但`////`仍然是普通注释
+                        let is_doc = self.expr.peek() == Some(&'/') && {
+                            let mut ahead = self.expr.clone();
+                            ahead.next();
+                            ahead.peek() != Some(&'/')
+                        };
+                        if is_doc {
+                            self.advance();
+                        }
+                        let content_start = self.pos;
+                        // 如果是注释，就一直读到换行符
+                        while let Some(&next_char) = self.expr.peek() {
+                            if next_char != '\n' {
+                                self.advance();
+                            } else {
+                                break;
+                            }
+                        };
+                        if is_doc {
+                            // 文档注释作为token返回，lexeme是`///`之后的内容
+                            let comment = &self.source[content_start..self.pos];
+                            return Some(self.token_with(TokenType::DOC_COMMENT, Cow::Borrowed(comment)));
+                        }
+                        // 跳过注释，接着读下一个token
+                        continue;
+                    } else if self.expected('*') {
+                        // 块注释，可以嵌套
+                        match self.block_comment() {
+                            Ok(()) => continue,
+                            Err(token) => Some(token),
+                        }
+                    } else if self.expected('=') {
+                        Some(self.token(TokenType::SLASH_ASSIGN))
+                    } else {
+                        Some(self.token(TokenType::SLASH))
+                    }
+                },
+                // 如果是空格，就跳过
+                Some('\r' | '\t' | ' ') => continue,
+                // 如果是换行符，跳过，行号在advance里已经加1
+                Some('\n') => continue,
+                Some('"') => {
+                    if self.source[self.pos..].starts_with("\"\"") {
+                        self.advance();
+                        self.advance();
+                        return Some(self.multiline_string());
+                    }
+                    // 如果是双引号，就一直读到下一个双引号或者`${`
+                    Some(self.string_part(true))
+                },
+                Some('0'..='9') => {
+                    // 处理数字的情况
                     while let Some(next_char) = self.expr.peek() {
-                        if next_char.is_numeric() {
-                            number.push(self.expr.next()?);
+                        if next_char.is_numeric() || next_char == &'.' {
+                            self.advance();
                         } else {
                             break;
                         }
                     }
-                }
 
+                    // 如果这个非数字字符是小数点，就继续读数字
+                    if self.expr.peek() == Some(&'.') {
+                        self.advance();
+                        while let Some(next_char) = self.expr.peek() {
+                            if next_char.is_numeric() {
+                                self.advance();
+                            } else {
+                                break;
+                            }
+                        }
+                    }
 
-                // 返回数字
-                Some(Token::new(
-                        TokenType::NUMBER,
-                        number.clone(),
-                        self.line))
-            },
-            Some(_) => {
-                if next_char?.is_alphabetic() {
-                    let mut identifier = next_char?.to_string();
-                    while let Some(next_char) = self.expr.peek() {
-                        if next_char.is_alphabetic() {
-                            identifier.push(self.expr.next()?);
-                        } else {
-                            break;
+
+                    // 返回数字
+                    Some(self.token(TokenType::NUMBER))
+                },
+                Some('r') if self.source[self.pos..].trim_start_matches('#').starts_with('"') => {
+                    Some(self.raw_string())
+                },
+                Some(_) => {
+                    if next_char?.is_alphabetic() {
+                        while let Some(next_char) = self.expr.peek() {
+                            if next_char.is_alphabetic() {
+                                self.advance();
+                            } else {
+                                break;
+                            }
                         }
+                        // 如果是字符，先看下是不是关键字，如果不是关键字就当做Identifier
+                        // 关键字的lexeme也保留源码原文
+                        let identifier = &self.source[self.start..self.pos];
+                        Some(self.token(loopkup_ident(identifier)))
+                    } else {
+                        // 其他情况就返回非法token
+                        Some(self.token(TokenType::ILLEGAL))
                     }
-                    // 如果是字符，先看下是不是关键字，如果不是关键字就当做Identifier
-                    Some(loopkup_ident(&mut identifier, self.line))
-                } else {
-                    // 其他情况就返回非法token
-                    Some(Token::new(TokenType::ILLEGAL, next_char?.to_string(), self.line))
                 }
-            }
+            };
+            return token;
         }
-       
+    }
+
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token().map(BorrowedToken::into_owned)
     }
 }
 
+// 去掉多行字符串的公共缩进:
+// 1. 紧跟在开头引号后面的换行符去掉
+// 2. 结束引号所在的行如果只有空白，连同前面的换行符一起去掉
+// 3. 所有非空行去掉相同数量的行首空格和制表符，只有空白的行变成空行
+// 内容不需要修改时直接借用源码
+fn dedent(content: &str) -> Cow<'_, str> {
+    let content = content.strip_prefix("\r\n").or_else(|| content.strip_prefix('\n')).unwrap_or(content);
+    let content = match content.rfind('\n') {
+        Some(last) if content[last + 1..].trim().is_empty() => content[..last].trim_end_matches('\r'),
+        _ => content,
+    };
+    let indent = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or(0);
+    let changed = indent > 0 || content.lines().any(|line| !line.is_empty() && line.trim().is_empty());
+    if !changed {
+        return Cow::Borrowed(content);
+    }
+    let lines: Vec<&str> = content
+        .split('\n')
+        .map(|line| if line.trim().is_empty() { "" } else { &line[indent..] })
+        .collect();
+    Cow::Owned(lines.join("\n"))
+}
 
 // pub struct CloneableLexer<'a>(Rc<RefCell<Lexer + 'a>>);
 
@@ -207,27 +492,41 @@ impl<'a> Iterator for Lexer<'a> {
 mod tests {
     use super::*;
 
+    // 手工构造的token没有span，只比较类型、lexeme和行号
+    fn assert_token(actual: Option<Token>, expect: &Token) {
+        let actual = actual.unwrap();
+        assert_eq!(
+            (actual.token_type, actual.lexeme, actual.line),
+            (expect.token_type, expect.lexeme.clone(), expect.line)
+        );
+    }
+
+    // 最后一个token是EOF，之后就没有了
+    fn assert_end(lexer: &mut Lexer) {
+        assert_eq!(lexer.next().unwrap().token_type, TokenType::EOF);
+        assert_eq!(lexer.next(), None);
+    }
     
     #[test]
     fn keywords_test() {
         let mut lexer = Lexer::new("true false if else return let fn");
         let expects = [
-            Token::new(TokenType::TRUE, "TRUE".to_string(), 1),
-            Token::new(TokenType::FALSE, "FALSE".to_string(), 1),
-            Token::new(TokenType::IF, "IF".to_string(), 1),
-            Token::new(TokenType::ELSE, "ELSE".to_string(), 1),
-            Token::new(TokenType::RETURN, "RETURN".to_string(), 1),
-            Token::new(TokenType::LET, "LET".to_string(), 1),
-            Token::new(TokenType::FUNCTION, "FUNCTION".to_string(), 1)
+            Token::new(TokenType::TRUE, "true".to_string(), 1),
+            Token::new(TokenType::FALSE, "false".to_string(), 1),
+            Token::new(TokenType::IF, "if".to_string(), 1),
+            Token::new(TokenType::ELSE, "else".to_string(), 1),
+            Token::new(TokenType::RETURN, "return".to_string(), 1),
+            Token::new(TokenType::LET, "let".to_string(), 1),
+            Token::new(TokenType::FUNCTION, "fn".to_string(), 1)
         ];
         for expect in expects {
-            assert_eq!(lexer.next().unwrap(), expect);
+            assert_token(lexer.next(), &expect);
         }
     }
     
     #[test]
     fn special_character_test() {
-        let mut lexer = Lexer::new("=+(){}[],;*/!<>^>= <= == !=");
+        let mut lexer = Lexer::new("=+(){}[],;*/!<>^>= <= == != += -= *= /=");
         let expects = [
             Token::new(TokenType::ASSIGN, "=".to_string(), 1),
             Token::new(TokenType::PLUS, "+".to_string(), 1),
@@ -249,9 +548,13 @@ mod tests {
             Token::new(TokenType::LE, "<=".to_string(), 1),
             Token::new(TokenType::EQ, "==".to_string(), 1),
             Token::new(TokenType::NOT_EQ, "!=".to_string(), 1),
+            Token::new(TokenType::PLUS_ASSIGN, "+=".to_string(), 1),
+            Token::new(TokenType::MINUS_ASSIGN, "-=".to_string(), 1),
+            Token::new(TokenType::ASTERISK_ASSIGN, "*=".to_string(), 1),
+            Token::new(TokenType::SLASH_ASSIGN, "/=".to_string(), 1),
         ];
         for expect in expects {
-            assert_eq!(lexer.next().unwrap(), expect);
+            assert_token(lexer.next(), &expect);
         }
         
     }
@@ -270,20 +573,20 @@ mod tests {
             return false;
         }");
         let expects = [
-            Token::new(TokenType::LET, "LET".to_string(), 1),
+            Token::new(TokenType::LET, "let".to_string(), 1),
             Token::new(TokenType::IDENT, "five".to_string(), 1),
             Token::new(TokenType::ASSIGN, "=".to_string(), 1),
             Token::new(TokenType::NUMBER, "5".to_string(), 1),
             Token::new(TokenType::SEMICOLON, ";".to_string(), 1),
-            Token::new(TokenType::LET, "LET".to_string(), 2),
+            Token::new(TokenType::LET, "let".to_string(), 2),
             Token::new(TokenType::IDENT, "ten".to_string(), 2),
             Token::new(TokenType::ASSIGN, "=".to_string(), 2),
             Token::new(TokenType::NUMBER, "10".to_string(), 2),
             Token::new(TokenType::SEMICOLON, ";".to_string(), 2),
-            Token::new(TokenType::LET, "LET".to_string(), 3),
+            Token::new(TokenType::LET, "let".to_string(), 3),
             Token::new(TokenType::IDENT, "add".to_string(), 3),
             Token::new(TokenType::ASSIGN, "=".to_string(), 3),
-            Token::new(TokenType::FUNCTION, "FUNCTION".to_string(), 3),
+            Token::new(TokenType::FUNCTION, "fn".to_string(), 3),
             Token::new(TokenType::LPAREN, "(".to_string(), 3),
             Token::new(TokenType::IDENT, "x".to_string(), 3),
             Token::new(TokenType::COMMA, ",".to_string(), 3),
@@ -297,28 +600,245 @@ mod tests {
             Token::new(TokenType::RBRACE, "}".to_string(), 5),
             Token::new(TokenType::SEMICOLON, ";".to_string(), 5),
 
-            Token::new(TokenType::IF, "IF".to_string(), 7),
+            Token::new(TokenType::IF, "if".to_string(), 7),
             Token::new(TokenType::LPAREN, "(".to_string(), 7),
             Token::new(TokenType::IDENT, "ten".to_string(), 7),
             Token::new(TokenType::NOT_EQ, "!=".to_string(), 7),
             Token::new(TokenType::IDENT, "five".to_string(), 7),
             Token::new(TokenType::RPAREN, ")".to_string(), 7),
             Token::new(TokenType::LBRACE, "{".to_string(), 7),
-            Token::new(TokenType::RETURN, "RETURN".to_string(), 8),
-            Token::new(TokenType::TRUE, "TRUE".to_string(), 8),
+            Token::new(TokenType::RETURN, "return".to_string(), 8),
+            Token::new(TokenType::TRUE, "true".to_string(), 8),
             Token::new(TokenType::SEMICOLON, ";".to_string(), 8),
             Token::new(TokenType::RBRACE, "}".to_string(), 9),
-            Token::new(TokenType::ELSE, "ELSE".to_string(), 9),
+            Token::new(TokenType::ELSE, "else".to_string(), 9),
             Token::new(TokenType::LBRACE, "{".to_string(), 9),
-            Token::new(TokenType::RETURN, "RETURN".to_string(), 10),
-            Token::new(TokenType::FALSE, "FALSE".to_string(), 10),
+            Token::new(TokenType::RETURN, "return".to_string(), 10),
+            Token::new(TokenType::FALSE, "false".to_string(), 10),
             Token::new(TokenType::SEMICOLON, ";".to_string(), 10),
             Token::new(TokenType::RBRACE, "}".to_string(), 11),
         ];
         print!("{:?}", expects);
         for expect in expects.iter() {
             print!("{:?}", *expect);
-            assert_eq!(lexer.next().unwrap(), *expect);
+            assert_token(lexer.next(), expect);
+        }
+    }
+
+    #[test]
+    fn block_comment_test() {
+        let mut lexer = Lexer::new("1 /* one
+        /* nested
+        */ still comment */ + 2 /**/ ;");
+        let expects = [
+            Token::new(TokenType::NUMBER, "1".to_string(), 1),
+            Token::new(TokenType::PLUS, "+".to_string(), 3),
+            Token::new(TokenType::NUMBER, "2".to_string(), 3),
+            Token::new(TokenType::SEMICOLON, ";".to_string(), 3),
+        ];
+        for expect in expects {
+            assert_token(lexer.next(), &expect);
+        }
+        assert_end(&mut lexer);
+    }
+
+    #[test]
+    fn unterminated_block_comment_test() {
+        let mut lexer = Lexer::new("1 /* open /* nested */\n");
+        assert_token(lexer.next(), &Token::new(TokenType::NUMBER, "1".to_string(), 1));
+        assert_token(
+            lexer.next(),
+            &Token::new(TokenType::ILLEGAL, "/* open /* nested */\n".to_string(), 1)
+        );
+        assert_end(&mut lexer);
+    }
+
+    #[test]
+    fn doc_comment_test() {
+        let mut lexer = Lexer::new("/// adds two numbers
+        fn // plain comment
+        //// not a doc comment
+        let");
+        let expects = [
+            Token::new(TokenType::DOC_COMMENT, " adds two numbers".to_string(), 1),
+            Token::new(TokenType::FUNCTION, "fn".to_string(), 2),
+            Token::new(TokenType::LET, "let".to_string(), 4),
+        ];
+        for expect in expects {
+            assert_token(lexer.next(), &expect);
+        }
+        assert_end(&mut lexer);
+    }
+
+    #[test]
+    fn span_test() {
+        let source = "let π = \"hi\"; /* c */ 3.14";
+        let spans: Vec<&str> = Lexer::new(source)
+            .map(|token| &source[token.span.start..token.span.end])
+            .collect();
+        assert_eq!(spans, ["let", "π", "=", "\"hi\"", ";", "3.14", ""]);
+    }
+
+    #[test]
+    fn borrowed_test() {
+        let source = "let name = \"world\"; name";
+        let tokens: Vec<BorrowedToken> = Lexer::new(source).borrowed().collect();
+        let owned: Vec<Token> = Lexer::new(source).collect();
+        assert_eq!(tokens.len(), owned.len());
+        for (borrowed, owned) in tokens.iter().zip(owned) {
+            assert_eq!(borrowed.clone().into_owned(), owned);
+        }
+        // lexeme都直接指向源码
+        assert!(matches!(tokens[0].lexeme, Cow::Borrowed("let")));
+        assert!(matches!(tokens[1].lexeme, Cow::Borrowed("name")));
+        assert!(matches!(tokens[3].lexeme, Cow::Borrowed("world")));
+    }
+
+    #[test]
+    fn keyword_prefix_test() {
+        // 关键字只匹配完整的单词
+        let types: Vec<TokenType> = Lexer::new("fn fnx iff classy or orr print printer")
+            .map(|token| token.token_type)
+            .collect();
+        assert_eq!(types, [
+            TokenType::FUNCTION, TokenType::IDENT,
+            TokenType::IDENT, TokenType::IDENT,
+            TokenType::OR, TokenType::IDENT,
+            TokenType::PRINT, TokenType::IDENT,
+            TokenType::EOF,
+        ]);
+    }
+
+    #[test]
+    fn source_reconstruction_test() {
+        let source = "let add = fn ( x , y ) { return x + y ; } ; if ( true and null ) { print add ( 1 , 2.5 ) ; } else { while false { break ; } }";
+        let tokens: Vec<Token> = Lexer::new(source).collect();
+        let lexemes: Vec<&str> = tokens
+            .iter()
+            .filter(|token| token.token_type != TokenType::EOF)
+            .map(|token| token.lexeme.as_str())
+            .collect();
+        assert_eq!(lexemes.join(" "), source);
+        for token in &tokens {
+            assert_eq!(token.lexeme, &source[token.span.start..token.span.end]);
+        }
+    }
+
+    #[test]
+    fn template_string_test() {
+        let mut lexer = Lexer::new("\"a ${x} b ${ {1: \"in ${y}\"} } c\" \"plain\"");
+        let expects = [
+            Token::new(TokenType::TEMPLATE_HEAD, "a ".to_string(), 1),
+            Token::new(TokenType::IDENT, "x".to_string(), 1),
+            Token::new(TokenType::TEMPLATE_MIDDLE, " b ".to_string(), 1),
+            Token::new(TokenType::LBRACE, "{".to_string(), 1),
+            Token::new(TokenType::NUMBER, "1".to_string(), 1),
+            Token::new(TokenType::COLON, ":".to_string(), 1),
+            Token::new(TokenType::TEMPLATE_HEAD, "in ".to_string(), 1),
+            Token::new(TokenType::IDENT, "y".to_string(), 1),
+            Token::new(TokenType::TEMPLATE_TAIL, "".to_string(), 1),
+            Token::new(TokenType::RBRACE, "}".to_string(), 1),
+            Token::new(TokenType::TEMPLATE_TAIL, " c".to_string(), 1),
+            Token::new(TokenType::STRING, "plain".to_string(), 1),
+        ];
+        for expect in expects {
+            assert_token(lexer.next(), &expect);
+        }
+        assert_end(&mut lexer);
+    }
+
+    #[test]
+    fn dollar_escape_test() {
+        // `$$`是一个`$`，`$${`不是插值
+        let mut lexer = Lexer::new("\"$${x} costs $5$$\" \"a$$${b}$$\"");
+        let expects = [
+            Token::new(TokenType::STRING, "${x} costs $5$".to_string(), 1),
+            Token::new(TokenType::TEMPLATE_HEAD, "a$".to_string(), 1),
+            Token::new(TokenType::IDENT, "b".to_string(), 1),
+            Token::new(TokenType::TEMPLATE_TAIL, "$".to_string(), 1),
+        ];
+        for expect in expects {
+            assert_token(lexer.next(), &expect);
         }
+        assert_end(&mut lexer);
+    }
+
+    #[test]
+    fn unterminated_string_test() {
+        let inputs = ["\"open", "\"a ${x} b", "r#\"raw\"", "\"\"\"multi\nline\""];
+        for source in inputs {
+            let tokens: Vec<Token> = Lexer::new(source).collect();
+            let last = &tokens[tokens.len() - 2];
+            assert_eq!(last.token_type, TokenType::ILLEGAL, "{}", source);
+            assert_eq!(last.span.end, source.len(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn raw_string_test() {
+        let source = "r\"C:\\path ${not} \\d+\" r#\"say \"hi\"\"# r##\"a\"#b\"## r\n";
+        let mut lexer = Lexer::new(source);
+        let expects = [
+            Token::new(TokenType::STRING, "C:\\path ${not} \\d+".to_string(), 1),
+            Token::new(TokenType::STRING, "say \"hi\"".to_string(), 1),
+            Token::new(TokenType::STRING, "a\"#b".to_string(), 1),
+            Token::new(TokenType::IDENT, "r".to_string(), 1),
+        ];
+        for expect in expects {
+            assert_token(lexer.next(), &expect);
+        }
+        assert_end(&mut lexer);
+        let spans: Vec<&str> = Lexer::new(source)
+            .map(|token| &source[token.span.start..token.span.end])
+            .collect();
+        assert_eq!(spans[1], "r#\"say \"hi\"\"#");
+    }
+
+    #[test]
+    fn multiline_string_test() {
+        let source = "let sql = \"\"\"
+            select *
+              from t
+
+            where x = \"${y}\"
+            \"\"\";
+        r\"a
+        b\" \"\"\"one line\"\"\" end";
+        let mut lexer = Lexer::new(source);
+        let expects = [
+            Token::new(TokenType::LET, "let".to_string(), 1),
+            Token::new(TokenType::IDENT, "sql".to_string(), 1),
+            Token::new(TokenType::ASSIGN, "=".to_string(), 1),
+            Token::new(TokenType::STRING, "select *\n  from t\n\nwhere x = \"${y}\"".to_string(), 1),
+            Token::new(TokenType::SEMICOLON, ";".to_string(), 6),
+            Token::new(TokenType::STRING, "a\n        b".to_string(), 7),
+            Token::new(TokenType::STRING, "one line".to_string(), 8),
+            Token::new(TokenType::IDENT, "end".to_string(), 8),
+        ];
+        for expect in expects {
+            assert_token(lexer.next(), &expect);
+        }
+        assert_end(&mut lexer);
+    }
+
+    #[test]
+    fn position_test() {
+        let source = "let x =\n  \"a\n b\" π;\n/* c\n */ y";
+        let positions: Vec<(TokenType, i32, i32)> = Lexer::new(source)
+            .map(|token| (token.token_type, token.line, token.column))
+            .collect();
+        assert_eq!(positions, [
+            (TokenType::LET, 1, 1),
+            (TokenType::IDENT, 1, 5),
+            (TokenType::ASSIGN, 1, 7),
+            // 多行的token，位置是开始的位置
+            (TokenType::STRING, 2, 3),
+            (TokenType::IDENT, 3, 5),
+            (TokenType::SEMICOLON, 3, 6),
+            (TokenType::IDENT, 5, 5),
+            (TokenType::EOF, 5, 6),
+        ]);
+        let eof = Lexer::new(source).last().unwrap();
+        assert_eq!(eof.span, Span::new(source.len(), source.len()));
     }
 }
\ No newline at end of file
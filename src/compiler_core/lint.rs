@@ -0,0 +1,541 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::Node;
+use super::ast::StmtId;
+use super::ast::Walk;
+use super::desugar::desugar;
+use super::expr::Binary;
+use super::expr::Expr;
+use super::expr::ExprWalker;
+use super::expr::Function;
+use super::expr::LiteralValue;
+use super::resolver::resolve;
+use super::resolver::Binding;
+use super::resolver::Resolution;
+use super::stmt::Block;
+use super::stmt::If;
+use super::stmt::Let;
+use super::stmt::Stmt;
+use super::stmt::StmtWalker;
+use super::stmt::While;
+use super::token::Span;
+use super::token::Token;
+use super::token::TokenType;
+use super::walk::same_expr;
+use super::walk::walk_children;
+use super::walk::walk_program;
+
+// 代码检查：每条规则是一个Rule，大多数规则内部是一个Walker，只覆盖关心的节点
+// 规则有编号(L001)和名字(unused-variable)，Linter里可以用任意一个关闭规则或者修改级别
+// 规则只报告位置和说明，级别由Linter的配置决定
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "warning" => Some(Severity::Warning),
+            "error" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    // `2:5: warning[L001]: unused variable "x"`，行号和列号从1开始
+    pub fn render(&self, source: &str) -> String {
+        let before = &source[..self.span.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        format!("{}:{}: {}[{}]: {}", line, column, self.severity, self.code, self.message)
+    }
+}
+
+// 规则检查时能用到的信息
+pub struct LintContext<'a> {
+    pub ast: &'a Ast,
+    pub program: &'a [StmtId],
+    pub resolution: &'a Resolution,
+}
+
+pub trait Rule {
+    fn code(&self) -> &'static str;
+    fn name(&self) -> &'static str;
+    fn default_severity(&self) -> Severity;
+    // 每个问题的位置和说明
+    fn check(&self, context: &LintContext) -> Vec<(Span, String)>;
+}
+
+pub struct Linter {
+    // 级别是None的规则不运行
+    rules: Vec<(Box<dyn Rule>, Option<Severity>)>,
+}
+
+impl Default for Linter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Linter {
+    // 所有内置的规则，使用默认的级别
+    pub fn new() -> Linter {
+        let rules: Vec<Box<dyn Rule>> = vec![
+            Box::new(UnusedVariable),
+            Box::new(UnreachableCode),
+            Box::new(Shadowing),
+            Box::new(SelfComparison),
+            Box::new(ConstantCondition),
+        ];
+        let mut linter = Linter { rules: vec![] };
+        for rule in rules {
+            linter.add_rule(rule);
+        }
+        linter
+    }
+
+    pub fn add_rule(&mut self, rule: Box<dyn Rule>) {
+        let severity = rule.default_severity();
+        self.rules.push((rule, Some(severity)));
+    }
+
+    // rule是编号或者名字，level是None的时候关闭这条规则
+    pub fn set_level(&mut self, rule: &str, level: Option<Severity>) -> Result<(), String> {
+        match self.rules.iter_mut().find(|(known, _)| known.code() == rule || known.name() == rule) {
+            Some((_, severity)) => {
+                *severity = level;
+                Ok(())
+            }
+            None => Err(format!("unknown lint rule {}", rule)),
+        }
+    }
+
+    // 按位置排序，规则检查的是desugar之后的核心语法，span还是源码里的
+    pub fn lint(&self, ast: &Ast, program: &[StmtId]) -> Vec<Diagnostic> {
+        let (ast, program) = desugar(ast, program);
+        let resolution = resolve(&ast, &program);
+        let context = LintContext { ast: &ast, program: &program, resolution: &resolution };
+        let mut diagnostics = vec![];
+        for (rule, severity) in &self.rules {
+            let Some(severity) = *severity else {
+                continue;
+            };
+            for (span, message) in rule.check(&context) {
+                diagnostics.push(Diagnostic { code: rule.code(), severity, message, span });
+            }
+        }
+        diagnostics.sort_by_key(|diagnostic| (diagnostic.span.start, diagnostic.code));
+        diagnostics
+    }
+}
+
+// 块或者函数里声明了但是没有读过的变量，只赋值不读也算
+// 全局变量可能在REPL后面的输入里用到，不检查
+struct UnusedVariable;
+
+impl Rule for UnusedVariable {
+    fn code(&self) -> &'static str {
+        "L001"
+    }
+
+    fn name(&self) -> &'static str {
+        "unused-variable"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<(Span, String)> {
+        let mut used = HashSet::new();
+        for (id, expr) in context.ast.exprs.iter() {
+            if let (Expr::Variable(_), Some(Binding::Local { declaration, .. })) = (expr, context.resolution.bindings.get(id)) {
+                used.insert((declaration.start, declaration.end));
+            }
+        }
+        let mut lets = LocalLets { depth: 0, names: vec![] };
+        walk_program(&mut lets, context.ast, context.program);
+        lets.names
+            .into_iter()
+            .filter(|name| !used.contains(&(name.span.start, name.span.end)))
+            .map(|name| (name.span, format!("unused variable \"{}\"", name.lexeme)))
+            .collect()
+    }
+}
+
+// 收集局部作用域里let声明的名字
+struct LocalLets {
+    depth: usize,
+    names: Vec<Token>,
+}
+
+impl LocalLets {
+    fn scope(&mut self, ast: &Ast, node: &impl Walk) {
+        self.depth += 1;
+        walk_children(self, ast, node);
+        self.depth -= 1;
+    }
+}
+
+impl ExprWalker for LocalLets {
+    fn visit_function(&mut self, ast: &Ast, _id: ExprId, expr: &Function) {
+        self.scope(ast, expr);
+    }
+}
+
+impl StmtWalker for LocalLets {
+    fn visit_let_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Let) {
+        if self.depth > 0 {
+            self.names.push(stmt.name.clone());
+        }
+        walk_children(self, ast, stmt);
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Block) {
+        self.scope(ast, stmt);
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Function) {
+        self.scope(ast, stmt);
+    }
+}
+
+// 同一个语句列表里return或者break后面的语句
+struct UnreachableCode;
+
+impl Rule for UnreachableCode {
+    fn code(&self) -> &'static str {
+        "L002"
+    }
+
+    fn name(&self) -> &'static str {
+        "unreachable-code"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<(Span, String)> {
+        let mut lists = StmtLists { lists: vec![context.program.to_vec()] };
+        walk_program(&mut lists, context.ast, context.program);
+        let ast = context.ast;
+        let mut result = vec![];
+        for stmts in lists.lists {
+            let Some(exit) = stmts.iter().position(|&stmt| matches!(ast[stmt], Stmt::Return(_) | Stmt::Break(_))) else {
+                continue;
+            };
+            if let (Some(&first), Some(&last)) = (stmts.get(exit + 1), stmts.last()) {
+                let keyword = if matches!(ast[stmts[exit]], Stmt::Return(_)) { "return" } else { "break" };
+                let span = Span::new(ast.stmt_span(first).start, ast.stmt_span(last).end);
+                result.push((span, format!("unreachable code after \"{}\"", keyword)));
+            }
+        }
+        result
+    }
+}
+
+// 块和函数体里的语句列表
+struct StmtLists {
+    lists: Vec<Vec<StmtId>>,
+}
+
+impl ExprWalker for StmtLists {
+    fn visit_function(&mut self, ast: &Ast, _id: ExprId, expr: &Function) {
+        self.lists.push(expr.body.clone());
+        walk_children(self, ast, expr);
+    }
+}
+
+impl StmtWalker for StmtLists {
+    fn visit_block_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Block) {
+        self.lists.push(stmt.stmts.clone());
+        walk_children(self, ast, stmt);
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Function) {
+        self.lists.push(stmt.body.clone());
+        walk_children(self, ast, stmt);
+    }
+}
+
+// 局部的声明(let、参数、函数名)和外层作用域里前面声明过的变量同名
+struct Shadowing;
+
+impl Rule for Shadowing {
+    fn code(&self) -> &'static str {
+        "L003"
+    }
+
+    fn name(&self) -> &'static str {
+        "shadowing"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<(Span, String)> {
+        let mut scopes = Scopes { scopes: vec![HashSet::new()], shadowed: vec![] };
+        walk_program(&mut scopes, context.ast, context.program);
+        scopes.shadowed.into_iter().map(|name| (name.span, format!("\"{}\" shadows a variable in an outer scope", name.lexeme))).collect()
+    }
+}
+
+// 第一层是全局作用域
+struct Scopes {
+    scopes: Vec<HashSet<String>>,
+    shadowed: Vec<Token>,
+}
+
+impl Scopes {
+    fn declare(&mut self, name: &Token) {
+        let (current, outer) = self.scopes.split_last_mut().unwrap();
+        if outer.iter().any(|scope| scope.contains(&name.lexeme)) {
+            self.shadowed.push(name.clone());
+        }
+        current.insert(name.lexeme.clone());
+    }
+
+    fn function(&mut self, ast: &Ast, function: &Function) {
+        self.scopes.push(HashSet::new());
+        for param in &function.params {
+            self.declare(param);
+        }
+        walk_program(self, ast, &function.body);
+        self.scopes.pop();
+    }
+}
+
+impl ExprWalker for Scopes {
+    fn visit_function(&mut self, ast: &Ast, _id: ExprId, expr: &Function) {
+        self.function(ast, expr);
+    }
+}
+
+impl StmtWalker for Scopes {
+    fn visit_let_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Let) {
+        walk_children(self, ast, stmt);
+        self.declare(&stmt.name);
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Block) {
+        self.scopes.push(HashSet::new());
+        walk_children(self, ast, stmt);
+        self.scopes.pop();
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Function) {
+        if let Some(name) = &stmt.name {
+            self.declare(name);
+        }
+        self.function(ast, stmt);
+    }
+}
+
+// `x == x`、`x + 1 < x + 1`这样两边一样的比较，结果是固定的
+// 有函数调用或者赋值的表达式每次求值可能不一样，不算
+struct SelfComparison;
+
+impl Rule for SelfComparison {
+    fn code(&self) -> &'static str {
+        "L004"
+    }
+
+    fn name(&self) -> &'static str {
+        "self-comparison"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<(Span, String)> {
+        let mut comparisons = Comparisons { spans: vec![] };
+        walk_program(&mut comparisons, context.ast, context.program);
+        comparisons.spans.into_iter().map(|span| (span, "comparison of an expression with itself".to_string())).collect()
+    }
+}
+
+struct Comparisons {
+    spans: Vec<Span>,
+}
+
+impl ExprWalker for Comparisons {
+    fn visit_binary(&mut self, ast: &Ast, id: ExprId, expr: &Binary) {
+        let comparison = matches!(
+            expr.op.token_type,
+            TokenType::EQ | TokenType::NOT_EQ | TokenType::LT | TokenType::LE | TokenType::GT | TokenType::GE
+        );
+        if comparison && pure(ast, expr.left) && same_expr(ast, expr.left, ast, expr.right) {
+            self.spans.push(ast.expr_span(id));
+        }
+        walk_children(self, ast, expr);
+    }
+}
+
+impl StmtWalker for Comparisons {}
+
+// 求值没有副作用，每次的结果一样
+fn pure(ast: &Ast, id: ExprId) -> bool {
+    if matches!(ast[id], Expr::Call(_) | Expr::Assign(_) | Expr::Function(_)) {
+        return false;
+    }
+    let mut result = true;
+    ast[id].walk(&mut |child| {
+        if let Node::Expr(child) = child {
+            result = result && pure(ast, child);
+        }
+    });
+    result
+}
+
+// if和while的条件是常量，`while true`是常见的写法，不算
+struct ConstantCondition;
+
+impl Rule for ConstantCondition {
+    fn code(&self) -> &'static str {
+        "L005"
+    }
+
+    fn name(&self) -> &'static str {
+        "constant-condition"
+    }
+
+    fn default_severity(&self) -> Severity {
+        Severity::Warning
+    }
+
+    fn check(&self, context: &LintContext) -> Vec<(Span, String)> {
+        let mut conditions = Conditions { found: vec![] };
+        walk_program(&mut conditions, context.ast, context.program);
+        conditions.found.into_iter().map(|(span, value)| (span, format!("condition is always {}", value))).collect()
+    }
+}
+
+struct Conditions {
+    found: Vec<(Span, bool)>,
+}
+
+impl Conditions {
+    // 去掉外面的括号，规则一般在desugar之后的树上运行，但不依赖这一点
+    fn literal(ast: &Ast, id: ExprId) -> Option<&LiteralValue> {
+        match &ast[id] {
+            Expr::Grouping(grouping) => Conditions::literal(ast, grouping.expr),
+            Expr::Literal(literal) => Some(&literal.value),
+            _ => None,
+        }
+    }
+
+    // 去掉括号之后是常量的时候返回它的真假
+    fn constant(ast: &Ast, id: ExprId) -> Option<bool> {
+        Conditions::literal(ast, id).map(|value| !matches!(value, LiteralValue::Null | LiteralValue::Bool(false)))
+    }
+}
+
+impl ExprWalker for Conditions {}
+
+impl StmtWalker for Conditions {
+    fn visit_if_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &If) {
+        if let Some(value) = Conditions::constant(ast, stmt.condition) {
+            self.found.push((ast.expr_span(stmt.condition), value));
+        }
+        walk_children(self, ast, stmt);
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &While) {
+        let infinite = Conditions::literal(ast, stmt.condition) == Some(&LiteralValue::Bool(true));
+        if let Some(value) = Conditions::constant(ast, stmt.condition).filter(|_| !infinite) {
+            self.found.push((ast.expr_span(stmt.condition), value));
+        }
+        walk_children(self, ast, stmt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::parser::Parser;
+
+    use super::*;
+
+    fn lint(linter: &Linter, source: &str) -> Vec<String> {
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        assert!(!parser.had_error(), "{}", source);
+        linter.lint(parser.ast(), &program).iter().map(|diagnostic| diagnostic.render(source)).collect()
+    }
+
+    #[test]
+    fn rules_test() {
+        let linter = Linter::new();
+        let inputs: [(&str, &[&str]); 6] = [
+            (
+                "let g = 1;\nfn f(a) {\n    let b = a; let c = 1; print c;\n    let d = 2; d = 3;\n}",
+                &["3:9: warning[L001]: unused variable \"b\"", "4:9: warning[L001]: unused variable \"d\""],
+            ),
+            (
+                "fn f() {\n    return 1;\n    print 2;\n    print 3;\n}\nwhile true { break; }",
+                &["3:5: warning[L002]: unreachable code after \"return\""],
+            ),
+            (
+                "let x = 1;\nfn f(x) { { let y = x; let x = y; print x; } }",
+                &["2:6: warning[L003]: \"x\" shadows a variable in an outer scope", "2:28: warning[L003]: \"x\" shadows a variable in an outer scope"],
+            ),
+            (
+                "let x = 1; print x == x; print (x + 1) < (x + 1); print f() == f(); print x == -x;",
+                &["1:18: warning[L004]: comparison of an expression with itself", "1:32: warning[L004]: comparison of an expression with itself"],
+            ),
+            (
+                "if (null) { print 1; } while 0 { print 2; } while true { break; } while (true) { break; }",
+                &["1:4: warning[L005]: condition is always false", "1:30: warning[L005]: condition is always true"],
+            ),
+            ("let a = 1; fn f(b) { let c = b; return c + a; }", &[]),
+        ];
+        for (source, expect) in inputs {
+            assert_eq!(lint(&linter, source), expect, "{}", source);
+        }
+    }
+
+    #[test]
+    fn config_test() {
+        let mut linter = Linter::new();
+        linter.set_level("L003", None).unwrap();
+        linter.set_level("unused-variable", Some(Severity::Error)).unwrap();
+        assert_eq!(linter.set_level("L999", None), Err("unknown lint rule L999".to_string()));
+        assert_eq!(lint(&linter, "let x = 1; { let x = 2; }"), ["1:18: error[L001]: unused variable \"x\""]);
+    }
+
+    #[test]
+    fn grouped_condition_test() {
+        // 没有desugar的树里括号还在，`while (true)`也不算
+        let mut parser = Parser::new("while (true) { break; } if ((false)) { print 1; }");
+        let program = parser.parse_program();
+        let mut conditions = Conditions { found: vec![] };
+        walk_program(&mut conditions, parser.ast(), &program);
+        assert_eq!(conditions.found, [(Span::new(27, 36), false)]);
+    }
+}
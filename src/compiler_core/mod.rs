@@ -1,5 +1,23 @@
 pub mod token;
 pub mod lexer;
+pub mod stream;
+pub mod incremental;
+pub mod trivia;
+pub mod ast;
+pub mod ast_macro;
 pub mod expr;
+pub mod stmt;
+pub mod walk;
+pub mod resolver;
+pub mod lint;
+pub mod typecheck;
+pub mod optimizer;
+pub mod desugar;
 pub mod visitors;
 pub mod parser;
+pub mod interpreter;
+pub mod dump;
+pub mod json;
+pub mod sexpr;
+pub mod cst;
+pub mod formatter;
@@ -0,0 +1,255 @@
+use std::fmt;
+
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::StmtId;
+use super::expr::Expr;
+use super::expr::Literal;
+use super::expr::LiteralValue;
+use super::token::Token;
+use super::token::TokenType;
+use super::walk::walk_expr_mut;
+use super::walk::walk_program_mut;
+use super::walk::MutVisitor;
+
+// 常量折叠和代数化简，解析之后原地改写语法树：
+// - 去掉Grouping，括号只影响解析
+// - 操作数都是常量的运算直接算出结果，规则和解释器一样，运行时会出错的(比如1 + "a")保持原样
+// - `x - 0`、`x * 1`、`x / 1`只在x一定是数字的时候化简，否则会去掉运行时的类型错误
+//   `x + 0`不化简，x是-0的时候结果是0
+// - 除数是常量0的除法和`/=`报错
+// - `!!b`只在b一定是bool的时候化简，其他的值`!!`之后变成了bool
+// - 左边是常量的and/or直接选出结果
+// 改写用MutVisitor，节点的id和span不变，但之前算好的side table(resolver、typecheck)不再对应
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OptimizeError {
+    pub token: Token,
+    pub message: String,
+}
+
+impl fmt::Display for OptimizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: at token \"{}\", {}", self.token.line, self.token.lexeme, self.message)
+    }
+}
+
+// 返回编译时就能发现的错误，出错的表达式不折叠
+pub fn optimize(ast: &mut Ast, stmts: &[StmtId]) -> Vec<OptimizeError> {
+    let mut optimizer = Optimizer { errors: vec![] };
+    walk_program_mut(&mut optimizer, ast, stmts);
+    optimizer.errors
+}
+
+struct Optimizer {
+    errors: Vec<OptimizeError>,
+}
+
+impl MutVisitor for Optimizer {
+    // 子节点先化简，常量可以一层层往上折叠
+    fn visit_expr_mut(&mut self, ast: &mut Ast, id: ExprId) {
+        walk_expr_mut(self, ast, id);
+        if let Some(expr) = self.simplify(ast, id) {
+            ast.exprs[id] = expr;
+        }
+    }
+}
+
+impl Optimizer {
+    fn error(&mut self, token: &Token, message: &str) {
+        self.errors.push(OptimizeError { token: token.clone(), message: message.to_string() });
+    }
+
+    // 返回替换id的新节点，不能化简的时候返回None
+    fn simplify(&mut self, ast: &Ast, id: ExprId) -> Option<Expr> {
+        match &ast[id] {
+            Expr::Grouping(grouping) => Some(ast[grouping.expr].clone()),
+            Expr::Unary(unary) => {
+                if let Some(value) = constant(ast, unary.right) {
+                    return match (unary.op.token_type, value) {
+                        (TokenType::MINUS, LiteralValue::Number(number)) => Some(literal(LiteralValue::Number(-number))),
+                        (TokenType::EXCLAMATION, value) => Some(literal(LiteralValue::Bool(!truthy(value)))),
+                        _ => None,
+                    };
+                }
+                match &ast[unary.right] {
+                    Expr::Unary(inner) if unary.op.token_type == TokenType::EXCLAMATION
+                        && inner.op.token_type == TokenType::EXCLAMATION
+                        && is_bool(ast, inner.right) =>
+                    {
+                        Some(ast[inner.right].clone())
+                    }
+                    _ => None,
+                }
+            }
+            Expr::Binary(binary) => {
+                let op = &binary.op;
+                if op.token_type == TokenType::SLASH && constant(ast, binary.right) == Some(&LiteralValue::Number(0.0)) {
+                    self.error(op, "Division by zero.");
+                    return None;
+                }
+                if let (Some(left), Some(right)) = (constant(ast, binary.left), constant(ast, binary.right)) {
+                    return fold(op.token_type, left, right).map(literal);
+                }
+                let number = |expr, value| constant(ast, expr) == Some(&LiteralValue::Number(value));
+                let kept = match op.token_type {
+                    TokenType::MINUS if number(binary.right, 0.0) => binary.left,
+                    TokenType::ASTERISK if number(binary.right, 1.0) => binary.left,
+                    TokenType::ASTERISK if number(binary.left, 1.0) => binary.right,
+                    TokenType::SLASH if number(binary.right, 1.0) => binary.left,
+                    _ => return None,
+                };
+                is_number(ast, kept).then(|| ast[kept].clone())
+            }
+            // 复合赋值不用先desugar
+            Expr::CompoundAssign(assign) => {
+                if assign.op.token_type == TokenType::SLASH_ASSIGN && constant(ast, assign.value) == Some(&LiteralValue::Number(0.0)) {
+                    self.error(&assign.op, "Division by zero.");
+                }
+                None
+            }
+            // 返回决定结果的那个操作数，和解释器的短路求值一样
+            Expr::Logical(logical) => {
+                let left = constant(ast, logical.left)?;
+                let short_circuit = match logical.op.token_type {
+                    TokenType::OR => truthy(left),
+                    _ => !truthy(left),
+                };
+                let kept = if short_circuit { logical.left } else { logical.right };
+                Some(ast[kept].clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+fn literal(value: LiteralValue) -> Expr {
+    Expr::Literal(Literal::new(value))
+}
+
+fn constant(ast: &Ast, id: ExprId) -> Option<&LiteralValue> {
+    match &ast[id] {
+        Expr::Literal(literal) => Some(&literal.value),
+        _ => None,
+    }
+}
+
+// 和解释器的Value::is_truthy一样
+fn truthy(value: &LiteralValue) -> bool {
+    !matches!(value, LiteralValue::Null | LiteralValue::Bool(false))
+}
+
+// 两个常量的运算，运行时会出错的返回None，留给解释器报错
+fn fold(op: TokenType, left: &LiteralValue, right: &LiteralValue) -> Option<LiteralValue> {
+    use LiteralValue::Bool;
+    use LiteralValue::Number;
+    let value = match (op, left, right) {
+        (TokenType::PLUS, Number(left), Number(right)) => Number(left + right),
+        (TokenType::PLUS, LiteralValue::String(left), LiteralValue::String(right)) => LiteralValue::String(format!("{}{}", left, right)),
+        (TokenType::MINUS, Number(left), Number(right)) => Number(left - right),
+        (TokenType::ASTERISK, Number(left), Number(right)) => Number(left * right),
+        (TokenType::SLASH, Number(left), Number(right)) => Number(left / right),
+        (TokenType::GT, Number(left), Number(right)) => Bool(left > right),
+        (TokenType::GE, Number(left), Number(right)) => Bool(left >= right),
+        (TokenType::LT, Number(left), Number(right)) => Bool(left < right),
+        (TokenType::LE, Number(left), Number(right)) => Bool(left <= right),
+        (TokenType::EQ, left, right) => Bool(left == right),
+        (TokenType::NOT_EQ, left, right) => Bool(left != right),
+        _ => return None,
+    };
+    Some(value)
+}
+
+// 求值成功的话一定是数字
+fn is_number(ast: &Ast, id: ExprId) -> bool {
+    match &ast[id] {
+        Expr::Literal(literal) => matches!(literal.value, LiteralValue::Number(_)),
+        Expr::Unary(unary) => unary.op.token_type == TokenType::MINUS,
+        Expr::Binary(binary) => match binary.op.token_type {
+            TokenType::MINUS | TokenType::ASTERISK | TokenType::SLASH => true,
+            // 一边是数字的话另一边也必须是数字
+            TokenType::PLUS => is_number(ast, binary.left) || is_number(ast, binary.right),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// 求值成功的话一定是bool
+fn is_bool(ast: &Ast, id: ExprId) -> bool {
+    match &ast[id] {
+        Expr::Literal(literal) => matches!(literal.value, LiteralValue::Bool(_)),
+        Expr::Unary(unary) => unary.op.token_type == TokenType::EXCLAMATION,
+        Expr::Binary(binary) => matches!(
+            binary.op.token_type,
+            TokenType::GT | TokenType::GE | TokenType::LT | TokenType::LE | TokenType::EQ | TokenType::NOT_EQ
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::parser::Parser;
+    use crate::compiler_core::visitors::AstPrinter;
+
+    use super::*;
+
+    fn optimized(source: &str) -> (String, Vec<String>) {
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        assert!(!parser.had_error(), "{}", source);
+        let mut ast = parser.into_ast();
+        let errors = optimize(&mut ast, &program);
+        let errors = errors.iter().map(|error| error.to_string()).collect();
+        (AstPrinter::new().print_program(&ast, &program), errors)
+    }
+
+    #[test]
+    fn fold_test() {
+        let inputs = [
+            ("print (1 + 3) * 2;", "(print 8)"),
+            ("print -(2 - 5) >= 3 == !null;", "(print true)"),
+            ("print \"a\" + \"b\" != \"ab\";", "(print false)"),
+            ("let x = (y);", "(let x y)"),
+            // 运行时才报错的不折叠
+            ("print 1 + \"a\"; print -true;", "(print (+ 1 \"a\"))\n(print (- true))"),
+            ("print null or x; print 0 and f(); print false and f();", "(print x)\n(print (call f))\n(print false)"),
+            // 函数体里也会化简
+            ("fn f(a) { return [a * (2 - 1), a[0 + 1]]; }", "(fn f (a) (return (list (* a 1) (index a 1))))"),
+        ];
+        for (source, expect) in inputs {
+            assert_eq!(optimized(source), (expect.to_string(), vec![]), "{}", source);
+        }
+    }
+
+    #[test]
+    fn identity_test() {
+        let inputs = [
+            ("print (x - 1) * 1 - 0;", "(print (- x 1))"),
+            ("print 1 * -x; print x / 1 * 1;", "(print (- x))\n(print (/ x 1))"),
+            // x可能是字符串，`x * 1`在运行时报错
+            ("print x * 1;", "(print (* x 1))"),
+            // -x可能是-0，`-x + 0`是0
+            ("print -x + 0; print 0 + -x;", "(print (+ (- x) 0))\n(print (+ 0 (- x)))"),
+            ("print !!(a < b); print !!a;", "(print (< a b))\n(print (! (! a)))"),
+        ];
+        for (source, expect) in inputs {
+            assert_eq!(optimized(source).0, expect, "{}", source);
+        }
+    }
+
+    #[test]
+    fn error_test() {
+        let (program, errors) = optimized("print 1 / 0;\nprint x / (2 - 2);\nx /= 0;");
+        assert_eq!(program, "(print (/ 1 0))\n(print (/ x 0))\n(/= x 0)");
+        assert_eq!(
+            errors,
+            [
+                "line 1: at token \"/\", Division by zero.",
+                "line 2: at token \"/\", Division by zero.",
+                "line 3: at token \"/=\", Division by zero.",
+            ]
+        );
+    }
+}
@@ -1,11 +1,38 @@
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::StmtId;
 use super::expr::Expr;
+use super::expr::Assign;
 use super::expr::Binary;
+use super::expr::Call;
+use super::expr::CompoundAssign;
+use super::expr::Function;
+use super::expr::Index;
+use super::expr::List;
 use super::expr::Literal;
+use super::expr::LiteralValue;
+use super::expr::Logical;
 use super::expr::Unary;
 use super::expr::Grouping;
+use super::expr::Interpolation;
+use super::expr::TemplatePart;
+use super::expr::TypeAnnotation;
+use super::expr::Variable;
+use super::stmt::Stmt;
+use super::stmt::Block;
+use super::stmt::Break;
+use super::stmt::Expression;
+use super::stmt::For;
+use super::stmt::If;
+use super::stmt::Let;
+use super::stmt::Print;
+use super::stmt::Return;
+use super::stmt::While;
 use super::lexer::Lexer;
+use super::token::Span;
 use super::token::TokenType;
 use super::token::Token;
+use std::iter::Filter;
 use std::iter::Peekable;
 
 macro_rules! error {
@@ -16,9 +43,21 @@ macro_rules! error {
 
 
 
+// 文档注释只对文档工具有意义，parser直接跳过
+type Tokens<'a> = Filter<Lexer<'a>, fn(&Token) -> bool>;
+
+fn is_not_doc_comment(token: &Token) -> bool {
+    token.token_type != TokenType::DOC_COMMENT
+}
+
 pub struct Parser<'a>{
-    lexer: Peekable<Lexer<'a>>,
-    current_token: Token
+    lexer: Peekable<Tokens<'a>>,
+    current_token: Token,
+    // 出现过语法错误，错误信息已经打印出来了
+    had_error: bool,
+    // 出错之后到同步之前不再报告新的错误
+    panic_mode: bool,
+    ast: Ast,
 }
 
 impl<'a> Parser<'a> {
@@ -26,26 +65,94 @@ impl<'a> Parser<'a> {
     // 如果直接传Lexer会有生命周期问题
     pub fn new(lexer: &'a str) -> Self {
         Self {
-            lexer: Lexer::new(lexer).peekable(),
+            lexer: Lexer::new(lexer)
+                .filter(is_not_doc_comment as fn(&Token) -> bool)
+                .peekable(),
             // 初始化current_token为ILLEGAL，这样在parse的时候就不用检查lexer是否为空了
             current_token: Token::new(TokenType::ILLEGAL, "".to_string(), 0),
+            had_error: false,
+            panic_mode: false,
+            ast: Ast::new(),
         }
     }
     
-    pub fn parse(&mut self) -> Box<dyn Expr<String>> {
-        if !self.lexer.peek().is_none() {
+    // 只解析一个表达式，节点都在self.ast里
+    pub fn parse(&mut self) -> ExprId {
+        if self.lexer.peek().is_some_and(|token| token.token_type != TokenType::EOF) {
             return self.expression()
         }
-        Box::new(Literal::new(Box::new("ILLEGAL".to_string())))
+        self.illegal(0)
+    }
+
+    // 解析整个程序，也就是一串语句
+    pub fn parse_program(&mut self) -> Vec<StmtId> {
+        let mut stmts = vec![];
+        while !self.check(TokenType::EOF) {
+            stmts.push(self.declaration());
+        }
+        stmts
+    }
+
+    pub fn had_error(&self) -> bool {
+        self.had_error
+    }
+
+    pub fn ast(&self) -> &Ast {
+        &self.ast
+    }
+
+    pub fn into_ast(self) -> Ast {
+        self.ast
+    }
+
+    // 同一个语句里只报告第一个错误，后面的错误多半是它引起的
+    fn report(&mut self, message: String) {
+        self.had_error = true;
+        if !self.panic_mode {
+            self.panic_mode = true;
+            error!("{}", message);
+        }
+    }
+
+    // 下一个token的开始位置，也就是接下来要解析的节点的开始位置
+    fn next_start(&mut self) -> usize {
+        match self.lexer.peek() {
+            Some(token) => token.span.start,
+            None => self.current_token.span.end,
+        }
+    }
+
+    // 节点从start开始，到最后一个消费的token为止
+    fn add_expr(&mut self, expr: Expr, start: usize) -> ExprId {
+        let end = self.current_token.span.end.max(start);
+        self.ast.add_expr(expr, Span::new(start, end))
+    }
+
+    fn add_stmt(&mut self, stmt: Stmt, start: usize) -> StmtId {
+        let end = self.current_token.span.end.max(start);
+        self.ast.add_stmt(stmt, Span::new(start, end))
+    }
+
+    // 出错之后用来占位的节点
+    fn illegal(&mut self, start: usize) -> ExprId {
+        self.add_expr(Expr::Literal(Literal::new(LiteralValue::String("ILLEGAL".to_string()))), start)
+    }
+
+    // 下一个token是否是token_type，不消费它
+    // lexer最后总会返回EOF，peek为None的时候也当作EOF
+    fn check(&mut self, token_type: TokenType) -> bool {
+        match self.lexer.peek() {
+            Some(token) => token.token_type == token_type,
+            None => token_type == TokenType::EOF,
+        }
     }
-    
 
     fn match_token(&mut self, token_type: TokenType) -> bool {
         if let Some(token) = self.lexer.peek() {
             if token.token_type == token_type {
                 // 检查下一个token是否是token_type，如果是的话，就消费它并返回true，否则返回false
-                self.current_token = token.clone();
-                self.lexer.next();
+                // 直接把token移到current_token，不用clone
+                self.current_token = self.lexer.next().unwrap();
                 true
             } else {
                 false
@@ -55,29 +162,30 @@ impl<'a> Parser<'a> {
         }
     }
     
-    // 消费当前token，如果当前token不是token_type，就panic
-    fn consume(&mut self, token_type: TokenType, message: &str) {
+    // 消费当前token，如果当前token不是token_type，就报错并返回false
+    fn consume(&mut self, token_type: TokenType, message: &str) -> bool {
         if let Some(token) = self.lexer.peek() {
             if token.token_type == token_type {
-                self.current_token = token.clone();
-                self.lexer.next();
-                // 这里不能用next
-                return;
+                self.current_token = self.lexer.next().unwrap();
+                true
             } else {
-                error!("line {}: at token \"{}\", {}", token.line, token.lexeme, message);
+                let message = format!("line {}: at token \"{}\", {}", token.line, token.lexeme, message);
+                self.report(message);
+                false
             }
         } else {
-            error!("Token Expected!");
+            self.report("Token Expected!".to_string());
+            false
         }
     }
     
     // 当发生错误的时候，为了避免导致后面的token也出现语法错误，需要同步到下一个语句的开始
     fn synchronize(&mut self) {
-        self.lexer.next();
-        
+        self.panic_mode = false;
         while let Some(token) = self.lexer.peek() {
-            // 跳过分号前的所有token 
+            // 跳过分号和它前面的所有token
             if token.token_type == TokenType::SEMICOLON {
+                self.lexer.next();
                 return;
             }
             
@@ -90,7 +198,8 @@ impl<'a> Parser<'a> {
                 | TokenType::IF 
                 | TokenType::WHILE 
                 | TokenType::PRINT 
-                | TokenType::RETURN => {
+                | TokenType::RETURN
+                | TokenType::EOF => {
                     return;
                 }
                 _ => {}
@@ -100,98 +209,516 @@ impl<'a> Parser<'a> {
         } 
     }
 
-    fn expression(&mut self) -> Box<dyn Expr<String>> {
-        self.equality()
+    // 语句出错的时候同步到下一个语句
+    fn declaration(&mut self) -> StmtId {
+        let start = self.next_start();
+        let stmt = if self.match_token(TokenType::LET) {
+            self.let_declaration(start)
+        } else if self.match_token(TokenType::FUNCTION) {
+            self.function_declaration(start)
+        } else {
+            self.statement(start)
+        };
+        if self.panic_mode {
+            self.synchronize();
+        }
+        stmt
+    }
+
+    fn let_declaration(&mut self, start: usize) -> StmtId {
+        self.consume(TokenType::IDENT, "Expect variable name.");
+        let name = self.current_token.clone();
+        let annotation = self.annotation();
+        let initializer = if self.match_token(TokenType::ASSIGN) {
+            Some(self.expression())
+        } else {
+            None
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after variable declaration.");
+        self.add_stmt(Stmt::Let(Let { name, annotation, initializer }), start)
+    }
+
+    // `fn`已经被消费
+    fn function_declaration(&mut self, start: usize) -> StmtId {
+        self.consume(TokenType::IDENT, "Expect function name.");
+        let name = self.current_token.clone();
+        let function = self.function(Some(name));
+        self.add_stmt(Stmt::Function(function), start)
+    }
+
+    // 读取参数列表和函数体
+    fn function(&mut self, name: Option<Token>) -> Box<Function> {
+        self.consume(TokenType::LPAREN, "Expect '(' after fn.");
+        let mut params = vec![];
+        let mut param_annotations = vec![];
+        if !self.check(TokenType::RPAREN) {
+            loop {
+                if self.consume(TokenType::IDENT, "Expect parameter name.") {
+                    params.push(self.current_token.clone());
+                    param_annotations.push(self.annotation());
+                }
+                if !self.match_token(TokenType::COMMA) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RPAREN, "Expect ')' after parameters.");
+        let return_annotation = self.annotation();
+        self.consume(TokenType::LBRACE, "Expect '{' before function body.");
+        Box::new(Function::new(name, params, param_annotations, return_annotation, self.block()))
+    }
+
+    // 可选的类型标注`: type`
+    fn annotation(&mut self) -> Option<TypeAnnotation> {
+        if self.match_token(TokenType::COLON) {
+            Some(self.type_annotation())
+        } else {
+            None
+        }
+    }
+
+    // type := 'fn' '(' [type (',' type)*] ')' ':' type
+    //       | name ['<' type (',' type)* '>']
+    fn type_annotation(&mut self) -> TypeAnnotation {
+        if self.match_token(TokenType::FUNCTION) {
+            self.consume(TokenType::LPAREN, "Expect '(' after fn.");
+            let mut params = vec![];
+            if !self.check(TokenType::RPAREN) {
+                loop {
+                    params.push(self.type_annotation());
+                    if !self.match_token(TokenType::COMMA) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RPAREN, "Expect ')' after parameter types.");
+            self.consume(TokenType::COLON, "Expect ':' before return type.");
+            return TypeAnnotation::Function(params, Box::new(self.type_annotation()));
+        }
+        // null既是关键字也是类型名
+        if !self.match_token(TokenType::NULL) {
+            self.consume(TokenType::IDENT, "Expect type name.");
+        }
+        let name = self.current_token.clone();
+        if !self.match_token(TokenType::LT) {
+            return TypeAnnotation::Name(name);
+        }
+        let mut arguments = vec![];
+        loop {
+            arguments.push(self.type_annotation());
+            if !self.match_token(TokenType::COMMA) {
+                break;
+            }
+        }
+        self.close_type_arguments();
+        TypeAnnotation::Generic(name, arguments)
+    }
+
+    // `let xs: list<number>= [];`里lexer扫描出来的是`>=`，拆成`>`和留给后面的`=`
+    fn close_type_arguments(&mut self) {
+        let Some(token) = self.lexer.peek_mut().filter(|token| token.token_type == TokenType::GE) else {
+            self.consume(TokenType::GT, "Expect '>' after type arguments.");
+            return;
+        };
+        let mut close = token.clone();
+        close.token_type = TokenType::GT;
+        close.lexeme = ">".to_string();
+        close.span = Span::new(token.span.start, token.span.start + 1);
+        token.token_type = TokenType::ASSIGN;
+        token.lexeme = "=".to_string();
+        token.column += 1;
+        token.span.start += 1;
+        self.current_token = close;
+    }
+
+    fn statement(&mut self, start: usize) -> StmtId {
+        let stmt = if self.match_token(TokenType::PRINT) {
+            let expr = self.expression();
+            self.consume(TokenType::SEMICOLON, "Expect ';' after value.");
+            Stmt::Print(Print { expr })
+        } else if self.match_token(TokenType::LBRACE) {
+            Stmt::Block(Block { stmts: self.block() })
+        } else if self.match_token(TokenType::IF) {
+            return self.if_statement(start);
+        } else if self.match_token(TokenType::FOR) {
+            return self.for_statement(start);
+        } else if self.match_token(TokenType::WHILE) {
+            let condition = self.expression();
+            let body = self.block_statement();
+            Stmt::While(While { condition, body })
+        } else if self.match_token(TokenType::RETURN) {
+            let keyword = self.current_token.clone();
+            let value = if self.check(TokenType::SEMICOLON) {
+                None
+            } else {
+                Some(self.expression())
+            };
+            self.consume(TokenType::SEMICOLON, "Expect ';' after return value.");
+            Stmt::Return(Return { keyword, value })
+        } else if self.match_token(TokenType::BREAK) {
+            let keyword = self.current_token.clone();
+            self.consume(TokenType::SEMICOLON, "Expect ';' after break.");
+            Stmt::Break(Break { keyword })
+        } else {
+            let expr = self.expression();
+            // 最后一个表达式语句可以省略分号，方便在REPL里直接输入表达式
+            if !self.check(TokenType::EOF) {
+                self.consume(TokenType::SEMICOLON, "Expect ';' after expression.");
+            }
+            Stmt::Expression(Expression { expr })
+        };
+        self.add_stmt(stmt, start)
+    }
+
+    // `{`已经被消费，读到`}`为止
+    fn block(&mut self) -> Vec<StmtId> {
+        let mut stmts = vec![];
+        while !self.check(TokenType::RBRACE) && !self.check(TokenType::EOF) {
+            stmts.push(self.declaration());
+        }
+        self.consume(TokenType::RBRACE, "Expect '}' after block.");
+        stmts
+    }
+
+    // 条件不需要括号，分支必须是块：if x > 1 { ... } else if ... { ... } else { ... }
+    fn if_statement(&mut self, start: usize) -> StmtId {
+        let condition = self.expression();
+        let then_branch = self.block_statement();
+        let else_branch = if self.match_token(TokenType::ELSE) {
+            if self.match_token(TokenType::IF) {
+                let start = self.current_token.span.start;
+                Some(self.if_statement(start))
+            } else {
+                Some(self.block_statement())
+            }
+        } else {
+            None
+        };
+        self.add_stmt(Stmt::If(If { condition, then_branch, else_branch }), start)
+    }
+
+    // `for`已经被消费，和while一样没有括号，三个部分用分号隔开
+    fn for_statement(&mut self, start: usize) -> StmtId {
+        let initializer = if self.match_token(TokenType::SEMICOLON) {
+            None
+        } else if self.match_token(TokenType::LET) {
+            let start = self.current_token.span.start;
+            Some(self.let_declaration(start))
+        } else {
+            let start = self.next_start();
+            let expr = self.expression();
+            self.consume(TokenType::SEMICOLON, "Expect ';' after loop initializer.");
+            Some(self.add_stmt(Stmt::Expression(Expression { expr }), start))
+        };
+        let condition = if self.check(TokenType::SEMICOLON) {
+            None
+        } else {
+            Some(self.expression())
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.");
+        let increment = if self.check(TokenType::LBRACE) {
+            None
+        } else {
+            Some(self.expression())
+        };
+        let body = self.block_statement();
+        self.add_stmt(Stmt::For(For { initializer, condition, increment, body }), start)
+    }
+
+    fn block_statement(&mut self) -> StmtId {
+        let start = self.next_start();
+        self.consume(TokenType::LBRACE, "Expect '{' before block.");
+        let stmts = self.block();
+        self.add_stmt(Stmt::Block(Block { stmts }), start)
+    }
+
+    fn expression(&mut self) -> ExprId {
+        self.assignment()
+    }
+
+    // 赋值是右结合的，先当作普通表达式解析左边，遇到`=`再检查左边是不是变量
+    fn assignment(&mut self) -> ExprId {
+        let start = self.next_start();
+        let expr = self.or();
+
+        if self.match_token(TokenType::ASSIGN) {
+            let equals = self.current_token.clone();
+            let value = self.assignment();
+            if let Expr::Variable(variable) = &self.ast[expr] {
+                let name = variable.name.clone();
+                return self.add_expr(Expr::Assign(Assign::new(name, value)), start);
+            }
+            self.report(format!("line {}: at token \"{}\", Invalid assignment target.", equals.line, equals.lexeme));
+        } else if self.match_token(TokenType::PLUS_ASSIGN)
+            || self.match_token(TokenType::MINUS_ASSIGN)
+            || self.match_token(TokenType::ASTERISK_ASSIGN)
+            || self.match_token(TokenType::SLASH_ASSIGN)
+        {
+            let op = self.current_token.clone();
+            let value = self.assignment();
+            if let Expr::Variable(variable) = &self.ast[expr] {
+                let name = variable.name.clone();
+                return self.add_expr(Expr::CompoundAssign(CompoundAssign::new(name, op, value)), start);
+            }
+            self.report(format!("line {}: at token \"{}\", Invalid assignment target.", op.line, op.lexeme));
+        }
+
+        expr
+    }
+
+    fn or(&mut self) -> ExprId {
+        let start = self.next_start();
+        let mut expr = self.and();
+
+        while self.match_token(TokenType::OR) {
+            let op = self.current_token.clone();
+            let right = self.and();
+            expr = self.add_expr(Expr::Logical(Logical::new(expr, op, right)), start);
+        }
+
+        expr
+    }
+
+    fn and(&mut self) -> ExprId {
+        let start = self.next_start();
+        let mut expr = self.equality();
+
+        while self.match_token(TokenType::AND) {
+            let op = self.current_token.clone();
+            let right = self.equality();
+            expr = self.add_expr(Expr::Logical(Logical::new(expr, op, right)), start);
+        }
+
+        expr
     }
     
-    fn equality(&mut self) -> Box<dyn Expr<String>> {
+    fn equality(&mut self) -> ExprId {
+        let start = self.next_start();
         let mut expr = self.comparison();
         
         while self.match_token(TokenType::NOT_EQ) || self.match_token(TokenType::EQ) {
             // 由于comparison中会顶替掉current_token， 需要先保存当前token 
             let op = self.current_token.clone();
             let right = self.comparison();
-            expr = Box::new(Binary::new(expr, op.clone(), right));
+            expr = self.add_expr(Expr::Binary(Binary::new(expr, op, right)), start);
         }
 
         expr
     }
     
-    fn comparison(&mut self) -> Box<dyn Expr<String>> {
+    fn comparison(&mut self) -> ExprId {
+        let start = self.next_start();
         let mut expr = self.addition();
         
         while self.match_token(TokenType::GT) || self.match_token(TokenType::LT)
             || self.match_token(TokenType::GE) || self.match_token(TokenType::LE) {
             let op = self.current_token.clone();
             let right = self.addition();
-            expr = Box::new(Binary::new(expr, op.clone(), right));
+            expr = self.add_expr(Expr::Binary(Binary::new(expr, op, right)), start);
         }
 
         expr        
     }
     
-    fn addition(&mut self) -> Box<dyn Expr<String>> {
+    fn addition(&mut self) -> ExprId {
+        let start = self.next_start();
         let mut expr = self.multiplication();
         
         while self.match_token(TokenType::MINUS) || self.match_token(TokenType::PLUS) {
             let op = self.current_token.clone();
             let right = self.multiplication();
-            expr = Box::new(Binary::new(expr, op.clone(), right));
+            expr = self.add_expr(Expr::Binary(Binary::new(expr, op, right)), start);
         }
 
         expr
     }
     
-    fn multiplication(&mut self) -> Box<dyn Expr<String>> {
+    fn multiplication(&mut self) -> ExprId {
+        let start = self.next_start();
         let mut expr = self.unary();
 
         while self.match_token(TokenType::ASTERISK) || self.match_token(TokenType::SLASH) {
             let op = self.current_token.clone();
             let right = self.unary();
-            expr = Box::new(Binary::new(expr, op.clone(), right));
+            expr = self.add_expr(Expr::Binary(Binary::new(expr, op, right)), start);
         }
 
         expr
     }
     
-    fn unary(&mut self) -> Box<dyn Expr<String>> {
+    fn unary(&mut self) -> ExprId {
+        let start = self.next_start();
         if self.match_token(TokenType::MINUS) || self.match_token(TokenType::EXCLAMATION) {
             let op = self.current_token.clone();
             let right = self.unary();
-            Box::new(Unary::new(op.clone(), right))
+            self.add_expr(Expr::Unary(Unary::new(op, right)), start)
         } else {
-            self.primary()
+            self.call()
         }
     }
+
+    // 调用和下标可以连在一起：f(1)[0](2)
+    fn call(&mut self) -> ExprId {
+        let start = self.next_start();
+        let mut expr = self.primary();
+
+        loop {
+            if self.match_token(TokenType::LPAREN) {
+                let arguments = self.arguments(TokenType::RPAREN);
+                self.consume(TokenType::RPAREN, "Expect ')' after arguments.");
+                let paren = self.current_token.clone();
+                expr = self.add_expr(Expr::Call(Call::new(expr, paren, arguments)), start);
+            } else if self.match_token(TokenType::LBRACKET) {
+                let bracket = self.current_token.clone();
+                let index = self.expression();
+                self.consume(TokenType::RBRACKET, "Expect ']' after index.");
+                expr = self.add_expr(Expr::Index(Index::new(expr, bracket, index)), start);
+            } else {
+                break;
+            }
+        }
+
+        expr
+    }
+
+    // 逗号分隔的表达式，读到end为止，不消费end
+    fn arguments(&mut self, end: TokenType) -> Vec<ExprId> {
+        let mut arguments = vec![];
+        if !self.check(end) {
+            loop {
+                arguments.push(self.expression());
+                if !self.match_token(TokenType::COMMA) {
+                    break;
+                }
+            }
+        }
+        arguments
+    }
     
-    fn primary(&mut self) -> Box<dyn Expr<String>> {
-        if self.match_token(TokenType::FALSE)
-        || self.match_token(TokenType::TRUE)
-        || self.match_token(TokenType::NULL)
-        || self.match_token(TokenType::NUMBER)
-        || self.match_token(TokenType::STRING) {
-            Box::new(Literal::new(Box::new(self.current_token.lexeme.clone())))
+    fn primary(&mut self) -> ExprId {
+        let start = self.next_start();
+        let expr = if self.match_token(TokenType::FALSE) {
+            Expr::Literal(Literal::new(LiteralValue::Bool(false)))
+        } else if self.match_token(TokenType::TRUE) {
+            Expr::Literal(Literal::new(LiteralValue::Bool(true)))
+        } else if self.match_token(TokenType::NULL) {
+            Expr::Literal(Literal::new(LiteralValue::Null))
+        } else if self.match_token(TokenType::NUMBER) {
+            match self.current_token.lexeme.parse::<f64>() {
+                Ok(number) => Expr::Literal(Literal::new(LiteralValue::Number(number))),
+                Err(_) => {
+                    self.report(format!("line {}: invalid number \"{}\"", self.current_token.line, self.current_token.lexeme));
+                    return self.illegal(start);
+                }
+            }
+        } else if self.match_token(TokenType::STRING) {
+            Expr::Literal(Literal::new(LiteralValue::String(self.current_token.lexeme.clone())))
+        } else if self.match_token(TokenType::TEMPLATE_HEAD) {
+            self.interpolation()
+        } else if self.match_token(TokenType::IDENT) {
+            Expr::Variable(Variable::new(self.current_token.clone()))
+        } else if self.match_token(TokenType::FUNCTION) {
+            Expr::Function(self.function(None))
+        } else if self.match_token(TokenType::LBRACKET) {
+            let bracket = self.current_token.clone();
+            let elements = self.arguments(TokenType::RBRACKET);
+            self.consume(TokenType::RBRACKET, "Expect ']' after list elements.");
+            Expr::List(List::new(bracket, elements))
         } else if self.match_token(TokenType::LPAREN) {
             let expr = self.expression();
             // 检查是否有右括号，并提供报错信息
             self.consume(TokenType::RPAREN, "Expect ')' after expression.");
-            Box::new(Grouping::new(expr))
+            Expr::Grouping(Grouping::new(expr))
         } else {
-            error!("ILLEGAL TOKEN: {}", self.lexer.peek().unwrap().lexeme);
-            self.lexer.next();
-            Box::new(Literal::new(Box::new("ILLEGAL")))
+            let message = match self.lexer.peek() {
+                Some(token) if token.token_type != TokenType::EOF => {
+                    let message = format!("line {}: ILLEGAL TOKEN: {}", token.line, token.lexeme);
+                    self.current_token = self.lexer.next().unwrap();
+                    message
+                }
+                _ => "Unexpected end of input.".to_string(),
+            };
+            self.report(message);
+            return self.illegal(start);
+        };
+        self.add_expr(expr, start)
+    }
+
+    // TEMPLATE_HEAD已经被消费，依次读取 表达式 (TEMPLATE_MIDDLE 表达式)* TEMPLATE_TAIL
+    // 空的字符串片段不放进语法树
+    fn interpolation(&mut self) -> Expr {
+        let mut parts = vec![];
+        loop {
+            if !self.current_token.lexeme.is_empty() {
+                parts.push(TemplatePart::Chunk(self.current_token.lexeme.clone()));
+            }
+            parts.push(TemplatePart::Expr(self.expression()));
+            if self.match_token(TokenType::TEMPLATE_MIDDLE) {
+                continue;
+            }
+            if self.consume(TokenType::TEMPLATE_TAIL, "Expect '}' after interpolated expression.")
+                && !self.current_token.lexeme.is_empty() {
+                parts.push(TemplatePart::Chunk(self.current_token.lexeme.clone()));
+            }
+            break;
         }
+        Expr::Interpolation(Interpolation::new(parts))
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use std::iter::zip;
 
     use crate::compiler_core::visitors::AstPrinter;
 
     use super::*;
 
+    // 下面测试里能正确解析的输入，其他pass用它们做往返测试
+    pub(crate) const CORPUS: &[&str] = &[
+        "1 == 2",
+        "1 != 2",
+        "1 > 2",
+        "1 >= 2",
+        "1 < 2",
+        "1 <= 2",
+        "1 + 2",
+        "1 - 2",
+        "1 * 2",
+        "1 / 2",
+        "-5",
+        "!true",
+        "true",
+        "false",
+        "null",
+        "3.14",
+        "\"hello world\"",
+        "(1 + 3) * 2",
+        "((1 + 1))",
+        "/// doc\n1 /* one /* two */ */ + // line\n 2",
+        "\"hello ${\"world\"}\"",
+        "\"${1 + 2} = ${3}!\"",
+        "\"outer ${\"inner ${-1}\"}\"",
+        "let x = 1; let y; print x + y;",
+        "{ let a = 1; { a = a and b or c; } }",
+        "if x > 1 { print 1; } else if x { print 2; } else { print 3; }",
+        "while true { break; }",
+        "fn add(a, b) { return a + b; } add(1, 2)(3);",
+        "let f = fn() { return; };",
+        "let xs = [1, [2, 3], []]; print xs[1][0] + f(xs)[0];",
+        "let n: number = 1; fn map(xs: list<a>, f: fn(a): b): list<b> { return []; } let m: map<string, list<null>>;",
+        "for let i = 0; i < 3; i += 1 { x *= (2); } for ;; { break; } for i = 0; ; { i /= 2; }",
+    ];
+
+    #[test]
+    fn corpus_test() {
+        for input in CORPUS {
+            let mut parser = Parser::new(input);
+            parser.parse_program();
+            assert!(!parser.had_error(), "{}", input);
+        }
+    }
+
     #[test]
     fn equality_test() {
         let mut printer = AstPrinter::new();
@@ -206,7 +733,7 @@ mod tests {
         for (input, expect) in zip(inputs.iter(), expects.iter()) {
             let mut parser = Parser::new(input);
             let expr = parser.parse();
-            let result = expr.accept(&mut printer);
+            let result = printer.print_expr(parser.ast(), expr);
             assert_eq!(result, expect.to_string());
         } 
     }
@@ -228,7 +755,7 @@ mod tests {
         for (input, expect) in zip(inputs.iter(), expects.iter()) {
             let mut parser = Parser::new(input);
             let expr = parser.parse();
-            let result = expr.accept(&mut printer);
+            let result = printer.print_expr(parser.ast(), expr);
             assert_eq!(result, expect.to_string());
         } 
     }
@@ -246,7 +773,7 @@ mod tests {
         for (input, expect) in zip(inputs.iter(), expects.iter()) {
             let mut parser = Parser::new(input);
             let expr = parser.parse();
-            let result = expr.accept(&mut printer);
+            let result = printer.print_expr(parser.ast(), expr);
             assert_eq!(result, expect.to_string());
         } 
     }
@@ -264,7 +791,7 @@ mod tests {
         for (input, expect) in zip(inputs.iter(), expects.iter()) {
             let mut parser = Parser::new(input);
             let expr = parser.parse();
-            let result = expr.accept(&mut printer);
+            let result = printer.print_expr(parser.ast(), expr);
             assert_eq!(result, expect.to_string());
         } 
     }
@@ -277,12 +804,12 @@ mod tests {
         ];
         let expects = [
             "(- 5)",
-            "(! TRUE)",
+            "(! true)",
         ];
         for (input, expect) in zip(inputs.iter(), expects.iter()) {
             let mut parser = Parser::new(input);
             let expr = parser.parse();
-            let result = expr.accept(&mut printer);
+            let result = printer.print_expr(parser.ast(), expr);
             assert_eq!(result, expect.to_string());
         } 
     }
@@ -299,19 +826,114 @@ mod tests {
             "((1 + 1))"
         ];
         let expects = [
-            "TRUE",
-            "FALSE",
-            "NULL",
+            "true",
+            "false",
+            "null",
             "3.14",
-            "hello world",
+            "\"hello world\"",
             "(* (group (+ 1 3)) 2)",
             "(group (group (+ 1 1)))",
         ];
         for (input, expect) in zip(inputs.iter(), expects.iter()) {
             let mut parser = Parser::new(input);
             let expr = parser.parse();
-            let result = expr.accept(&mut printer);
+            let result = printer.print_expr(parser.ast(), expr);
             assert_eq!(result, expect.to_string());
         } 
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn comments_are_skipped() {
+        let mut printer = AstPrinter::new();
+        let mut parser = Parser::new("/// doc\n1 /* one /* two */ */ + // line\n 2");
+        let expr = parser.parse();
+        assert_eq!(printer.print_expr(parser.ast(), expr), "(+ 1 2)");
+    }
+
+    #[test]
+    fn interpolation_test() {
+        let mut printer = AstPrinter::new();
+        let inputs = [
+            "\"hello ${\"world\"}\"",
+            "\"${1 + 2} = ${3}!\"",
+            "\"outer ${\"inner ${-1}\"}\"",
+        ];
+        let expects = [
+            "(interp \"hello \" ${\"world\"})",
+            "(interp ${(+ 1 2)} \" = \" ${3} \"!\")",
+            "(interp \"outer \" ${(interp \"inner \" ${(- 1)})})",
+        ];
+        for (input, expect) in zip(inputs.iter(), expects.iter()) {
+            let mut parser = Parser::new(input);
+            let expr = parser.parse();
+            let result = printer.print_expr(parser.ast(), expr);
+            assert_eq!(result, expect.to_string());
+        }
+    }
+
+    #[test]
+    fn statement_test() {
+        let mut printer = AstPrinter::new();
+        let inputs = [
+            "let x = 1; let y; print x + y;",
+            "{ let a = 1; { a = a and b or c; } }",
+            "if x > 1 { print 1; } else if x { print 2; } else { print 3; }",
+            "while true { break; }",
+            "fn add(a, b) { return a + b; } add(1, 2)(3);",
+            "let f = fn() { return; };",
+            "let xs = [1, [2, 3], []]; print xs[1][0] + f(xs)[0];",
+            "let n: number = 1; fn map(xs: list<a>, f: fn(a): b): list<b> { return []; } let m: map<string, list<null>>;",
+            "for let i = 0; i < 3; i += 1 { x *= (2); } for ;; { break; } for i = 0; ; { i /= 2; }",
+            // 类型参数后面紧跟着`=`的时候lexer扫描出来的是`>=`
+            "let xs: list<number>= []; let m: map<string, list<a>>= m;",
+        ];
+        let expects = [
+            "(let x 1)\n(let y)\n(print (+ x y))",
+            "(block (let a 1) (block (= a (or (and a b) c))))",
+            "(if (> x 1) (block (print 1)) (if x (block (print 2)) (block (print 3))))",
+            "(while true (block (break)))",
+            "(fn add (a b) (return (+ a b)))\n(call (call add 1 2) 3)",
+            "(let f (fn () (return)))",
+            "(let xs (list 1 (list 2 3) (list)))\n(print (+ (index (index xs 1) 0) (index (call f xs) 0)))",
+            "(let n : number 1)\n(fn map (xs : (list a) f : (fn (a) b)) : (list b) (return (list)))\n(let m : (map string (list null)))",
+            "(for (let i 0) (< i 3) (+= i 1) (block (*= x (group 2))))\n(for _ _ _ (block (break)))\n(for (= i 0) _ _ (block (/= i 2)))",
+            "(let xs : (list number) (list))\n(let m : (map string (list a)) m)",
+        ];
+        for (input, expect) in zip(inputs.iter(), expects.iter()) {
+            let mut parser = Parser::new(input);
+            let program = parser.parse_program();
+            assert!(!parser.had_error(), "{}", input);
+            assert_eq!(printer.print_program(parser.ast(), &program), expect.to_string());
+        }
+    }
+
+    #[test]
+    fn statement_error_test() {
+        let inputs = [
+            "let = 1; print 2;",
+            "1 = 2;",
+            "print 1 print 2;",
+            "{ print 1;",
+            "fn (a) { }",
+            "[1, 2",
+            "xs[1;",
+            "let x: = 1;",
+            "let xs: list<number = [];",
+            "fn f(a: fn(number)) { }",
+            "for let i = 0 { }",
+            "for i < 3; { }",
+            "1 += 2;",
+        ];
+        for input in inputs {
+            let mut parser = Parser::new(input);
+            parser.parse_program();
+            assert!(parser.had_error(), "{}", input);
+        }
+
+        // 出错之后同步到下一个语句，后面的语句照常解析
+        let mut parser = Parser::new("let x = ); print 2;");
+        let program = parser.parse_program();
+        assert!(parser.had_error());
+        assert_eq!(AstPrinter::new().print_program(parser.ast(), &program[1..]), "(print 2)");
+    }
+}
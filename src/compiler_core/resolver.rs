@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::SideTable;
+use super::ast::StmtId;
+use super::expr::Assign;
+use super::expr::CompoundAssign;
+use super::expr::Expr;
+use super::expr::ExprWalker;
+use super::expr::Function;
+use super::expr::Variable;
+use super::stmt::Block;
+use super::stmt::Break;
+use super::stmt::For;
+use super::stmt::Let;
+use super::stmt::Return;
+use super::stmt::StmtWalker;
+use super::stmt::While;
+use super::token::Span;
+use super::token::Token;
+use super::walk::walk_children;
+use super::walk::walk_program;
+
+// 静态的作用域分析，解析之后、执行之前运行一遍：
+// - 块和函数各自是一层作用域，函数的参数和函数体在同一层，和解释器里的Environment一一对应
+// - 每个Variable和Assign节点绑定到声明它的作用域，记录在bindings里，解释器按绑定的层数去找变量
+// - 顶层的变量是全局变量，不检查重复声明(REPL里可以重新定义)，也不检查是否声明过
+// - return只能在函数里，break只能在循环里，函数体里的break不能跳出函数外面的循环
+// - for和复合赋值直接分析，不需要先desugar。for的init在外面单独一层作用域，和desugar之后一样
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Binding {
+    // depth是往外数的层数，0就是当前作用域；declaration是声明时名字的位置
+    Local { depth: usize, declaration: Span },
+    // 运行时到全局环境里去找
+    Global,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ResolveError {
+    pub token: Token,
+    pub message: String,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: at token \"{}\", {}", self.token.line, self.token.lexeme, self.message)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Resolution {
+    pub bindings: SideTable<Expr, Binding>,
+    pub errors: Vec<ResolveError>,
+}
+
+pub fn resolve(ast: &Ast, stmts: &[StmtId]) -> Resolution {
+    let mut resolver = Resolver { scopes: vec![], functions: 0, loops: 0, resolution: Resolution::default() };
+    walk_program(&mut resolver, ast, stmts);
+    resolver.resolution
+}
+
+// 在顶层单独分析一个表达式
+pub fn resolve_expr(ast: &Ast, expr: ExprId) -> Resolution {
+    let mut resolver = Resolver { scopes: vec![], functions: 0, loops: 0, resolution: Resolution::default() };
+    resolver.visit_expr(ast, expr);
+    resolver.resolution
+}
+
+// 作用域里的一个局部变量
+struct Local {
+    // 初始化表达式求值完之后才算定义好
+    defined: bool,
+    declaration: Span,
+}
+
+struct Resolver {
+    // 不包括全局作用域
+    scopes: Vec<HashMap<String, Local>>,
+    // 正在分析的函数有几层，return只能出现在函数里
+    functions: usize,
+    // 当前函数里正在分析的循环有几层，break只能出现在循环里
+    loops: usize,
+    resolution: Resolution,
+}
+
+impl Resolver {
+    fn error(&mut self, token: &Token, message: &str) {
+        self.resolution.errors.push(ResolveError { token: token.clone(), message: message.to_string() });
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        if scope.contains_key(&name.lexeme) {
+            self.error(name, "Already a variable with this name in this scope.");
+            return;
+        }
+        scope.insert(name.lexeme.clone(), Local { defined: false, declaration: name.span });
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(local) = self.scopes.last_mut().and_then(|scope| scope.get_mut(&name.lexeme)) {
+            local.defined = true;
+        }
+    }
+
+    // 从里往外找，找不到就是全局变量
+    fn bind(&mut self, id: ExprId, name: &Token) {
+        let binding = self
+            .scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| scope.get(&name.lexeme).map(|local| Binding::Local { depth, declaration: local.declaration }))
+            .unwrap_or(Binding::Global);
+        self.resolution.bindings.insert(id, binding);
+    }
+
+    fn function(&mut self, ast: &Ast, function: &Function) {
+        self.scopes.push(HashMap::new());
+        self.functions += 1;
+        let loops = std::mem::take(&mut self.loops);
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+        walk_program(self, ast, &function.body);
+        self.loops = loops;
+        self.functions -= 1;
+        self.scopes.pop();
+    }
+}
+
+impl ExprWalker for Resolver {
+    fn visit_variable(&mut self, _ast: &Ast, id: ExprId, expr: &Variable) {
+        let declaring = self.scopes.last().and_then(|scope| scope.get(&expr.name.lexeme)).is_some_and(|local| !local.defined);
+        if declaring {
+            self.error(&expr.name, "Can't read local variable in its own initializer.");
+        }
+        self.bind(id, &expr.name);
+    }
+
+    fn visit_assign(&mut self, ast: &Ast, id: ExprId, expr: &Assign) {
+        walk_children(self, ast, expr);
+        self.bind(id, &expr.name);
+    }
+
+    fn visit_compound_assign(&mut self, ast: &Ast, id: ExprId, expr: &CompoundAssign) {
+        walk_children(self, ast, expr);
+        self.bind(id, &expr.name);
+    }
+
+    // 匿名函数的名字不在任何作用域里
+    fn visit_function(&mut self, ast: &Ast, _id: ExprId, expr: &Function) {
+        self.function(ast, expr);
+    }
+}
+
+impl StmtWalker for Resolver {
+    fn visit_let_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Let) {
+        self.declare(&stmt.name);
+        walk_children(self, ast, stmt);
+        self.define(&stmt.name);
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Block) {
+        self.scopes.push(HashMap::new());
+        walk_children(self, ast, stmt);
+        self.scopes.pop();
+    }
+
+    // 名字先定义好，函数体里可以递归调用自己
+    fn visit_function_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Function) {
+        if let Some(name) = &stmt.name {
+            self.declare(name);
+            self.define(name);
+        }
+        self.function(ast, stmt);
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &While) {
+        self.loops += 1;
+        walk_children(self, ast, stmt);
+        self.loops -= 1;
+    }
+
+    fn visit_for_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &For) {
+        self.scopes.push(HashMap::new());
+        self.loops += 1;
+        walk_children(self, ast, stmt);
+        self.loops -= 1;
+        self.scopes.pop();
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Return) {
+        if self.functions == 0 {
+            self.error(&stmt.keyword, "Can't return from top-level code.");
+        }
+        walk_children(self, ast, stmt);
+    }
+
+    fn visit_break_stmt(&mut self, _ast: &Ast, _id: StmtId, stmt: &Break) {
+        if self.loops == 0 {
+            self.error(&stmt.keyword, "Can't break outside of a loop.");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::parser::Parser;
+
+    use super::*;
+
+    // 按源码里的顺序列出每个变量的名字和绑定
+    fn bindings(source: &str) -> Vec<(String, Binding)> {
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        let ast = parser.ast();
+        let resolution = resolve(ast, &program);
+        assert_eq!(resolution.errors, []);
+        let mut result: Vec<(usize, String, Binding)> = vec![];
+        for (id, expr) in ast.exprs.iter() {
+            let name = match expr {
+                Expr::Variable(variable) => &variable.name,
+                Expr::Assign(assign) => &assign.name,
+                Expr::CompoundAssign(assign) => &assign.name,
+                _ => continue,
+            };
+            if let Some(&binding) = resolution.bindings.get(id) {
+                result.push((name.span.start, name.lexeme.clone(), binding));
+            }
+        }
+        result.sort_by_key(|(start, _, _)| *start);
+        result.into_iter().map(|(_, name, binding)| (name, binding)).collect()
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        resolve(parser.ast(), &program).errors.iter().map(|error| error.to_string()).collect()
+    }
+
+    #[test]
+    fn binding_test() {
+        let local = |depth, start, end| Binding::Local { depth, declaration: Span::new(start, end) };
+        let source = "let g = 1; fn f(a) { let b = a; { b = g; } return fn() { return a + f(b); }; }";
+        assert_eq!(
+            bindings(source),
+            [
+                ("a".to_string(), local(0, 16, 17)),
+                ("b".to_string(), local(1, 25, 26)),
+                ("g".to_string(), Binding::Global),
+                ("a".to_string(), local(1, 16, 17)),
+                ("f".to_string(), Binding::Global),
+                ("b".to_string(), local(1, 25, 26)),
+            ]
+        );
+        // 内层的声明遮住外层的
+        assert_eq!(bindings("{ let x = 1; { let x = 2; print x; } }"), [("x".to_string(), local(0, 19, 20))]);
+        // 没有desugar的for，init在单独的一层作用域里
+        assert_eq!(
+            bindings("{ for let i = 0; i < 3; i += 1 { print i; } }"),
+            [("i".to_string(), local(0, 10, 11)), ("i".to_string(), local(0, 10, 11)), ("i".to_string(), local(1, 10, 11))]
+        );
+    }
+
+    #[test]
+    fn error_test() {
+        assert_eq!(errors("{ let a = 1; { let a = a; } }"), ["line 1: at token \"a\", Can't read local variable in its own initializer."]);
+        assert_eq!(
+            errors("fn f(a, a) { let b; let b; }"),
+            [
+                "line 1: at token \"a\", Already a variable with this name in this scope.",
+                "line 1: at token \"b\", Already a variable with this name in this scope.",
+            ]
+        );
+        assert_eq!(errors("return 1;\nif true { return; }"), ["line 1: at token \"return\", Can't return from top-level code.", "line 2: at token \"return\", Can't return from top-level code."]);
+        assert_eq!(
+            errors("break;\nwhile true { fn f() { break; } if true { break; } }\nfor ;; { break; fn g() { for ;; { break; } break; } }"),
+            [
+                "line 1: at token \"break\", Can't break outside of a loop.",
+                "line 2: at token \"break\", Can't break outside of a loop.",
+                "line 3: at token \"break\", Can't break outside of a loop.",
+            ]
+        );
+        // 全局变量可以重新定义，也可以在初始化时读自己(读的是之前的值)
+        assert_eq!(errors("let a = 1; let a = a; fn f() { return 1; }"), Vec::<String>::new());
+    }
+}
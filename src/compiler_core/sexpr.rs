@@ -0,0 +1,561 @@
+use std::fmt;
+
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::StmtId;
+use super::expr::Assign;
+use super::expr::Binary;
+use super::expr::Call;
+use super::expr::CompoundAssign;
+use super::expr::Expr;
+use super::expr::Function;
+use super::expr::Grouping;
+use super::expr::Index;
+use super::expr::Interpolation;
+use super::expr::List;
+use super::expr::Literal;
+use super::expr::LiteralValue;
+use super::expr::Logical;
+use super::expr::TemplatePart;
+use super::expr::TypeAnnotation;
+use super::expr::Unary;
+use super::expr::Variable;
+use super::lexer::Lexer;
+use super::stmt::Block;
+use super::stmt::Break;
+use super::stmt::Expression;
+use super::stmt::For;
+use super::stmt::If;
+use super::stmt::Let;
+use super::stmt::Print;
+use super::stmt::Return;
+use super::stmt::Stmt;
+use super::stmt::While;
+use super::token::Token;
+use super::token::TokenType;
+
+// 把AstPrinter输出的S表达式读回语法树，测试可以直接用S表达式写期望的树
+//
+// - 原子: 数字(可以带负号，常量折叠会产生负数)、true/false/null、带引号的字符串(Rust的转义)，其他的都是变量
+// - 表达式: (运算符 a b)、(运算符 a)、(and a b)、(or a b)、(= x v)、(group e)、(call f 参数...)、
+//   (+= x v)这样的复合赋值、(interp "片段" ${表达式} ...)、(fn (参数...) 语句...)、(list 元素...)、(index xs i)
+// - 语句: (let x [: 类型] [e])、(print e)、(block 语句...)、(if c then [else])、(while c body)、
+//   (for init cond inc body)(省略的部分写成_)、
+//   (fn name (参数...) 语句...)、(return [e])、(break)，其他的是表达式语句
+// - 类型标注: let的变量名、参数和参数列表后面可以接`: 类型`，类型是名字、(list number)这样的泛型，
+//   或者(fn (参数类型...) 返回类型)
+//
+// S表达式里没有位置信息，token的行号是它在S表达式里的行号，没有span
+// 读回来的树和parser的树用walk::same_program/same_expr比较
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReadError {
+    pub message: String,
+    pub line: i32,
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// 每个语句一个S表达式，和AstPrinter::print_program对应
+pub fn read_program(source: &str) -> Result<(Ast, Vec<StmtId>), ReadError> {
+    let forms = SExprParser::new(source).forms()?;
+    let mut reader = AstReader { ast: Ast::new() };
+    let program = forms.iter().map(|form| reader.stmt(form)).collect::<Result<_, _>>()?;
+    Ok((reader.ast, program))
+}
+
+// 一个表达式，和AstPrinter::print_expr对应
+pub fn read_expr(source: &str) -> Result<(Ast, ExprId), ReadError> {
+    let forms = SExprParser::new(source).forms()?;
+    let [form] = forms.as_slice() else {
+        return Err(ReadError { message: format!("expected one expression, got {}", forms.len()), line: 1 });
+    };
+    let mut reader = AstReader { ast: Ast::new() };
+    let expr = reader.expr(form)?;
+    Ok((reader.ast, expr))
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum SExpr {
+    Atom(String, i32),
+    Str(String, i32),
+    // `${表达式}`，只出现在interp里
+    Splice(Box<SExpr>, i32),
+    List(Vec<SExpr>, i32),
+}
+
+impl SExpr {
+    fn line(&self) -> i32 {
+        match self {
+            SExpr::Atom(_, line) | SExpr::Str(_, line) | SExpr::Splice(_, line) | SExpr::List(_, line) => *line,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            SExpr::Atom(atom, _) => atom.clone(),
+            SExpr::Str(value, _) => format!("{:?}", value),
+            SExpr::Splice(..) => "${...}".to_string(),
+            SExpr::List(..) => "(...)".to_string(),
+        }
+    }
+}
+
+struct SExprParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: i32,
+}
+
+impl<'a> SExprParser<'a> {
+    fn new(source: &'a str) -> Self {
+        Self { chars: source.chars().peekable(), line: 1 }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, ReadError> {
+        Err(ReadError { message, line: self.line })
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_whitespace() {
+                break;
+            }
+            if c == '\n' {
+                self.line += 1;
+            }
+            self.chars.next();
+        }
+    }
+
+    // 读到结束为止
+    fn forms(&mut self) -> Result<Vec<SExpr>, ReadError> {
+        let mut forms = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek().is_none() {
+                return Ok(forms);
+            }
+            forms.push(self.form()?);
+        }
+    }
+
+    fn form(&mut self) -> Result<SExpr, ReadError> {
+        self.skip_whitespace();
+        let line = self.line;
+        match self.chars.peek() {
+            None => self.error("unexpected end of input".to_string()),
+            Some('(') => {
+                self.chars.next();
+                let mut items = vec![];
+                loop {
+                    self.skip_whitespace();
+                    match self.chars.peek() {
+                        Some(')') => {
+                            self.chars.next();
+                            return Ok(SExpr::List(items, line));
+                        }
+                        None => return self.error("unclosed '('".to_string()),
+                        _ => items.push(self.form()?),
+                    }
+                }
+            }
+            Some(')') => self.error("unexpected ')'".to_string()),
+            Some('"') => {
+                self.chars.next();
+                Ok(SExpr::Str(self.string()?, line))
+            }
+            Some('$') => {
+                self.chars.next();
+                if self.chars.next() != Some('{') {
+                    return self.error("expected '{' after '$'".to_string());
+                }
+                let form = self.form()?;
+                self.skip_whitespace();
+                if self.chars.next() != Some('}') {
+                    return self.error("expected '}'".to_string());
+                }
+                Ok(SExpr::Splice(Box::new(form), line))
+            }
+            Some(_) => {
+                let mut atom = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_whitespace() || matches!(c, '(' | ')' | '}' | '"') {
+                        break;
+                    }
+                    atom.push(c);
+                    self.chars.next();
+                }
+                if atom.is_empty() {
+                    let c = self.chars.peek().copied().unwrap_or_default();
+                    return self.error(format!("unexpected '{}'", c));
+                }
+                Ok(SExpr::Atom(atom, line))
+            }
+        }
+    }
+
+    // AstPrinter用{:?}输出字符串，这里按Rust的转义规则读回来
+    fn string(&mut self) -> Result<String, ReadError> {
+        let mut result = String::new();
+        loop {
+            let Some(c) = self.chars.next() else {
+                return self.error("unterminated string".to_string());
+            };
+            match c {
+                '"' => return Ok(result),
+                '\\' => {
+                    let escaped = match self.chars.next() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('0') => '\0',
+                        Some(c @ ('\\' | '"' | '\'')) => c,
+                        Some('u') => self.unicode_escape()?,
+                        _ => return self.error("invalid escape".to_string()),
+                    };
+                    result.push(escaped);
+                }
+                '\n' => {
+                    self.line += 1;
+                    result.push(c);
+                }
+                c => result.push(c),
+            }
+        }
+    }
+
+    // \u{XXXX}
+    fn unicode_escape(&mut self) -> Result<char, ReadError> {
+        if self.chars.next() != Some('{') {
+            return self.error("invalid unicode escape".to_string());
+        }
+        let mut digits = String::new();
+        for c in self.chars.by_ref() {
+            if c == '}' {
+                break;
+            }
+            digits.push(c);
+        }
+        match u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32) {
+            Some(c) => Ok(c),
+            None => self.error("invalid unicode escape".to_string()),
+        }
+    }
+}
+
+struct AstReader {
+    ast: Ast,
+}
+
+fn error<T>(form: &SExpr, message: String) -> Result<T, ReadError> {
+    Err(ReadError { message, line: form.line() })
+}
+
+// 名字原子，变量、参数、函数名
+fn name(form: &SExpr) -> Result<Token, ReadError> {
+    match form {
+        SExpr::Atom(name, line) if TokenType::IDENT == token_type(name) => {
+            Ok(Token::new(TokenType::IDENT, name.clone(), *line))
+        }
+        _ => error(form, format!("expected a name, got {}", form.describe())),
+    }
+}
+
+// for省略的部分
+fn omitted(form: &SExpr) -> bool {
+    matches!(form, SExpr::Atom(atom, _) if atom == "_")
+}
+
+// 开头是`: 类型`的话读出类型，返回剩下的部分
+fn annotation(forms: &[SExpr]) -> Result<(Option<TypeAnnotation>, &[SExpr]), ReadError> {
+    match forms {
+        [SExpr::Atom(colon, _), rest @ ..] if colon == ":" => match rest.split_first() {
+            Some((annotation, rest)) => Ok((Some(type_annotation(annotation)?), rest)),
+            None => error(&forms[0], "expected a type after ':'".to_string()),
+        },
+        _ => Ok((None, forms)),
+    }
+}
+
+fn type_annotation(form: &SExpr) -> Result<TypeAnnotation, ReadError> {
+    match form {
+        SExpr::Atom(null, line) if null == "null" => Ok(TypeAnnotation::Name(Token::new(TokenType::NULL, null.clone(), *line))),
+        SExpr::Atom(..) => Ok(TypeAnnotation::Name(name(form)?)),
+        SExpr::List(items, _) => match items.as_slice() {
+            [SExpr::Atom(head, _), SExpr::List(params, _), ret] if head == "fn" => {
+                let params = params.iter().map(type_annotation).collect::<Result<_, _>>()?;
+                Ok(TypeAnnotation::Function(params, Box::new(type_annotation(ret)?)))
+            }
+            [head, arguments @ ..] if !arguments.is_empty() => {
+                let arguments = arguments.iter().map(type_annotation).collect::<Result<_, _>>()?;
+                Ok(TypeAnnotation::Generic(name(head)?, arguments))
+            }
+            _ => error(form, format!("expected a type, got {}", form.describe())),
+        },
+        _ => error(form, format!("expected a type, got {}", form.describe())),
+    }
+}
+
+// 用lexer得到运算符和关键字的种类
+fn token_type(lexeme: &str) -> TokenType {
+    let mut tokens = Lexer::new(lexeme);
+    match (tokens.next(), tokens.next()) {
+        (Some(token), Some(eof)) if token.lexeme == lexeme && eof.token_type == TokenType::EOF => token.token_type,
+        _ => TokenType::ILLEGAL,
+    }
+}
+
+impl AstReader {
+    fn add_expr(&mut self, expr: Expr) -> ExprId {
+        self.ast.exprs.alloc(expr)
+    }
+
+    fn add_stmt(&mut self, stmt: Stmt) -> StmtId {
+        self.ast.stmts.alloc(stmt)
+    }
+
+    fn stmt(&mut self, form: &SExpr) -> Result<StmtId, ReadError> {
+        let SExpr::List(items, line) = form else {
+            let expr = self.expr(form)?;
+            return Ok(self.add_stmt(Stmt::Expression(Expression::new(expr))));
+        };
+        let keyword = |token_type, lexeme: &str| Token::new(token_type, lexeme.to_string(), *line);
+        let stmt = match (head(items), &items[1.min(items.len())..]) {
+            (Some("let"), [variable, rest @ ..]) if rest.len() <= 3 => {
+                let (annotation, rest) = annotation(rest)?;
+                match rest {
+                    [] => Stmt::Let(Let::new(name(variable)?, annotation, None)),
+                    [initializer] => Stmt::Let(Let::new(name(variable)?, annotation, Some(self.expr(initializer)?))),
+                    _ => return error(form, "wrong number of arguments for let".to_string()),
+                }
+            }
+            (Some("print"), [expr]) => Stmt::Print(Print::new(self.expr(expr)?)),
+            (Some("block"), stmts) => Stmt::Block(Block::new(self.stmts(stmts)?)),
+            (Some("if"), [condition, then_branch]) => Stmt::If(If::new(self.expr(condition)?, self.stmt(then_branch)?, None)),
+            (Some("if"), [condition, then_branch, else_branch]) => {
+                Stmt::If(If::new(self.expr(condition)?, self.stmt(then_branch)?, Some(self.stmt(else_branch)?)))
+            }
+            (Some("while"), [condition, body]) => Stmt::While(While::new(self.expr(condition)?, self.stmt(body)?)),
+            (Some("for"), [initializer, condition, increment, body]) => {
+                let initializer = if omitted(initializer) { None } else { Some(self.stmt(initializer)?) };
+                let condition = if omitted(condition) { None } else { Some(self.expr(condition)?) };
+                let increment = if omitted(increment) { None } else { Some(self.expr(increment)?) };
+                Stmt::For(For::new(initializer, condition, increment, self.stmt(body)?))
+            }
+            // 有名字的是函数声明，匿名函数是表达式语句
+            (Some("fn"), [SExpr::Atom(..), ..]) => Stmt::Function(self.function(items)?),
+            (Some("return"), []) => Stmt::Return(Return::new(keyword(TokenType::RETURN, "return"), None)),
+            (Some("return"), [value]) => Stmt::Return(Return::new(keyword(TokenType::RETURN, "return"), Some(self.expr(value)?))),
+            (Some("break"), []) => Stmt::Break(Break::new(keyword(TokenType::BREAK, "break"))),
+            (Some("let" | "print" | "if" | "while" | "for" | "return" | "break"), _) => {
+                return error(form, format!("wrong number of arguments for {}", head(items).unwrap_or_default()));
+            }
+            _ => Stmt::Expression(Expression::new(self.expr(form)?)),
+        };
+        Ok(self.add_stmt(stmt))
+    }
+
+    fn stmts(&mut self, forms: &[SExpr]) -> Result<Vec<StmtId>, ReadError> {
+        forms.iter().map(|form| self.stmt(form)).collect()
+    }
+
+    // (fn [name] (参数...) 语句...)
+    fn function(&mut self, items: &[SExpr]) -> Result<Box<Function>, ReadError> {
+        let (function_name, rest) = match &items[1..] {
+            [SExpr::Atom(..), rest @ ..] => (Some(name(&items[1])?), rest),
+            rest => (None, rest),
+        };
+        let Some((SExpr::List(param_list, _), rest)) = rest.split_first() else {
+            return error(&items[0], "expected a parameter list".to_string());
+        };
+        let mut params = vec![];
+        let mut param_annotations = vec![];
+        let mut param_list = &param_list[..];
+        while let Some((param, rest)) = param_list.split_first() {
+            let (param_annotation, rest) = annotation(rest)?;
+            params.push(name(param)?);
+            param_annotations.push(param_annotation);
+            param_list = rest;
+        }
+        let (return_annotation, body) = annotation(rest)?;
+        Ok(Box::new(Function::new(function_name, params, param_annotations, return_annotation, self.stmts(body)?)))
+    }
+
+    fn expr(&mut self, form: &SExpr) -> Result<ExprId, ReadError> {
+        let expr = match form {
+            SExpr::Str(value, _) => Expr::Literal(Literal::new(LiteralValue::String(value.clone()))),
+            SExpr::Atom(atom, _) => match atom.as_str() {
+                "true" => Expr::Literal(Literal::new(LiteralValue::Bool(true))),
+                "false" => Expr::Literal(Literal::new(LiteralValue::Bool(false))),
+                "null" => Expr::Literal(Literal::new(LiteralValue::Null)),
+                // 优化之后的树里有负数的字面量，`-1`是数字，`(- 1)`才是取负
+                _ if atom.strip_prefix('-').unwrap_or(atom).starts_with(|c: char| c.is_ascii_digit()) => match atom.parse() {
+                    Ok(number) => Expr::Literal(Literal::new(LiteralValue::Number(number))),
+                    Err(_) => return error(form, format!("invalid number {}", atom)),
+                },
+                _ => Expr::Variable(Variable::new(name(form)?)),
+            },
+            SExpr::Splice(..) => return error(form, "${...} is only allowed in interp".to_string()),
+            SExpr::List(items, line) => self.list(form, items, *line)?,
+        };
+        Ok(self.add_expr(expr))
+    }
+
+    fn list(&mut self, form: &SExpr, items: &[SExpr], line: i32) -> Result<Expr, ReadError> {
+        let Some(SExpr::Atom(head, _)) = items.first() else {
+            return error(form, "expected an operator".to_string());
+        };
+        let operator = |token_type| Token::new(token_type, head.clone(), line);
+        let expr = match (head.as_str(), &items[1..]) {
+            ("group", [expr]) => Expr::Grouping(Grouping::new(self.expr(expr)?)),
+            ("call", [callee, arguments @ ..]) => {
+                let callee = self.expr(callee)?;
+                let arguments = arguments.iter().map(|argument| self.expr(argument)).collect::<Result<_, _>>()?;
+                Expr::Call(Call::new(callee, Token::new(TokenType::RPAREN, ")".to_string(), line), arguments))
+            }
+            ("interp", parts) => {
+                let mut template = vec![];
+                for part in parts {
+                    match part {
+                        SExpr::Str(chunk, _) => template.push(TemplatePart::Chunk(chunk.clone())),
+                        SExpr::Splice(expr, _) => template.push(TemplatePart::Expr(self.expr(expr)?)),
+                        _ => return error(part, format!("expected a string or ${{...}}, got {}", part.describe())),
+                    }
+                }
+                Expr::Interpolation(Interpolation::new(template))
+            }
+            ("fn", _) => Expr::Function(self.function(items)?),
+            ("list", elements) => {
+                let elements = elements.iter().map(|element| self.expr(element)).collect::<Result<_, _>>()?;
+                Expr::List(List::new(Token::new(TokenType::LBRACKET, "[".to_string(), line), elements))
+            }
+            ("index", [object, index]) => {
+                let bracket = Token::new(TokenType::LBRACKET, "[".to_string(), line);
+                Expr::Index(Index::new(self.expr(object)?, bracket, self.expr(index)?))
+            }
+            ("=", [variable, value]) => Expr::Assign(Assign::new(name(variable)?, self.expr(value)?)),
+            ("+=" | "-=" | "*=" | "/=", [variable, value]) => {
+                Expr::CompoundAssign(CompoundAssign::new(name(variable)?, operator(token_type(head)), self.expr(value)?))
+            }
+            (_, [left, right]) => match token_type(head) {
+                token_type @ (TokenType::AND | TokenType::OR) => {
+                    Expr::Logical(Logical::new(self.expr(left)?, operator(token_type), self.expr(right)?))
+                }
+                token_type @ (TokenType::PLUS
+                | TokenType::MINUS
+                | TokenType::ASTERISK
+                | TokenType::SLASH
+                | TokenType::GT
+                | TokenType::GE
+                | TokenType::LT
+                | TokenType::LE
+                | TokenType::EQ
+                | TokenType::NOT_EQ) => Expr::Binary(Binary::new(self.expr(left)?, operator(token_type), self.expr(right)?)),
+                _ => return error(form, format!("unknown binary operator {}", head)),
+            },
+            (_, [right]) => match token_type(head) {
+                token_type @ (TokenType::MINUS | TokenType::EXCLAMATION) => {
+                    Expr::Unary(Unary::new(operator(token_type), self.expr(right)?))
+                }
+                _ => return error(form, format!("unknown unary operator {}", head)),
+            },
+            _ => return error(form, format!("unknown form {}", head)),
+        };
+        Ok(expr)
+    }
+}
+
+fn head(items: &[SExpr]) -> Option<&str> {
+    match items.first() {
+        Some(SExpr::Atom(head, _)) => Some(head),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::parser::tests::CORPUS;
+    use crate::compiler_core::optimizer::optimize;
+    use crate::compiler_core::parser::Parser;
+    use crate::compiler_core::visitors::AstPrinter;
+    use crate::compiler_core::walk::same_expr;
+    use crate::compiler_core::walk::same_program;
+
+    use super::*;
+
+    #[test]
+    fn round_trip_test() {
+        let mut printer = AstPrinter::new();
+        let inputs = CORPUS.iter().copied().chain([
+            "print \"back\\\\slash\n${x}\";",
+            "print \"é😀\";",
+            "while i < 10 { if !done { i = i + 1; } else { break; } }",
+            "fn outer() { fn inner(a) { return; } return inner; } let f = fn(x, y) { print x; };",
+            "x; f(); -(-1) >= 2 != true;",
+        ]);
+        for input in inputs {
+            let mut parser = Parser::new(input);
+            let program = parser.parse_program();
+            assert!(!parser.had_error(), "{}", input);
+            let printed = printer.print_program(parser.ast(), &program);
+            let (ast, read) = read_program(&printed).unwrap();
+            assert!(same_program(&ast, &read, parser.ast(), &program), "{}", printed);
+            assert_eq!(printer.print_program(&ast, &read), printed);
+        }
+
+        // 常量折叠出来的负数
+        let mut parser = Parser::new("print -1; print -(2 * 3.5) + x; print x - -0.25;");
+        let program = parser.parse_program();
+        let mut ast = parser.into_ast();
+        assert_eq!(optimize(&mut ast, &program), []);
+        let printed = printer.print_program(&ast, &program);
+        assert_eq!(printed, "(print -1)\n(print (+ -7 x))\n(print (- x -0.25))");
+        let (read, read_program) = read_program(&printed).unwrap();
+        assert!(same_program(&read, &read_program, &ast, &program), "{}", printed);
+    }
+
+    #[test]
+    fn fixture_test() {
+        // 期望的树直接用S表达式写
+        let (expected, expr) = read_expr("(* (group (+ 1 3)) 2)").unwrap();
+        let mut parser = Parser::new("(1 + 3) * 2");
+        let parsed = parser.parse();
+        assert!(same_expr(&expected, expr, parser.ast(), parsed));
+
+        let mut parser = Parser::new("(1 + 3) * 3");
+        let parsed = parser.parse();
+        assert!(!same_expr(&expected, expr, parser.ast(), parsed));
+
+        let (expected, program) = read_program("(let x \"a\")\n(print x)").unwrap();
+        let mut parser = Parser::new("let x = \"a\"; print x;");
+        let parsed = parser.parse_program();
+        assert!(same_program(&expected, &program, parser.ast(), &parsed));
+        // 字符串和变量不一样
+        let (other, other_program) = read_program("(let x a)\n(print x)").unwrap();
+        assert!(!same_program(&other, &other_program, parser.ast(), &parsed));
+    }
+
+    #[test]
+    fn read_error_test() {
+        let inputs = [
+            ("(+ 1 2", "line 1: unclosed '('"),
+            ("(print 1))", "line 1: unexpected ')'"),
+            ("(print)", "line 1: wrong number of arguments for print"),
+            ("(print 1)\n(% 1 2)", "line 2: unknown binary operator %"),
+            ("(interp x)", "line 1: expected a string or ${...}, got x"),
+            ("(let 1 2)", "line 1: expected a name, got 1"),
+            ("(print -1x)", "line 1: invalid number -1x"),
+            ("(print \"abc)", "line 1: unterminated string"),
+            ("(fn f a)", "line 1: expected a parameter list"),
+            ("${x}", "line 1: ${...} is only allowed in interp"),
+        ];
+        for (input, message) in inputs {
+            assert_eq!(read_program(input).unwrap_err().to_string(), message, "{}", input);
+        }
+        assert_eq!(read_expr("1 2").unwrap_err().message, "expected one expression, got 2");
+    }
+}
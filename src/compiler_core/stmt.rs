@@ -0,0 +1,25 @@
+use super::ast::ExprId;
+use super::ast::StmtId;
+use super::ast_macro::define_ast;
+use super::expr::Function;
+use super::expr::TypeAnnotation;
+use super::token::Token;
+
+define_ast! {
+    Stmt(StmtId) visited by StmtVisitor, walked by StmtWalker::visit_stmt {
+        // 表达式语句 `x + 1;`
+        Expression { expr: ExprId } => visit_expression_stmt,
+        Print { expr: ExprId } => visit_print_stmt,
+        // `let x = 1;`，没有初始值的变量是null
+        Let { name: Token, annotation: Option<TypeAnnotation>, initializer: Option<ExprId> } => visit_let_stmt,
+        Block { stmts: Vec<StmtId> } => visit_block_stmt,
+        If { condition: ExprId, then_branch: StmtId, else_branch: Option<StmtId> } => visit_if_stmt,
+        While { condition: ExprId, body: StmtId } => visit_while_stmt,
+        // `for let i = 0; i < n; i += 1 { ... }`，三个部分都可以省略，desugar之后变成while
+        For { initializer: Option<StmtId>, condition: Option<ExprId>, increment: Option<ExprId>, body: StmtId } => visit_for_stmt,
+        Function(Box<Function>) => visit_function_stmt,
+        // keyword是return关键字，报错的时候用来定位
+        Return { keyword: Token, value: Option<ExprId> } => visit_return_stmt,
+        Break { keyword: Token } => visit_break_stmt,
+    }
+}
@@ -0,0 +1,256 @@
+use std::io::{self, BufRead};
+
+use super::lexer::Lexer;
+use super::lexer::LexerState;
+use super::token::Token;
+use super::token::TokenType;
+
+// 已经扫描过的部分超过这个大小时，从缓冲区里删掉
+const COMPACT_THRESHOLD: usize = 64 * 1024;
+
+// 从任意BufRead里分块读取源码的lexer，不需要把整个文件读进内存
+// 产生的token序列和Lexer扫描整个字符串完全一样，span是在整个输入中的字节偏移
+//
+// 一个token是否已经完整，看它后面的缓冲区里还有没有字符，见complete：
+// lexer在token结束之后最多再看一个字符，只有`r###"`这样的开头要越过所有的`#`，
+// 没有结束的字符串和注释会一直读到缓冲区末尾，后面自然也没有字符。
+// 不满足条件时就再读数据，然后从这个token之前的状态重新扫描。
+pub struct StreamLexer<R: BufRead> {
+    reader: R,
+    // 已经解码的源码，buffer[0]在输入中的偏移是state.offset - consumed
+    buffer: String,
+    // buffer中已经扫描完的部分
+    consumed: usize,
+    state: LexerState,
+    // 块边界上还不完整的UTF-8字节
+    pending: Vec<u8>,
+    eof: bool,
+    // 是否已经返回了EOF token
+    done: bool,
+}
+
+impl<R: BufRead> StreamLexer<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: String::new(),
+            consumed: 0,
+            state: LexerState::default(),
+            pending: vec![],
+            eof: false,
+            done: false,
+        }
+    }
+
+    // 再读一块数据到缓冲区，读到输入末尾时设置eof
+    fn fill(&mut self) -> io::Result<()> {
+        let chunk = self.reader.fill_buf()?;
+        if chunk.is_empty() {
+            self.eof = true;
+            if !self.pending.is_empty() {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "stream did not end with valid UTF-8"));
+            }
+            return Ok(());
+        }
+        let len = chunk.len();
+        self.pending.extend_from_slice(chunk);
+        self.reader.consume(len);
+
+        // 只把完整的UTF-8字符放进缓冲区，剩下的字节等下一块
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            Err(error) => return Err(io::Error::new(io::ErrorKind::InvalidData, error)),
+        };
+        // 上面已经检查过这部分是合法的UTF-8
+        self.buffer.push_str(std::str::from_utf8(&self.pending[..valid]).unwrap());
+        self.pending.drain(..valid);
+        Ok(())
+    }
+
+    // 扫描缓冲区里的下一个token，不读取新的数据
+    // token后面的数据还不够、没法确定它已经完整的时候返回None
+    fn scan(&mut self) -> Option<Option<Token>> {
+        let rest = &self.buffer[self.consumed..];
+        let mut lexer = Lexer::with_state(rest, self.state.clone());
+        let token = lexer.next();
+        let state = lexer.state();
+        let end = state.offset - self.state.offset;
+        // 缓冲区扫完时lexer会返回EOF，只有真正读到输入末尾才算数
+        if !self.eof && !complete(&rest[end..]) {
+            return None;
+        }
+        self.done = token.as_ref().is_none_or(|token| token.token_type == TokenType::EOF);
+        self.consumed += end;
+        self.state = state;
+        self.compact();
+        Some(token)
+    }
+
+    // 读取更多数据，直到没扫描的部分至少变成原来的两倍
+    // 很长的token每次都要从头重新扫描，按倍数读取时总的扫描量还是线性的
+    fn fill_more(&mut self) -> io::Result<()> {
+        let target = self.buffer.len() + (self.buffer.len() - self.consumed).max(1);
+        while !self.eof && self.buffer.len() < target {
+            self.fill()?;
+        }
+        Ok(())
+    }
+
+    // 交互式输入用：再读一块数据(标准输入一般是一行)，输入已经结束时返回false
+    // 之后用buffered_tokens取出这次已经完整的token，最后剩下的token和EOF用迭代器取
+    pub fn read_chunk(&mut self) -> io::Result<bool> {
+        if self.eof {
+            return Ok(false);
+        }
+        self.fill()?;
+        Ok(!self.eof)
+    }
+
+    // 缓冲区里已经完整的token，不会阻塞等待输入
+    pub fn buffered_tokens(&mut self) -> Vec<Token> {
+        let mut tokens = vec![];
+        while !self.done {
+            match self.scan() {
+                Some(Some(token)) => tokens.push(token),
+                _ => break,
+            }
+        }
+        tokens
+    }
+
+    // 删掉已经扫描过的部分，避免缓冲区随着输入一直增长
+    fn compact(&mut self) {
+        if self.consumed >= COMPACT_THRESHOLD {
+            self.buffer.drain(..self.consumed);
+            self.consumed = 0;
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for StreamLexer<R> {
+    type Item = io::Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            if let Some(token) = self.scan() {
+                return token.map(Ok);
+            }
+            if let Err(error) = self.fill_more() {
+                // 出错之后不再读取，剩下的缓冲区还可以继续扫描
+                self.eof = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+// token之后的内容，跳过`#`之后至少还有一个字符时，token不会因为后面的输入而改变
+fn complete(after: &str) -> bool {
+    !after.trim_start_matches('#').is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::io::Read;
+
+    use super::*;
+
+    // 用很小的块读取，让token、UTF-8字符跨过块边界
+    fn assert_same_tokens(source: &str) {
+        let expects: Vec<Token> = Lexer::new(source).collect();
+        for capacity in [1, 2, 3, 7, 64] {
+            let reader = BufReader::with_capacity(capacity, source.as_bytes());
+            let tokens: Vec<Token> = StreamLexer::new(reader).map(Result::unwrap).collect();
+            assert_eq!(tokens, expects, "capacity {}", capacity);
+        }
+    }
+
+    #[test]
+    fn same_as_in_memory_test() {
+        let inputs = [
+            "",
+            "let five = 5;\nlet ten = 10.25;\n",
+            "/// doc\nfn add(x, y) { x + y; } // comment\n/* block /* nested */\n comment */ print add(1, 2) >= 3",
+            "let 名字 = \"中文字符串 🎉\"; print \"${名字} ${ {1: \"in ${x}\"} }\"",
+            "r##\"raw \"# string\"## \"\"\"\n    multi\n      line\n    \"\"\" r#",
+            "\"unterminated string\n across lines",
+            "1 /* unterminated",
+        ];
+        for input in inputs {
+            assert_same_tokens(input);
+        }
+    }
+
+    #[test]
+    fn long_input_test() {
+        // 超过COMPACT_THRESHOLD，缓冲区会被压缩
+        let mut source = String::new();
+        for i in 0..5000 {
+            source.push_str(&format!("let x{} = \"{}\"; // 注释 {}\n", i, "long ".repeat(i % 50), i));
+        }
+        let expects: Vec<Token> = Lexer::new(&source).collect();
+        let reader = BufReader::with_capacity(100, source.as_bytes());
+        let tokens: Vec<Token> = StreamLexer::new(reader).map(Result::unwrap).collect();
+        assert_eq!(tokens, expects);
+    }
+
+    #[test]
+    fn long_comment_block_test() {
+        // 跳过注释不能递归，否则连续很多行注释会栈溢出
+        let source = format!("{}print 1;", "// c\n".repeat(1_000_000));
+        let expects: Vec<Token> = Lexer::new(&source).collect();
+        assert_eq!(expects.iter().map(|token| token.lexeme.as_str()).collect::<Vec<&str>>(), ["print", "1", ";", ""]);
+        assert_eq!(expects[0].line, 1_000_001);
+        let tokens: Vec<Token> = StreamLexer::new(source.as_bytes()).map(Result::unwrap).collect();
+        assert_eq!(tokens, expects);
+    }
+
+    // 一直产生`a `的输入，没有换行符
+    struct Endless;
+
+    impl io::Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            for (i, byte) in buf.iter_mut().enumerate() {
+                *byte = if i % 2 == 0 { b'a' } else { b' ' };
+            }
+            Ok(buf.len() / 2 * 2)
+        }
+    }
+
+    #[test]
+    fn without_newline_test() {
+        // 不需要读完整个输入就能得到token
+        let tokens: Vec<Token> = StreamLexer::new(BufReader::with_capacity(4, Endless)).take(1000).map(Result::unwrap).collect();
+        assert!(tokens.iter().all(|token| token.lexeme == "a"));
+    }
+
+    #[test]
+    fn buffered_tokens_test() {
+        // 每次read_chunk读一行，跨行的字符串在第二行读完之后才完整
+        let mut lexer = StreamLexer::new(b"let s = \"a\n".chain(&b"b\" s\n"[..]));
+        let mut lines = vec![];
+        while lexer.read_chunk().unwrap() {
+            let tokens = lexer.buffered_tokens();
+            lines.push(tokens.iter().map(|token| token.lexeme.clone()).collect::<Vec<String>>().join(" "));
+        }
+        assert_eq!(lines, ["let s =", "a\nb s"]);
+        let rest: Vec<Token> = lexer.map(Result::unwrap).collect();
+        assert_eq!(rest.iter().map(|token| token.token_type).collect::<Vec<TokenType>>(), [TokenType::EOF]);
+    }
+
+    #[test]
+    fn invalid_utf8_test() {
+        let reader = BufReader::with_capacity(2, &b"let x = \xff;"[..]);
+        let result: io::Result<Vec<Token>> = StreamLexer::new(reader).collect();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let reader = BufReader::with_capacity(2, &b"let x = \xe4\xb8"[..]);
+        let result: io::Result<Vec<Token>> = StreamLexer::new(reader).collect();
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -1,16 +1,9 @@
-use std::{collections::HashMap, fmt, any::Any};
-use std::rc::Rc;
+use std::borrow::Cow;
+use std::fmt;
 
 
-// lazy_static! {
-//     pub static ref KEYWORDS: HashMap<&'static str, self::Token> = HashMap::from([
-//         ("fn", Token::FUNCTION),   
-//         ("let", Token::FUNCTION),   
-//     ]);
-// }
-
 #[derive(Debug, PartialEq, Hash, Eq, Clone, Copy)]
-#[allow(dead_code, non_camel_case_types)]
+#[allow(dead_code, non_camel_case_types, clippy::upper_case_acronyms)]
 pub enum TokenType {
     
 
@@ -20,6 +13,14 @@ pub enum TokenType {
     IDENT,
     NUMBER,
     STRING,
+    // 插值字符串 "a ${x} b ${y} c" 被拆成
+    // TEMPLATE_HEAD("a ") x TEMPLATE_MIDDLE(" b ") y TEMPLATE_TAIL(" c")
+    TEMPLATE_HEAD,
+    TEMPLATE_MIDDLE,
+    TEMPLATE_TAIL,
+
+    // 文档注释 `///`，供文档工具挂到声明上
+    DOC_COMMENT,
     
     // operator
     ASSIGN,
@@ -37,6 +38,12 @@ pub enum TokenType {
     EQ,
     NOT_EQ,
 
+    // 复合赋值 `+=` `-=` `*=` `/=`
+    PLUS_ASSIGN,
+    MINUS_ASSIGN,
+    ASTERISK_ASSIGN,
+    SLASH_ASSIGN,
+
     COMMA,
     SEMICOLON,
     COLON,
@@ -67,30 +74,140 @@ pub enum TokenType {
     PRINT
 }
 
+// token在源码中的字节区间，左闭右开
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
-    pub line: i32
+    // token开始的行号和列号，都从1开始，列号按字符计数
+    pub line: i32,
+    pub column: i32,
+    // 不是从源码中扫描出来的token（比如手工构造的），span为空，column为0
+    pub span: Span,
 }
 
 impl Token {
     pub fn new(token_type: TokenType, lexeme: String, line: i32) -> Self {
         Self {
             token_type,
-            lexeme: lexeme.clone(),
-            line
+            lexeme,
+            line,
+            column: 0,
+            span: Span::default(),
         }
     }
     
 }
 
+// 借用源码的token，lexeme通常直接指向源码切片，扫描时不需要分配内存
+#[derive(Debug, PartialEq, Clone)]
+pub struct BorrowedToken<'src> {
+    pub token_type: TokenType,
+    pub lexeme: Cow<'src, str>,
+    pub line: i32,
+    pub column: i32,
+    pub span: Span,
+}
+
+impl<'src> BorrowedToken<'src> {
+    pub fn into_owned(self) -> Token {
+        Token {
+            token_type: self.token_type,
+            lexeme: self.lexeme.into_owned(),
+            line: self.line,
+            column: self.column,
+            span: self.span,
+        }
+    }
+}
+
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} {}", self.token_type, self.lexeme)
     }
 }
 
+impl TokenType {
+    // Display的反过程，从JSON等文本格式读回token的时候用
+    pub fn from_name(name: &str) -> Option<Self> {
+        let token_type = match name {
+            "ILLEGAL" => TokenType::ILLEGAL,
+            "EOF" => TokenType::EOF,
+            "IDENT" => TokenType::IDENT,
+            "NUMBER" => TokenType::NUMBER,
+            "STRING" => TokenType::STRING,
+            "TEMPLATE_HEAD" => TokenType::TEMPLATE_HEAD,
+            "TEMPLATE_MIDDLE" => TokenType::TEMPLATE_MIDDLE,
+            "TEMPLATE_TAIL" => TokenType::TEMPLATE_TAIL,
+            "DOC_COMMENT" => TokenType::DOC_COMMENT,
+            "ASSIGN" => TokenType::ASSIGN,
+            "PLUS" => TokenType::PLUS,
+            "MINUS" => TokenType::MINUS,
+            "ASTERISK" => TokenType::ASTERISK,
+            "SLASH" => TokenType::SLASH,
+            "EXCLAMATION" => TokenType::EXCLAMATION,
+            "GT" => TokenType::GT,
+            "LT" => TokenType::LT,
+            "GE" => TokenType::GE,
+            "LE" => TokenType::LE,
+            "CARET" => TokenType::CARET,
+            "EQ" => TokenType::EQ,
+            "NOT_EQ" => TokenType::NOT_EQ,
+            "PLUS_ASSIGN" => TokenType::PLUS_ASSIGN,
+            "MINUS_ASSIGN" => TokenType::MINUS_ASSIGN,
+            "ASTERISK_ASSIGN" => TokenType::ASTERISK_ASSIGN,
+            "SLASH_ASSIGN" => TokenType::SLASH_ASSIGN,
+            "COMMA" => TokenType::COMMA,
+            "SEMICOLON" => TokenType::SEMICOLON,
+            "COLON" => TokenType::COLON,
+            "DOT" => TokenType::DOT,
+            "LPAREN" => TokenType::LPAREN,
+            "RPAREN" => TokenType::RPAREN,
+            "LBRACE" => TokenType::LBRACE,
+            "RBRACE" => TokenType::RBRACE,
+            "LBRACKET" => TokenType::LBRACKET,
+            "RBRACKET" => TokenType::RBRACKET,
+            "FUNCTION" => TokenType::FUNCTION,
+            "CLASS" => TokenType::CLASS,
+            "LET" => TokenType::LET,
+            "IF" => TokenType::IF,
+            "ELSE" => TokenType::ELSE,
+            "RETURN" => TokenType::RETURN,
+            "TRUE" => TokenType::TRUE,
+            "FALSE" => TokenType::FALSE,
+            "AND" => TokenType::AND,
+            "OR" => TokenType::OR,
+            "FOR" => TokenType::FOR,
+            "WHILE" => TokenType::WHILE,
+            "BREAK" => TokenType::BREAK,
+            "NULL" => TokenType::NULL,
+            "PRINT" => TokenType::PRINT,
+            _ => return None,
+        };
+        Some(token_type)
+    }
+}
+
 impl fmt::Display for TokenType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{:?}", self)
@@ -98,43 +215,26 @@ impl fmt::Display for TokenType {
 }
 
 
-// pub static KEYWORDS: HashMap<&str, self::Token> = HashMap::from([
-//     ("fn", Token::FUNCTION),   
-//     ("let", Token::LET),   
-//     ("if", Token::IF),   
-//     ("else", Token::ELSE),   
-//     ("return", Token::RETURN),   
-//     ("true", Token::TRUE),   
-//     ("false", Token::FALSE),   
-// ]);
-
-
-pub fn loopkup_ident(token: &mut String, line: i32) -> Token {
-    let keywords = HashMap::from([
-        ("fn", TokenType::FUNCTION),   
-        ("class", TokenType::CLASS),   
-        ("let", TokenType::LET),   
-        ("if", TokenType::IF),   
-        ("else", TokenType::ELSE),   
-        ("return", TokenType::RETURN),   
-        ("true", TokenType::TRUE),   
-        ("false", TokenType::FALSE),
-        ("and", TokenType::AND),
-        ("or", TokenType::OR),
-        ("for", TokenType::FOR),
-        ("while", TokenType::WHILE),
-        ("break", TokenType::BREAK),
-        ("null", TokenType::NULL),
-        ("print", TokenType::PRINT),
-    ]);
-    match keywords.get(token.as_str()) {
-        Some(&tok) => {
-            // 关键字
-            Token::new(tok, tok.to_string(), line)
-        }
-        None => {
-            // 标识符
-            Token::new(TokenType::IDENT, token.to_string(), line)     
-        }
+
+// 返回标识符对应的关键字类型，不是关键字就返回IDENT
+// 用match代替哈希表，编译器会先按长度再按字节比较，不需要在运行时构造任何东西
+pub fn loopkup_ident(ident: &str) -> TokenType {
+    match ident {
+        "fn" => TokenType::FUNCTION,
+        "class" => TokenType::CLASS,
+        "let" => TokenType::LET,
+        "if" => TokenType::IF,
+        "else" => TokenType::ELSE,
+        "return" => TokenType::RETURN,
+        "true" => TokenType::TRUE,
+        "false" => TokenType::FALSE,
+        "and" => TokenType::AND,
+        "or" => TokenType::OR,
+        "for" => TokenType::FOR,
+        "while" => TokenType::WHILE,
+        "break" => TokenType::BREAK,
+        "null" => TokenType::NULL,
+        "print" => TokenType::PRINT,
+        _ => TokenType::IDENT,
     }
 }
@@ -0,0 +1,233 @@
+use std::iter::Peekable;
+
+use super::lexer::Lexer;
+use super::token::Token;
+use super::token::TokenType;
+
+// 无损模式：每个token都带上前后的空白和注释(trivia)
+// 把所有token的 leading + text + trailing 依次拼起来，就能逐字节还原源码
+// 这是格式化工具和重构工具的基础
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TriviaKind {
+    // 空格、制表符和单独的\r
+    Whitespace,
+    // \n 或 \r\n
+    Newline,
+    // `// ...`，不包括结尾的换行符
+    LineComment,
+    // `/* ... */`，可以嵌套
+    BlockComment,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TriviaToken {
+    pub leading: Vec<Trivia>,
+    pub token: Token,
+    // token在源码中的原文，和lexeme不同，比如字符串会带上引号
+    pub text: String,
+    pub trailing: Vec<Trivia>,
+}
+
+impl TriviaToken {
+    // 还原这个token覆盖的全部源码
+    pub fn full_text(&self) -> String {
+        let mut result = String::new();
+        for trivia in &self.leading {
+            result.push_str(&trivia.text);
+        }
+        result.push_str(&self.text);
+        for trivia in &self.trailing {
+            result.push_str(&trivia.text);
+        }
+        result
+    }
+}
+
+// trailing trivia是token之后、直到第一个换行符(包括换行符)的部分
+// 剩下的都算作下一个token的leading trivia
+// 文件末尾的trivia挂在最后的EOF token上
+pub struct TriviaLexer<'a> {
+    source: &'a str,
+    tokens: Peekable<Lexer<'a>>,
+    // 已经分配给token的源码位置
+    pos: usize,
+    done: bool,
+}
+
+impl<'a> TriviaLexer<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self {
+            source,
+            tokens: Lexer::new(source).peekable(),
+            pos: 0,
+            done: false,
+        }
+    }
+}
+
+impl<'a> Iterator for TriviaLexer<'a> {
+    type Item = TriviaToken;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let token = self.tokens.next()?;
+        if token.token_type == TokenType::EOF {
+            // 文件末尾剩下的trivia都挂在EOF上
+            self.done = true;
+            let leading = split_trivia(&self.source[self.pos..]);
+            return Some(TriviaToken { leading, token, text: String::new(), trailing: vec![] });
+        }
+        let leading = split_trivia(&self.source[self.pos..token.span.start]);
+        let next_start = match self.tokens.peek() {
+            Some(next) => next.span.start,
+            None => self.source.len(),
+        };
+        let mut trailing = split_trivia(&self.source[token.span.end..next_start]);
+        if let Some(newline) = trailing.iter().position(|t| t.kind == TriviaKind::Newline) {
+            trailing.truncate(newline + 1);
+        }
+        self.pos = token.span.end + trailing.iter().map(|t| t.text.len()).sum::<usize>();
+        Some(TriviaToken {
+            leading,
+            text: self.source[token.span.start..token.span.end].to_string(),
+            token,
+            trailing,
+        })
+    }
+}
+
+// 把两个token之间的源码切分成trivia
+// 这里的文本都是lexer跳过的部分，所以只可能是空白、换行和注释
+fn split_trivia(text: &str) -> Vec<Trivia> {
+    let mut result = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let (kind, len) = if rest.starts_with("\r\n") {
+            (TriviaKind::Newline, 2)
+        } else if c == '\n' {
+            (TriviaKind::Newline, 1)
+        } else if rest.starts_with("//") {
+            (TriviaKind::LineComment, rest.find('\n').unwrap_or(rest.len()))
+        } else if rest.starts_with("/*") {
+            (TriviaKind::BlockComment, block_comment_len(rest))
+        } else {
+            // 至少前进一个字符，避免死循环
+            (TriviaKind::Whitespace, whitespace_len(rest).max(c.len_utf8()))
+        };
+        result.push(Trivia { kind, text: rest[..len].to_string() });
+        rest = &rest[len..];
+    }
+    result
+}
+
+// 连续的空格、制表符和不属于\r\n的\r
+fn whitespace_len(text: &str) -> usize {
+    let mut len = 0;
+    let bytes = text.as_bytes();
+    while len < bytes.len() {
+        match bytes[len] {
+            b' ' | b'\t' => len += 1,
+            b'\r' if bytes.get(len + 1) != Some(&b'\n') => len += 1,
+            _ => break,
+        }
+    }
+    len
+}
+
+// 嵌套块注释的长度，text以`/*`开头
+fn block_comment_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reconstruct(source: &str) -> String {
+        TriviaLexer::new(source).map(|token| token.full_text()).collect()
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let inputs = [
+            "",
+            "   ",
+            "let five = 5;\n",
+            "let  x\t=  /* a /* nested */ comment */ 1 ; // trailing\r\n\n  print x;",
+            "/// doc\nfn add(x, y) {\n    x + y; // sum\n}\n\n// end of file",
+            "\"unterminated string",
+            "1 /* unterminated\n comment",
+            "let π = \"中文\"; @ #",
+            "r#\"raw \"quoted\"\"# \"\"\"\n    multi\n    line\n    \"\"\" r\"open",
+            "print \"a ${ x /* c */ } b ${ {1: y} }\n c\"; \"open ${",
+        ];
+        for input in inputs {
+            assert_eq!(reconstruct(input), input);
+        }
+    }
+
+    #[test]
+    fn leading_and_trailing_test() {
+        let tokens: Vec<TriviaToken> = TriviaLexer::new("// head\nlet x = 1; // tail\n\n  y").collect();
+        assert_eq!(tokens[0].token.token_type, TokenType::LET);
+        assert_eq!(
+            tokens[0].leading,
+            vec![
+                Trivia { kind: TriviaKind::LineComment, text: "// head".to_string() },
+                Trivia { kind: TriviaKind::Newline, text: "\n".to_string() },
+            ]
+        );
+        assert_eq!(tokens[0].trailing, vec![Trivia { kind: TriviaKind::Whitespace, text: " ".to_string() }]);
+
+        let semicolon = &tokens[4];
+        assert_eq!(semicolon.text, ";");
+        assert_eq!(
+            semicolon.trailing,
+            vec![
+                Trivia { kind: TriviaKind::Whitespace, text: " ".to_string() },
+                Trivia { kind: TriviaKind::LineComment, text: "// tail".to_string() },
+                Trivia { kind: TriviaKind::Newline, text: "\n".to_string() },
+            ]
+        );
+        assert_eq!(
+            tokens[5].leading,
+            vec![
+                Trivia { kind: TriviaKind::Newline, text: "\n".to_string() },
+                Trivia { kind: TriviaKind::Whitespace, text: "  ".to_string() },
+            ]
+        );
+
+        let eof = tokens.last().unwrap();
+        assert_eq!(eof.token.token_type, TokenType::EOF);
+        assert_eq!(eof.token.line, 4);
+
+        let tokens: Vec<TriviaToken> = TriviaLexer::new("x\n").collect();
+        assert_eq!(tokens[1].token.line, 2);
+    }
+}
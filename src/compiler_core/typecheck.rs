@@ -0,0 +1,916 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::SideTable;
+use super::ast::StmtId;
+use super::desugar::binary_operator;
+use super::expr::Assign;
+use super::expr::Binary;
+use super::expr::Call;
+use super::expr::CompoundAssign;
+use super::expr::Expr;
+use super::expr::Function;
+use super::expr::Grouping;
+use super::expr::Index;
+use super::expr::Interpolation;
+use super::expr::List;
+use super::expr::Literal;
+use super::expr::LiteralValue;
+use super::expr::Logical;
+use super::expr::TemplatePart;
+use super::expr::TypeAnnotation;
+use super::expr::Unary;
+use super::expr::Variable;
+use super::expr::Visitor;
+use super::stmt::Block;
+use super::stmt::Break;
+use super::stmt::Expression;
+use super::stmt::For;
+use super::stmt::If;
+use super::stmt::Let;
+use super::stmt::Print;
+use super::stmt::Return;
+use super::stmt::Stmt;
+use super::stmt::StmtVisitor;
+use super::stmt::While;
+use super::token::Token;
+use super::token::TokenType;
+
+// Hindley–Milner类型推导，可选的静态检查，解释器不依赖它：
+// - 每个表达式先得到一个类型，不知道的部分是类型变量，遇到约束的时候用unify把两边合一
+// - let多态：函数声明和`let f = fn...`在声明之后泛化，每次使用的时候换成新的类型变量
+//   其他的let不泛化，变量可以重新赋值，泛化之后就不安全了；泛化过的名字也不能再赋值
+// - 比解释器严格：==两边、and/or两边、列表的元素必须是同一种类型，和null比较除外
+// - `+`要求两边类型相同，并且是数字或者字符串。不知道的时候给类型变量加上这个约束，
+//   约束跟着合一和实例化传下去，`fn(x) { return x + x; }`只能用数字或者字符串调用
+// - 只能使用前面已经声明的名字。同一个语句列表里的函数声明先用单态的类型变量占位，
+//   可以互相递归调用
+// - for和复合赋值直接检查，不用先desugar，types里的id和调用者的树对应
+// - 类型标注和推导出来的类型合一。标注里number、string、bool、null以外的名字是固定的类型变量，
+//   同一个声明里的同名变量是同一个类型。声明里它代表任意的类型，只能和自己合一，
+//   `fn f(x: a): a { return x + 1; }`是错的；泛化之后和普通的类型变量一样换成新的
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+    Number,
+    String,
+    Bool,
+    Null,
+    // 还不知道的类型，推导的过程中由unify确定
+    Var(usize),
+    // 类型标注里的类型变量，在声明里不能换成别的类型，编号和Var的来自同一个计数
+    Rigid(usize),
+    Function(Vec<Type>, Box<Type>),
+    List(Box<Type>),
+    // 只能通过类型标注得到，还没有map的值
+    Map(Box<Type>, Box<Type>),
+}
+
+impl Type {
+    // 类型里出现的类型变量，按出现的顺序，不重复
+    fn vars(&self, vars: &mut Vec<usize>) {
+        match self {
+            Type::Var(var) | Type::Rigid(var) if !vars.contains(var) => vars.push(*var),
+            Type::Function(params, ret) => {
+                for param in params {
+                    param.vars(vars);
+                }
+                ret.vars(vars);
+            }
+            Type::List(element) => element.vars(vars),
+            Type::Map(key, value) => {
+                key.vars(vars);
+                value.vars(vars);
+            }
+            _ => {}
+        }
+    }
+
+    // 类型变量按出现的顺序命名为a、b、c...，names是已经命名的变量
+    // 写法和类型标注一样，输出的类型可以直接写回源码里
+    fn render(&self, names: &mut Vec<usize>) -> String {
+        match self {
+            Type::Number => "number".to_string(),
+            Type::String => "string".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::Null => "null".to_string(),
+            Type::Var(var) | Type::Rigid(var) => {
+                let index = names.iter().position(|name| name == var).unwrap_or_else(|| {
+                    names.push(*var);
+                    names.len() - 1
+                });
+                match index {
+                    0..=25 => ((b'a' + index as u8) as char).to_string(),
+                    _ => format!("t{}", index),
+                }
+            }
+            Type::Function(params, ret) => {
+                let params: Vec<String> = params.iter().map(|param| param.render(names)).collect();
+                format!("fn({}): {}", params.join(", "), ret.render(names))
+            }
+            Type::List(element) => format!("list<{}>", element.render(names)),
+            Type::Map(key, value) => format!("map<{}, {}>", key.render(names), value.render(names)),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(&mut vec![]))
+    }
+}
+
+// 类型模式，vars里的类型变量每次使用的时候换成新的
+#[derive(Debug, PartialEq, Clone)]
+pub struct Scheme {
+    pub vars: Vec<usize>,
+    pub ty: Type,
+    // ty里只能是数字或者字符串的类型变量
+    pub addable: Vec<usize>,
+}
+
+impl Scheme {
+    fn mono(ty: Type) -> Scheme {
+        Scheme { vars: vec![], ty, addable: vec![] }
+    }
+}
+
+// 带约束的类型变量写在后面，`fn(a, a): a where a: number | string`
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = vec![];
+        write!(f, "{}", self.ty.render(&mut names))?;
+        let addable: Vec<String> = names
+            .iter()
+            .filter(|var| self.addable.contains(var))
+            .map(|&var| format!("{}: number | string", Type::Var(var).render(&mut names.clone())))
+            .collect();
+        if !addable.is_empty() {
+            write!(f, " where {}", addable.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TypeError {
+    // 出错的运算符，没有运算符的时候是名字或者关键字
+    pub token: Token,
+    pub message: String,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: at token \"{}\", {}", self.token.line, self.token.lexeme, self.message)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Typing {
+    // 每个表达式的类型，已经代入了检查结束时知道的所有类型变量
+    pub types: SideTable<Expr, Type>,
+    // 顶层的let和函数声明，按源码的顺序
+    pub declarations: Vec<(Token, Scheme)>,
+    // 按位置排序
+    pub errors: Vec<TypeError>,
+}
+
+pub fn check(ast: &Ast, stmts: &[StmtId]) -> Typing {
+    let mut checker = Checker {
+        substitution: vec![],
+        scopes: vec![HashMap::new()],
+        functions: vec![],
+        addable: vec![],
+        predeclared: HashMap::new(),
+        typing: Typing::default(),
+    };
+    checker.stmts(ast, stmts);
+    checker.finish()
+}
+
+// 正在检查的函数
+struct FunctionContext {
+    ret: Type,
+    // 最后一个return语句，函数可能不返回值的时候在这里报错
+    last_return: Option<Token>,
+}
+
+struct Checker {
+    // 类型变量的替换，None是还不知道
+    substitution: Vec<Option<Type>>,
+    // 第一层是全局作用域
+    scopes: Vec<HashMap<String, Scheme>>,
+    functions: Vec<FunctionContext>,
+    // 和substitution一一对应，类型变量只能是数字或者字符串(`+`的操作数)
+    addable: Vec<bool>,
+    // 语句列表开头预先声明的函数，声明语句用同一个类型变量
+    predeclared: HashMap<StmtId, Type>,
+    typing: Typing,
+}
+
+impl Checker {
+    fn fresh(&mut self) -> Type {
+        self.substitution.push(None);
+        self.addable.push(false);
+        Type::Var(self.substitution.len() - 1)
+    }
+
+    // 不会被替换的类型变量，占用substitution里的一个位置，编号不会和Var重复
+    fn rigid(&mut self) -> Type {
+        let var = self.substitution.len();
+        self.fresh();
+        Type::Rigid(var)
+    }
+
+    // `+`的操作数必须是数字或者字符串，还不知道的时候记在类型变量上
+    fn require_addable(&mut self, op: &Token, ty: &Type) {
+        match self.resolve(ty) {
+            Type::Number | Type::String => {}
+            Type::Var(var) => self.addable[var] = true,
+            ty => self.error(op, format!("expected number or string, found {}", ty)),
+        }
+    }
+
+    // 代入所有已知的类型变量
+    fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(var) => match &self.substitution[*var] {
+                Some(ty) => self.resolve(ty),
+                None => ty.clone(),
+            },
+            Type::Function(params, ret) => {
+                Type::Function(params.iter().map(|param| self.resolve(param)).collect(), Box::new(self.resolve(ret)))
+            }
+            Type::List(element) => Type::List(Box::new(self.resolve(element))),
+            Type::Map(key, value) => Type::Map(Box::new(self.resolve(key)), Box::new(self.resolve(value))),
+            _ => ty.clone(),
+        }
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        let (a, b) = (self.resolve(a), self.resolve(b));
+        match (&a, &b) {
+            (Type::Var(a), Type::Var(b)) if a == b => true,
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => {
+                // 不能是无限的类型，比如a = list<a>
+                let mut vars = vec![];
+                ty.vars(&mut vars);
+                if vars.contains(var) {
+                    return false;
+                }
+                if self.addable[*var] {
+                    match ty {
+                        Type::Var(other) => self.addable[*other] = true,
+                        Type::Number | Type::String => {}
+                        _ => return false,
+                    }
+                }
+                self.substitution[*var] = Some(ty.clone());
+                true
+            }
+            (Type::Function(a_params, a_ret), Type::Function(b_params, b_ret)) => {
+                a_params.len() == b_params.len()
+                    && a_params.iter().zip(b_params).all(|(a, b)| self.unify(a, b))
+                    && self.unify(a_ret, b_ret)
+            }
+            (Type::List(a), Type::List(b)) => self.unify(a, b),
+            (Type::Map(a_key, a_value), Type::Map(b_key, b_value)) => self.unify(a_key, b_key) && self.unify(a_value, b_value),
+            _ => a == b,
+        }
+    }
+
+    // found的类型必须能和expected合一，否则在token处报错
+    fn expect(&mut self, token: &Token, expected: &Type, found: &Type) -> bool {
+        let unified = self.unify(expected, found);
+        if !unified {
+            let mut names = vec![];
+            let expected = self.resolve(expected).render(&mut names);
+            let found = self.resolve(found).render(&mut names);
+            // 带约束的类型变量要说明，否则`expected a, found bool`看不出哪里不对
+            let addable: Vec<String> = names
+                .iter()
+                .filter(|&&var| self.addable[var])
+                .map(|&var| Type::Var(var).render(&mut names.clone()))
+                .collect();
+            match addable.as_slice() {
+                [] => self.error(token, format!("expected {}, found {}", expected, found)),
+                _ => self.error(token, format!("expected {}, found {} ({} must be number or string)", expected, found, addable.join(", "))),
+            }
+        }
+        unified
+    }
+
+    fn error(&mut self, token: &Token, message: String) {
+        self.typing.errors.push(TypeError { token: token.clone(), message });
+    }
+
+    // 不在环境里的类型变量都可以换成新的
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.resolve(ty);
+        let mut env = vec![];
+        for scheme in self.scopes.iter().flat_map(|scope| scope.values()) {
+            let mut vars = vec![];
+            self.resolve(&scheme.ty).vars(&mut vars);
+            env.extend(vars.into_iter().filter(|var| !scheme.vars.contains(var)));
+        }
+        let mut vars = vec![];
+        ty.vars(&mut vars);
+        vars.retain(|var| !env.contains(var));
+        let addable = vars.iter().copied().filter(|&var| self.addable[var]).collect();
+        Scheme { vars, ty, addable }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let mut mapping = HashMap::new();
+        for &var in &scheme.vars {
+            let fresh = self.fresh();
+            if let Type::Var(fresh) = fresh {
+                self.addable[fresh] = self.addable[var];
+            }
+            mapping.insert(var, fresh);
+        }
+        Checker::substitute(&self.resolve(&scheme.ty), &mapping)
+    }
+
+    fn substitute(ty: &Type, mapping: &HashMap<usize, Type>) -> Type {
+        match ty {
+            Type::Var(var) | Type::Rigid(var) => mapping.get(var).cloned().unwrap_or_else(|| ty.clone()),
+            Type::Function(params, ret) => Type::Function(
+                params.iter().map(|param| Checker::substitute(param, mapping)).collect(),
+                Box::new(Checker::substitute(ret, mapping)),
+            ),
+            Type::List(element) => Type::List(Box::new(Checker::substitute(element, mapping))),
+            Type::Map(key, value) => {
+                Type::Map(Box::new(Checker::substitute(key, mapping)), Box::new(Checker::substitute(value, mapping)))
+            }
+            _ => ty.clone(),
+        }
+    }
+
+    // 把类型标注转换成类型，vars是这个声明里已经出现的类型变量
+    fn annotation(&mut self, annotation: &TypeAnnotation, vars: &mut HashMap<String, Type>) -> Type {
+        match annotation {
+            TypeAnnotation::Name(name) => match name.lexeme.as_str() {
+                "number" => Type::Number,
+                "string" => Type::String,
+                "bool" => Type::Bool,
+                "null" => Type::Null,
+                var => match vars.get(var) {
+                    Some(ty) => ty.clone(),
+                    None => {
+                        let ty = self.rigid();
+                        vars.insert(var.to_string(), ty.clone());
+                        ty
+                    }
+                },
+            },
+            TypeAnnotation::Generic(name, arguments) => {
+                let mut arguments: Vec<Type> = arguments.iter().map(|argument| self.annotation(argument, vars)).collect();
+                match (name.lexeme.as_str(), arguments.len()) {
+                    ("list", 1) => Type::List(Box::new(arguments.remove(0))),
+                    ("map", 2) => {
+                        let value = arguments.remove(1);
+                        Type::Map(Box::new(arguments.remove(0)), Box::new(value))
+                    }
+                    _ => {
+                        self.error(name, format!("unknown type {}", annotation));
+                        self.fresh()
+                    }
+                }
+            }
+            TypeAnnotation::Function(params, ret) => Type::Function(
+                params.iter().map(|param| self.annotation(param, vars)).collect(),
+                Box::new(self.annotation(ret, vars)),
+            ),
+        }
+    }
+
+    // 有标注的时候用标注的类型，没有的时候是新的类型变量
+    fn optional_annotation(&mut self, annotation: &Option<TypeAnnotation>, vars: &mut HashMap<String, Type>) -> Type {
+        match annotation {
+            Some(annotation) => self.annotation(annotation, vars),
+            None => self.fresh(),
+        }
+    }
+
+    fn define(&mut self, name: &Token, scheme: Scheme) {
+        if self.scopes.len() == 1 {
+            self.typing.declarations.push((name.clone(), scheme.clone()));
+        }
+        self.scopes.last_mut().unwrap().insert(name.lexeme.clone(), scheme);
+    }
+
+    fn lookup_scheme(&mut self, name: &Token) -> Option<Scheme> {
+        let scheme = self.scopes.iter().rev().find_map(|scope| scope.get(&name.lexeme)).cloned();
+        if scheme.is_none() {
+            self.error(name, "Undefined variable.".to_string());
+        }
+        scheme
+    }
+
+    // 赋值的目标，泛化过的名字每次使用的类型都不一样，赋值之后就不对了
+    fn assignable(&mut self, name: &Token) -> Type {
+        let Some(scheme) = self.lookup_scheme(name) else {
+            return self.fresh();
+        };
+        if !scheme.vars.is_empty() {
+            let message = format!("can't assign to {}, its type {} is generic", name.lexeme, self.resolve(&scheme.ty));
+            self.error(name, message);
+            return self.fresh();
+        }
+        scheme.ty
+    }
+
+    // 除了==和!=以外的二元运算，复合赋值的时候op是`+=`，token_type是对应的二元运算
+    fn arithmetic(&mut self, op: &Token, token_type: TokenType, left: &Type, right: &Type) -> Type {
+        match token_type {
+            TokenType::PLUS => {
+                // 两边不一样的时候已经报过错了
+                if self.expect(op, left, right) {
+                    self.require_addable(op, left);
+                }
+                left.clone()
+            }
+            TokenType::GT | TokenType::GE | TokenType::LT | TokenType::LE => {
+                self.expect(op, &Type::Number, left);
+                self.expect(op, &Type::Number, right);
+                Type::Bool
+            }
+            _ => {
+                self.expect(op, &Type::Number, left);
+                self.expect(op, &Type::Number, right);
+                Type::Number
+            }
+        }
+    }
+
+    fn lookup(&mut self, name: &Token) -> Type {
+        match self.lookup_scheme(name) {
+            Some(scheme) => self.instantiate(&scheme),
+            None => self.fresh(),
+        }
+    }
+
+    // 推导表达式的类型，并记录下来
+    fn infer(&mut self, ast: &Ast, expr: ExprId) -> Type {
+        let ty = expr.accept(ast, self);
+        self.typing.types.insert(expr, ty.clone());
+        ty
+    }
+
+    fn stmts(&mut self, ast: &Ast, stmts: &[StmtId]) {
+        for &stmt in stmts {
+            if let Stmt::Function(function) = &ast[stmt] {
+                let Some(name) = &function.name else { continue };
+                let own = self.fresh();
+                self.scopes.last_mut().unwrap().insert(name.lexeme.clone(), Scheme::mono(own.clone()));
+                self.predeclared.insert(stmt, own);
+            }
+        }
+        for &stmt in stmts {
+            stmt.accept(ast, self);
+        }
+    }
+
+    fn function(&mut self, ast: &Ast, function: &Function) -> Type {
+        let mut vars = HashMap::new();
+        let params: Vec<Type> =
+            function.param_annotations.iter().map(|annotation| self.optional_annotation(annotation, &mut vars)).collect();
+        let ret = self.optional_annotation(&function.return_annotation, &mut vars);
+        let mut scope = HashMap::new();
+        for (param, ty) in function.params.iter().zip(&params) {
+            scope.insert(param.lexeme.clone(), Scheme::mono(ty.clone()));
+        }
+        self.scopes.push(scope);
+        self.functions.push(FunctionContext { ret: ret.clone(), last_return: None });
+        self.stmts(ast, &function.body);
+        let context = self.functions.pop().unwrap();
+        self.scopes.pop();
+        // 执行到函数体的最后返回null
+        if completes(ast, &function.body) && !self.unify(&ret, &Type::Null) {
+            // 没有return语句的时候在返回类型的标注处报错
+            let token = context.last_return.or_else(|| function.return_annotation.as_ref().map(|annotation| annotation_token(annotation).clone()));
+            if let Some(token) = token {
+                let message = format!("expected {}, found null (the function can end without a return)", self.resolve(&ret));
+                self.error(&token, message);
+            }
+        }
+        Type::Function(params, Box::new(ret))
+    }
+
+    // 代入最后的结果
+    fn finish(mut self) -> Typing {
+        let types: Vec<(ExprId, Type)> = self.typing.types.iter().map(|(id, ty)| (id, self.resolve(ty))).collect();
+        for (id, ty) in types {
+            self.typing.types.insert(id, ty);
+        }
+        let declarations = std::mem::take(&mut self.typing.declarations);
+        self.typing.declarations = declarations
+            .into_iter()
+            .map(|(name, scheme)| {
+                let ty = self.resolve(&scheme.ty);
+                let mut vars = vec![];
+                ty.vars(&mut vars);
+                let addable = vars.into_iter().filter(|&var| self.addable[var]).collect();
+                (name, Scheme { vars: scheme.vars, ty, addable })
+            })
+            .collect();
+        self.typing.errors.sort_by_key(|error| error.token.span.start);
+        self.typing
+    }
+}
+
+// 语句列表可能执行到最后，没有在中间return
+fn completes(ast: &Ast, stmts: &[StmtId]) -> bool {
+    stmts.iter().all(|&stmt| match &ast[stmt] {
+        Stmt::Return(_) => false,
+        Stmt::Block(block) => completes(ast, &block.stmts),
+        Stmt::If(stmt) => match stmt.else_branch {
+            Some(else_branch) => completes(ast, &[stmt.then_branch]) || completes(ast, &[else_branch]),
+            None => true,
+        },
+        // `while true`和省略了条件的for只能通过break结束
+        Stmt::While(stmt) => !is_true(ast, stmt.condition) || breaks(ast, &[stmt.body]),
+        Stmt::For(stmt) => stmt.condition.is_some_and(|condition| !is_true(ast, condition)) || breaks(ast, &[stmt.body]),
+        _ => true,
+    })
+}
+
+// 有没有跳出这一层循环的break，里面的循环和函数里的break不算
+fn breaks(ast: &Ast, stmts: &[StmtId]) -> bool {
+    stmts.iter().any(|&stmt| match &ast[stmt] {
+        Stmt::Break(_) => true,
+        Stmt::Block(block) => breaks(ast, &block.stmts),
+        Stmt::If(stmt) => breaks(ast, &[stmt.then_branch]) || stmt.else_branch.is_some_and(|else_branch| breaks(ast, &[else_branch])),
+        _ => false,
+    })
+}
+
+// 去掉括号之后是字面量true
+fn is_true(ast: &Ast, expr: ExprId) -> bool {
+    match &ast[expr] {
+        Expr::Grouping(grouping) => is_true(ast, grouping.expr),
+        Expr::Literal(literal) => literal.value == LiteralValue::Bool(true),
+        _ => false,
+    }
+}
+
+// 类型标注里第一个名字，函数类型没有名字，用返回类型的
+fn annotation_token(annotation: &TypeAnnotation) -> &Token {
+    match annotation {
+        TypeAnnotation::Name(name) | TypeAnnotation::Generic(name, _) => name,
+        TypeAnnotation::Function(_, ret) => annotation_token(ret),
+    }
+}
+
+fn is_null(ast: &Ast, expr: ExprId) -> bool {
+    matches!(&ast[expr], Expr::Literal(literal) if literal.value == LiteralValue::Null)
+}
+
+impl Visitor<Type> for Checker {
+    fn visit_binary(&mut self, ast: &Ast, _id: ExprId, expr: &Binary) -> Type {
+        let left = self.infer(ast, expr.left);
+        let right = self.infer(ast, expr.right);
+        if matches!(expr.op.token_type, TokenType::EQ | TokenType::NOT_EQ) {
+            if !is_null(ast, expr.left) && !is_null(ast, expr.right) {
+                self.expect(&expr.op, &left, &right);
+            }
+            return Type::Bool;
+        }
+        self.arithmetic(&expr.op, expr.op.token_type, &left, &right)
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, _id: ExprId, expr: &Grouping) -> Type {
+        self.infer(ast, expr.expr)
+    }
+
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, expr: &Literal) -> Type {
+        match expr.value {
+            LiteralValue::Number(_) => Type::Number,
+            LiteralValue::String(_) => Type::String,
+            LiteralValue::Bool(_) => Type::Bool,
+            LiteralValue::Null => Type::Null,
+        }
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, _id: ExprId, expr: &Unary) -> Type {
+        let right = self.infer(ast, expr.right);
+        match expr.op.token_type {
+            TokenType::MINUS => {
+                self.expect(&expr.op, &Type::Number, &right);
+                Type::Number
+            }
+            _ => Type::Bool,
+        }
+    }
+
+    // 任何值都可以放进字符串
+    fn visit_interpolation(&mut self, ast: &Ast, _id: ExprId, expr: &Interpolation) -> Type {
+        for part in &expr.parts {
+            if let TemplatePart::Expr(expr) = part {
+                self.infer(ast, *expr);
+            }
+        }
+        Type::String
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, _id: ExprId, expr: &Variable) -> Type {
+        self.lookup(&expr.name)
+    }
+
+    // 泛化过的名字每次使用的类型都不一样，赋值之后就不对了
+    fn visit_assign(&mut self, ast: &Ast, _id: ExprId, expr: &Assign) -> Type {
+        let value = self.infer(ast, expr.value);
+        let variable = self.assignable(&expr.name);
+        self.expect(&expr.name, &variable, &value);
+        variable
+    }
+
+    // 和`x = x + v`一样检查，不用先desugar，类型记在原来的节点上
+    fn visit_compound_assign(&mut self, ast: &Ast, _id: ExprId, expr: &CompoundAssign) -> Type {
+        let value = self.infer(ast, expr.value);
+        let variable = self.assignable(&expr.name);
+        let errors = self.typing.errors.len();
+        let result = self.arithmetic(&expr.op, binary_operator(&expr.op).token_type, &variable, &value);
+        // 运算已经报过错的时候，结果的类型多半也对不上，不再重复报错
+        if self.typing.errors.len() == errors {
+            self.expect(&expr.name, &variable, &result);
+        }
+        variable
+    }
+
+    fn visit_logical(&mut self, ast: &Ast, _id: ExprId, expr: &Logical) -> Type {
+        let left = self.infer(ast, expr.left);
+        let right = self.infer(ast, expr.right);
+        self.expect(&expr.op, &left, &right);
+        left
+    }
+
+    fn visit_call(&mut self, ast: &Ast, _id: ExprId, expr: &Call) -> Type {
+        let callee = self.infer(ast, expr.callee);
+        let arguments: Vec<Type> = expr.arguments.iter().map(|argument| self.infer(ast, *argument)).collect();
+        let ret = self.fresh();
+        self.expect(&expr.paren, &Type::Function(arguments, Box::new(ret.clone())), &callee);
+        ret
+    }
+
+    fn visit_function(&mut self, ast: &Ast, _id: ExprId, expr: &Function) -> Type {
+        self.function(ast, expr)
+    }
+
+    fn visit_list(&mut self, ast: &Ast, _id: ExprId, expr: &List) -> Type {
+        let element = self.fresh();
+        for &item in &expr.elements {
+            let ty = self.infer(ast, item);
+            self.expect(&expr.bracket, &element, &ty);
+        }
+        Type::List(Box::new(element))
+    }
+
+    fn visit_index(&mut self, ast: &Ast, _id: ExprId, expr: &Index) -> Type {
+        let object = self.infer(ast, expr.object);
+        let index = self.infer(ast, expr.index);
+        let element = self.fresh();
+        self.expect(&expr.bracket, &Type::List(Box::new(element.clone())), &object);
+        self.expect(&expr.bracket, &Type::Number, &index);
+        element
+    }
+}
+
+impl StmtVisitor<()> for Checker {
+    fn visit_expression_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Expression) {
+        self.infer(ast, stmt.expr);
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Print) {
+        self.infer(ast, stmt.expr);
+    }
+
+    fn visit_let_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Let) {
+        let annotation = self.optional_annotation(&stmt.annotation, &mut HashMap::new());
+        let scheme = match stmt.initializer {
+            Some(initializer) => {
+                let ty = self.infer(ast, initializer);
+                self.expect(&stmt.name, &annotation, &ty);
+                if matches!(ast[initializer], Expr::Function(_)) {
+                    self.generalize(&annotation)
+                } else {
+                    Scheme::mono(annotation)
+                }
+            }
+            None => Scheme::mono(annotation),
+        };
+        self.define(&stmt.name, scheme);
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Block) {
+        self.scopes.push(HashMap::new());
+        self.stmts(ast, &stmt.stmts);
+        self.scopes.pop();
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &If) {
+        self.infer(ast, stmt.condition);
+        stmt.then_branch.accept(ast, self);
+        if let Some(else_branch) = stmt.else_branch {
+            else_branch.accept(ast, self);
+        }
+    }
+
+    // init声明的变量只在循环里可见
+    fn visit_for_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &For) {
+        self.scopes.push(HashMap::new());
+        if let Some(initializer) = stmt.initializer {
+            initializer.accept(ast, self);
+        }
+        if let Some(condition) = stmt.condition {
+            self.infer(ast, condition);
+        }
+        stmt.body.accept(ast, self);
+        if let Some(increment) = stmt.increment {
+            self.infer(ast, increment);
+        }
+        self.scopes.pop();
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &While) {
+        self.infer(ast, stmt.condition);
+        stmt.body.accept(ast, self);
+    }
+
+    // 函数体里递归调用的时候还没有泛化，用的是预先声明的同一个类型
+    fn visit_function_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Function) {
+        let Some(name) = &stmt.name else {
+            self.function(ast, stmt);
+            return;
+        };
+        let own = match self.predeclared.remove(&id) {
+            Some(own) => own,
+            None => self.fresh(),
+        };
+        self.scopes.last_mut().unwrap().insert(name.lexeme.clone(), Scheme::mono(own.clone()));
+        let ty = self.function(ast, stmt);
+        self.expect(name, &own, &ty);
+        // 泛化之前去掉单态的绑定，否则它的类型变量都算在环境里
+        self.scopes.last_mut().unwrap().remove(&name.lexeme);
+        let scheme = self.generalize(&ty);
+        self.define(name, scheme);
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Return) {
+        let value = match stmt.value {
+            Some(value) => self.infer(ast, value),
+            None => Type::Null,
+        };
+        // 函数外面的return由resolver报错
+        let Some(context) = self.functions.last_mut() else {
+            return;
+        };
+        context.last_return = Some(stmt.keyword.clone());
+        let ret = context.ret.clone();
+        self.expect(&stmt.keyword, &ret, &value);
+    }
+
+    fn visit_break_stmt(&mut self, _ast: &Ast, _id: StmtId, _stmt: &Break) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::parser::Parser;
+
+    use super::*;
+
+    fn typing(source: &str) -> Typing {
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        assert!(!parser.had_error(), "{}", source);
+        check(parser.ast(), &program)
+    }
+
+    fn declarations(source: &str) -> Vec<String> {
+        let typing = typing(source);
+        assert_eq!(typing.errors, [], "{}", source);
+        typing.declarations.iter().map(|(name, scheme)| format!("{}: {}", name.lexeme, scheme)).collect()
+    }
+
+    fn errors(source: &str) -> Vec<String> {
+        typing(source).errors.iter().map(|error| error.to_string()).collect()
+    }
+
+    #[test]
+    fn inference_test() {
+        let inputs: [(&str, &[&str]); 11] = [
+            ("let x = 1; let s = \"a\" + \"b\"; let b = x < 2 and !s;", &["x: number", "s: string", "b: bool"]),
+            // `+`的约束跟在类型后面
+            (
+                "fn add(x, y) { return x + y; } let twice = fn(x, y) { return \"${x + x} ${y + y}\"; };",
+                &["add: fn(a, a): a where a: number | string", "twice: fn(a, b): string where a: number | string, b: number | string"],
+            ),
+            ("fn fib(n) { if n < 2 { return n; } return fib(n - 1) + fib(n - 2); }", &["fib: fn(number): number"]),
+            // let多态：id在两个地方用成不同的类型
+            ("fn id(x) { return x; } let a = id(1); let b = id(\"s\");", &["id: fn(a): a", "a: number", "b: string"]),
+            (
+                "let compose = fn(f, g) { return fn(x) { return f(g(x)); }; };",
+                &["compose: fn(fn(a): b, fn(c): a): fn(c): b"],
+            ),
+            ("let xs = [[1], []]; let first = fn(xs) { return xs[0]; }; let y = first(xs);", &["xs: list<list<number>>", "first: fn(list<a>): a", "y: list<number>"]),
+            ("let x; x = \"late\"; fn log(m) { print m; }", &["x: string", "log: fn(a): null"]),
+            // 互相递归的函数，调用的时候还没有声明
+            (
+                "fn even(n) { if n == 0 { return true; } return odd(n - 1); } fn odd(n) { if n == 0 { return false; } return even(n - 1); }",
+                &["even: fn(number): bool", "odd: fn(number): bool"],
+            ),
+            // `while true`不会执行到函数的最后
+            // 没有desugar的for和复合赋值，for的变量不在顶层
+            ("let s = 0; for let i = 0; i < 3; i += 1 { s += i; } let t = \"a\"; t += \"b\";", &["s: number", "t: string"]),
+            ("fn find(xs, x) { for let i = 0; ; i += 1 { if xs[i] == x { return i; } } }", &["find: fn(list<a>, a): number"]),
+            ("fn forever(): number { while (true) { print 1; } } fn first(xs) { while true { return xs[0]; } }", &["forever: fn(): number", "first: fn(list<a>): a"]),
+        ];
+        for (source, expect) in inputs {
+            assert_eq!(declarations(source), expect, "{}", source);
+        }
+    }
+
+    #[test]
+    fn expr_types_test() {
+        let mut parser = Parser::new("fn f(x) { return [x, 1]; }");
+        let program = parser.parse_program();
+        let typing = check(parser.ast(), &program);
+        // 参数x的类型最后确定是number
+        let types: Vec<String> = typing.types.iter().map(|(_, ty)| ty.to_string()).collect();
+        assert_eq!(types, ["number", "number", "list<number>"]);
+    }
+
+    #[test]
+    fn annotation_test() {
+        let inputs: [(&str, &[&str]); 4] = [
+            // 标注里的a是类型变量，泛化之后和推导出来的一样
+            ("fn id(x: a): a { return x; } let n: number = id(1);", &["id: fn(a): a", "n: number"]),
+            ("let xs: list<string> = []; let m: map<string, list<number>>;", &["xs: list<string>", "m: map<string, list<number>>"]),
+            // 同一个声明里的同名类型变量是同一个类型
+            ("fn apply(f: fn(a): b, x: a): b { return f(x); }", &["apply: fn(fn(a): b, a): b"]),
+            ("let f: fn(number): number = fn(x) { return x; }; let g = fn(x: string) { return x; };", &["f: fn(number): number", "g: fn(string): string"]),
+        ];
+        for (source, expect) in inputs {
+            assert_eq!(declarations(source), expect, "{}", source);
+        }
+
+        let inputs: [(&str, &[&str]); 7] = [
+            ("let x: number = \"one\";", &["line 1: at token \"x\", expected number, found string"]),
+            ("fn f(x: string) { return x - 1; }", &["line 1: at token \"-\", expected number, found string"]),
+            ("fn f(): number { print 1; }", &["line 1: at token \"number\", expected number, found null (the function can end without a return)"]),
+            // 标注里的类型变量代表任意的类型，不能被推导成具体的类型
+            ("fn f(x: a): a { return x + 1; }", &["line 1: at token \"+\", expected a, found number"]),
+            ("fn f(x: a) { return x + x; }", &["line 1: at token \"+\", expected number or string, found a"]),
+            ("fn f(x: a, y: b) { return x == y; }\nlet g: fn(a): a = fn(x) { return x; }; let h: fn(a): a = fn(x) { return 0; };", &["line 1: at token \"==\", expected a, found b", "line 2: at token \"h\", expected fn(a): a, found fn(a): number"]),
+            ("let xs: list<number, string>; let s: set<number>;", &["line 1: at token \"list\", unknown type list<number, string>", "line 1: at token \"set\", unknown type set<number>"]),
+        ];
+        for (source, expect) in inputs {
+            assert_eq!(errors(source), expect, "{}", source);
+        }
+    }
+
+    #[test]
+    fn error_test() {
+        let inputs: [(&str, &[&str]); 12] = [
+            ("\"a\" - 1;", &["line 1: at token \"-\", expected number, found string"]),
+            ("let xs = [1, \"two\"];", &["line 1: at token \"[\", expected number, found string"]),
+            ("fn f(a) { return a; }\nf(1, 2);", &["line 2: at token \")\", expected fn(number, number): a, found fn(b): b"]),
+            ("let x = 1; x();\n[1][\"0\"];", &["line 1: at token \")\", expected fn(): a, found number", "line 2: at token \"[\", expected number, found string"]),
+            ("fn f(x) { if x { return 1; } }", &["line 1: at token \"return\", expected number, found null (the function can end without a return)"]),
+            (
+                "let f = fn(x) { return x + x; }; f(true); [1] + [2];",
+                &[
+                    "line 1: at token \")\", expected fn(bool): a, found fn(b): b (b must be number or string)",
+                    "line 1: at token \"+\", expected number or string, found list<number>",
+                ],
+            ),
+            // `+`的约束在泛化、实例化和合一之后还在
+            (
+                "let f = fn(x) { return x + x; }; let g = fn(y) { return f(y); }; print g(\"s\"); print g([1]);",
+                &["line 1: at token \")\", expected fn(list<number>): a, found fn(b): b (b must be number or string)"],
+            ),
+            // 泛化过的函数被赋值之后，每次使用的类型就不对了
+            (
+                "fn id(x) { return x; } id = fn(x) { return 1; }; let s = id(\"a\") + \"b\";",
+                &["line 1: at token \"id\", can't assign to id, its type fn(a): a is generic"],
+            ),
+            (
+                "let s = \"a\"; s -= 1; for let i = 0; i < \"3\"; i += 1 { }\nfn f(): number { for ;; { break; } }",
+                &[
+                    "line 1: at token \"-=\", expected number, found string",
+                    "line 1: at token \"<\", expected number, found string",
+                    "line 2: at token \"number\", expected number, found null (the function can end without a return)",
+                ],
+            ),
+            ("fn f() { while true { if true { break; } } }\nfn g(): number { while true { while true { break; } } }", &[]),
+            ("fn f(): number { while true { if true { break; } } }", &["line 1: at token \"number\", expected number, found null (the function can end without a return)"]),
+            ("print y; fn f(x) { return x(x); }", &["line 1: at token \"y\", Undefined variable.", "line 1: at token \")\", expected fn(a): b, found a"]),
+        ];
+        for (source, expect) in inputs {
+            assert_eq!(errors(source), expect, "{}", source);
+        }
+    }
+}
@@ -1,36 +1,175 @@
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::StmtId;
+use super::token::Span;
 use super::expr::Visitor;
-use super::expr::Expr;
+use super::expr::Assign;
 use super::expr::Binary;
+use super::expr::Call;
+use super::expr::CompoundAssign;
+use super::expr::Function;
+use super::expr::Index;
+use super::expr::List;
 use super::expr::Unary;
 use super::expr::Literal;
+use super::expr::LiteralValue;
+use super::expr::Logical;
 use super::expr::Grouping;
-use super::token::Token;
-use super::token::TokenType;
+use super::expr::Interpolation;
+use super::expr::TemplatePart;
+use super::expr::TypeAnnotation;
+use super::expr::Variable;
+use super::stmt::StmtVisitor;
+use super::stmt::Block;
+use super::stmt::Break;
+use super::stmt::Expression;
+use super::stmt::For;
+use super::stmt::If;
+use super::stmt::Let;
+use super::stmt::Print;
+use super::stmt::Return;
+use super::stmt::While;
+use super::json::optional_annotation_to_json;
+use super::json::span_to_json;
+use super::json::token_to_json;
+use super::json::Json;
 
+#[derive(Default)]
 pub struct AstPrinter { }
 
 impl Visitor<String> for AstPrinter {
 
-    fn visit_binary(&mut self, expr: &Binary<String>) -> String {
-        self.parenthesize(format!("{}" , expr.op.lexeme.clone()), vec![expr.left.as_ref(), expr.right.as_ref()])
+    fn visit_binary(&mut self, ast: &Ast, _id: ExprId, expr: &Binary) -> String {
+        self.parenthesize(ast, &expr.op.lexeme, &[expr.left, expr.right])
     }
 
-    fn visit_grouping(&mut self, expr: &Grouping<String>) -> String {
-        self.parenthesize("group".to_string(), vec![expr.expr.as_ref()])
+    fn visit_grouping(&mut self, ast: &Ast, _id: ExprId, expr: &Grouping) -> String {
+        self.parenthesize(ast, "group", &[expr.expr])
     }
 
-    fn visit_literal(&mut self, expr: &Literal) -> String {
-        if let Some(value) = expr.value.downcast_ref::<String>() {
-            value.clone()
-        } else if let Some(value) = expr.value.downcast_ref::<f64>() {
-            value.to_string()
-        } else {
-            "null".to_string()
+    fn visit_literal(&mut self, _ast: &Ast, _id: ExprId, expr: &Literal) -> String {
+        match &expr.value {
+            // 加上引号，和变量区分开，sexpr.rs才能读回来
+            LiteralValue::String(value) => format!("{:?}", value),
+            LiteralValue::Number(value) => value.to_string(),
+            LiteralValue::Bool(value) => value.to_string(),
+            LiteralValue::Null => "null".to_string(),
         }
     }
 
-    fn visit_unary(&mut self, expr: &Unary<String>) -> String {
-        self.parenthesize(expr.op.lexeme.clone(), vec![expr.right.as_ref()])
+    fn visit_unary(&mut self, ast: &Ast, _id: ExprId, expr: &Unary) -> String {
+        self.parenthesize(ast, &expr.op.lexeme, &[expr.right])
+    }
+
+    fn visit_interpolation(&mut self, ast: &Ast, _id: ExprId, expr: &Interpolation) -> String {
+        // 字符串片段加上引号，表达式放在${}里，和字符串字面量区分开
+        let mut result = "(interp".to_string();
+        for part in &expr.parts {
+            result.push(' ');
+            match part {
+                TemplatePart::Chunk(chunk) => result.push_str(&format!("{:?}", chunk)),
+                TemplatePart::Expr(expr) => result.push_str(&format!("${{{}}}", expr.accept(ast, self))),
+            }
+        }
+        result.push(')');
+        result
+    }
+
+    fn visit_variable(&mut self, _ast: &Ast, _id: ExprId, expr: &Variable) -> String {
+        expr.name.lexeme.clone()
+    }
+
+    fn visit_assign(&mut self, ast: &Ast, _id: ExprId, expr: &Assign) -> String {
+        format!("(= {} {})", expr.name.lexeme, expr.value.accept(ast, self))
+    }
+
+    fn visit_compound_assign(&mut self, ast: &Ast, _id: ExprId, expr: &CompoundAssign) -> String {
+        format!("({} {} {})", expr.op.lexeme, expr.name.lexeme, expr.value.accept(ast, self))
+    }
+
+    fn visit_logical(&mut self, ast: &Ast, _id: ExprId, expr: &Logical) -> String {
+        self.parenthesize(ast, &expr.op.lexeme, &[expr.left, expr.right])
+    }
+
+    fn visit_call(&mut self, ast: &Ast, _id: ExprId, expr: &Call) -> String {
+        let mut exprs = vec![expr.callee];
+        exprs.extend(expr.arguments.iter());
+        self.parenthesize(ast, "call", &exprs)
+    }
+
+    fn visit_function(&mut self, ast: &Ast, _id: ExprId, expr: &Function) -> String {
+        self.function(ast, expr)
+    }
+
+    fn visit_list(&mut self, ast: &Ast, _id: ExprId, expr: &List) -> String {
+        self.parenthesize(ast, "list", &expr.elements)
+    }
+
+    fn visit_index(&mut self, ast: &Ast, _id: ExprId, expr: &Index) -> String {
+        self.parenthesize(ast, "index", &[expr.object, expr.index])
+    }
+
+}
+
+impl StmtVisitor<String> for AstPrinter {
+
+    fn visit_expression_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Expression) -> String {
+        stmt.expr.accept(ast, self)
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Print) -> String {
+        self.parenthesize(ast, "print", &[stmt.expr])
+    }
+
+    fn visit_let_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Let) -> String {
+        let mut result = format!("(let {}{}", stmt.name.lexeme, annotation_sexpr(&stmt.annotation));
+        if let Some(initializer) = stmt.initializer {
+            result.push(' ');
+            result.push_str(&initializer.accept(ast, self));
+        }
+        result.push(')');
+        result
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Block) -> String {
+        self.statements(ast, "(block".to_string(), &stmt.stmts)
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &If) -> String {
+        let mut result = format!("(if {} {}", stmt.condition.accept(ast, self), stmt.then_branch.accept(ast, self));
+        if let Some(else_branch) = stmt.else_branch {
+            result.push(' ');
+            result.push_str(&else_branch.accept(ast, self));
+        }
+        result.push(')');
+        result
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &While) -> String {
+        format!("(while {} {})", stmt.condition.accept(ast, self), stmt.body.accept(ast, self))
+    }
+
+    // (for init cond inc body)，省略的部分是_
+    fn visit_for_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &For) -> String {
+        let initializer = stmt.initializer.map_or("_".to_string(), |initializer| initializer.accept(ast, self));
+        let condition = stmt.condition.map_or("_".to_string(), |condition| condition.accept(ast, self));
+        let increment = stmt.increment.map_or("_".to_string(), |increment| increment.accept(ast, self));
+        format!("(for {} {} {} {})", initializer, condition, increment, stmt.body.accept(ast, self))
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Function) -> String {
+        self.function(ast, stmt)
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, _id: StmtId, stmt: &Return) -> String {
+        match stmt.value {
+            Some(value) => self.parenthesize(ast, "return", &[value]),
+            None => "(return)".to_string(),
+        }
+    }
+
+    fn visit_break_stmt(&mut self, _ast: &Ast, _id: StmtId, _stmt: &Break) -> String {
+        "(break)".to_string()
     }
 
 }
@@ -39,41 +178,655 @@ impl AstPrinter {
     pub fn new() -> AstPrinter {
         AstPrinter { }
     }
+
+    pub fn print_expr(&mut self, ast: &Ast, expr: ExprId) -> String {
+        expr.accept(ast, self)
+    }
+
+    // 每个语句一行
+    pub fn print_program(&mut self, ast: &Ast, stmts: &[StmtId]) -> String {
+        let lines: Vec<String> = stmts.iter().map(|stmt| stmt.accept(ast, self)).collect();
+        lines.join("\n")
+    }
     
-    fn parenthesize(&mut self, name: String, exprs: Vec<&dyn Expr<String>>) -> String {
+    fn parenthesize(&mut self, ast: &Ast, name: &str, exprs: &[ExprId]) -> String {
         let mut result = String::new();
-        result.push_str("(");
-        result.push_str(&name);
+        result.push('(');
+        result.push_str(name);
         for expr in exprs {
-            result.push_str(" ");
-            result.push_str(&expr.accept( self));
+            result.push(' ');
+            result.push_str(&expr.accept(ast, self));
         }
-        result.push_str(")");
+        result.push(')');
+        result
+    }
+
+    // (fn name (a b) stmt...)，匿名函数没有name
+    // 有类型标注的时候是(fn name (a : number b) : bool stmt...)
+    fn function(&mut self, ast: &Ast, function: &Function) -> String {
+        let mut head = "(fn".to_string();
+        if let Some(name) = &function.name {
+            head.push(' ');
+            head.push_str(&name.lexeme);
+        }
+        let params: Vec<String> = function
+            .params
+            .iter()
+            .zip(&function.param_annotations)
+            .map(|(param, annotation)| format!("{}{}", param.lexeme, annotation_sexpr(annotation)))
+            .collect();
+        head.push_str(&format!(" ({}){}", params.join(" "), annotation_sexpr(&function.return_annotation)));
+        self.statements(ast, head, &function.body)
+    }
+
+    // head后面依次接上每个语句，再加上右括号
+    fn statements(&mut self, ast: &Ast, head: String, stmts: &[StmtId]) -> String {
+        let mut result = head;
+        for stmt in stmts {
+            result.push(' ');
+            result.push_str(&stmt.accept(ast, self));
+        }
+        result.push(')');
         result
     }
 }
 
+// ` : 类型`，没有标注就是空字符串
+fn annotation_sexpr(annotation: &Option<TypeAnnotation>) -> String {
+    annotation.as_ref().map_or(String::new(), |annotation| format!(" : {}", type_sexpr(annotation)))
+}
+
+// number、(list number)、(map string number)、(fn (number string) bool)
+fn type_sexpr(annotation: &TypeAnnotation) -> String {
+    match annotation {
+        TypeAnnotation::Name(name) => name.lexeme.clone(),
+        TypeAnnotation::Generic(name, arguments) => {
+            let arguments: Vec<String> = arguments.iter().map(type_sexpr).collect();
+            format!("({} {})", name.lexeme, arguments.join(" "))
+        }
+        TypeAnnotation::Function(params, ret) => {
+            let params: Vec<String> = params.iter().map(type_sexpr).collect();
+            format!("(fn ({}) {})", params.join(" "), type_sexpr(ret))
+        }
+    }
+}
+
+// 输出JSON格式的语法树，格式见json.rs，读回用json::read_program
+#[derive(Default)]
+pub struct JsonPrinter { }
+
+impl JsonPrinter {
+    pub fn new() -> JsonPrinter {
+        JsonPrinter { }
+    }
+
+    pub fn print_expr(&mut self, ast: &Ast, expr: ExprId) -> Json {
+        expr.accept(ast, self)
+    }
+
+    pub fn print_program(&mut self, ast: &Ast, stmts: &[StmtId]) -> Json {
+        Json::Object(vec![
+            ("kind".to_string(), Json::String("Program".to_string())),
+            ("body".to_string(), self.stmts(ast, stmts)),
+        ])
+    }
+
+    // kind和span在前，后面是节点的字段
+    fn node(&self, kind: &str, span: Option<Span>, fields: Vec<(&str, Json)>) -> Json {
+        let mut entries = vec![("kind".to_string(), Json::String(kind.to_string()))];
+        if let Some(span) = span {
+            entries.push(("span".to_string(), span_to_json(span)));
+        }
+        entries.extend(fields.into_iter().map(|(key, value)| (key.to_string(), value)));
+        Json::Object(entries)
+    }
+
+    fn expr_node(&self, ast: &Ast, id: ExprId, fields: Vec<(&str, Json)>) -> Json {
+        self.node(ast[id].kind(), ast.expr_spans.get(id).copied(), fields)
+    }
+
+    fn stmt_node(&self, ast: &Ast, id: StmtId, fields: Vec<(&str, Json)>) -> Json {
+        self.node(&format!("{}Stmt", ast[id].kind()), ast.stmt_spans.get(id).copied(), fields)
+    }
+
+    fn optional_expr(&mut self, ast: &Ast, expr: Option<ExprId>) -> Json {
+        expr.map_or(Json::Null, |expr| expr.accept(ast, self))
+    }
+
+    fn stmts(&mut self, ast: &Ast, stmts: &[StmtId]) -> Json {
+        Json::Array(stmts.iter().map(|stmt| stmt.accept(ast, self)).collect())
+    }
+
+    fn function_fields(&mut self, ast: &Ast, function: &Function) -> Vec<(&'static str, Json)> {
+        vec![
+            ("name", function.name.as_ref().map_or(Json::Null, token_to_json)),
+            ("params", Json::Array(function.params.iter().map(token_to_json).collect())),
+            ("param_annotations", Json::Array(function.param_annotations.iter().map(optional_annotation_to_json).collect())),
+            ("return_annotation", optional_annotation_to_json(&function.return_annotation)),
+            ("body", self.stmts(ast, &function.body)),
+        ]
+    }
+}
+
+impl Visitor<Json> for JsonPrinter {
+    fn visit_binary(&mut self, ast: &Ast, id: ExprId, expr: &Binary) -> Json {
+        let fields = vec![
+            ("left", expr.left.accept(ast, self)),
+            ("op", token_to_json(&expr.op)),
+            ("right", expr.right.accept(ast, self)),
+        ];
+        self.expr_node(ast, id, fields)
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, id: ExprId, expr: &Grouping) -> Json {
+        let fields = vec![("expr", expr.expr.accept(ast, self))];
+        self.expr_node(ast, id, fields)
+    }
+
+    fn visit_literal(&mut self, ast: &Ast, id: ExprId, expr: &Literal) -> Json {
+        let value = match &expr.value {
+            LiteralValue::Number(value) => Json::Number(*value),
+            LiteralValue::String(value) => Json::String(value.clone()),
+            LiteralValue::Bool(value) => Json::Bool(*value),
+            LiteralValue::Null => Json::Null,
+        };
+        self.expr_node(ast, id, vec![("value", value)])
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, id: ExprId, expr: &Unary) -> Json {
+        let fields = vec![("op", token_to_json(&expr.op)), ("right", expr.right.accept(ast, self))];
+        self.expr_node(ast, id, fields)
+    }
+
+    fn visit_interpolation(&mut self, ast: &Ast, id: ExprId, expr: &Interpolation) -> Json {
+        let parts = expr.parts.iter().map(|part| match part {
+            TemplatePart::Chunk(chunk) => Json::String(chunk.clone()),
+            TemplatePart::Expr(expr) => expr.accept(ast, self),
+        });
+        let fields = vec![("parts", Json::Array(parts.collect()))];
+        self.expr_node(ast, id, fields)
+    }
+
+    fn visit_variable(&mut self, ast: &Ast, id: ExprId, expr: &Variable) -> Json {
+        self.expr_node(ast, id, vec![("name", token_to_json(&expr.name))])
+    }
+
+    fn visit_assign(&mut self, ast: &Ast, id: ExprId, expr: &Assign) -> Json {
+        let fields = vec![("name", token_to_json(&expr.name)), ("value", expr.value.accept(ast, self))];
+        self.expr_node(ast, id, fields)
+    }
+
+    fn visit_compound_assign(&mut self, ast: &Ast, id: ExprId, expr: &CompoundAssign) -> Json {
+        let fields = vec![
+            ("name", token_to_json(&expr.name)),
+            ("op", token_to_json(&expr.op)),
+            ("value", expr.value.accept(ast, self)),
+        ];
+        self.expr_node(ast, id, fields)
+    }
+
+    fn visit_logical(&mut self, ast: &Ast, id: ExprId, expr: &Logical) -> Json {
+        let fields = vec![
+            ("left", expr.left.accept(ast, self)),
+            ("op", token_to_json(&expr.op)),
+            ("right", expr.right.accept(ast, self)),
+        ];
+        self.expr_node(ast, id, fields)
+    }
+
+    fn visit_call(&mut self, ast: &Ast, id: ExprId, expr: &Call) -> Json {
+        let fields = vec![
+            ("callee", expr.callee.accept(ast, self)),
+            ("paren", token_to_json(&expr.paren)),
+            ("arguments", Json::Array(expr.arguments.iter().map(|argument| argument.accept(ast, self)).collect())),
+        ];
+        self.expr_node(ast, id, fields)
+    }
+
+    fn visit_function(&mut self, ast: &Ast, id: ExprId, expr: &Function) -> Json {
+        let fields = self.function_fields(ast, expr);
+        self.expr_node(ast, id, fields)
+    }
+
+    fn visit_list(&mut self, ast: &Ast, id: ExprId, expr: &List) -> Json {
+        let fields = vec![
+            ("bracket", token_to_json(&expr.bracket)),
+            ("elements", Json::Array(expr.elements.iter().map(|element| element.accept(ast, self)).collect())),
+        ];
+        self.expr_node(ast, id, fields)
+    }
+
+    fn visit_index(&mut self, ast: &Ast, id: ExprId, expr: &Index) -> Json {
+        let fields = vec![
+            ("object", expr.object.accept(ast, self)),
+            ("bracket", token_to_json(&expr.bracket)),
+            ("index", expr.index.accept(ast, self)),
+        ];
+        self.expr_node(ast, id, fields)
+    }
+}
+
+impl StmtVisitor<Json> for JsonPrinter {
+    fn visit_expression_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Expression) -> Json {
+        let fields = vec![("expr", stmt.expr.accept(ast, self))];
+        self.stmt_node(ast, id, fields)
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Print) -> Json {
+        let fields = vec![("expr", stmt.expr.accept(ast, self))];
+        self.stmt_node(ast, id, fields)
+    }
+
+    fn visit_let_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Let) -> Json {
+        let fields = vec![
+            ("name", token_to_json(&stmt.name)),
+            ("annotation", optional_annotation_to_json(&stmt.annotation)),
+            ("initializer", self.optional_expr(ast, stmt.initializer)),
+        ];
+        self.stmt_node(ast, id, fields)
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Block) -> Json {
+        let fields = vec![("stmts", self.stmts(ast, &stmt.stmts))];
+        self.stmt_node(ast, id, fields)
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &If) -> Json {
+        let fields = vec![
+            ("condition", stmt.condition.accept(ast, self)),
+            ("then_branch", stmt.then_branch.accept(ast, self)),
+            ("else_branch", stmt.else_branch.map_or(Json::Null, |branch| branch.accept(ast, self))),
+        ];
+        self.stmt_node(ast, id, fields)
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &While) -> Json {
+        let fields = vec![("condition", stmt.condition.accept(ast, self)), ("body", stmt.body.accept(ast, self))];
+        self.stmt_node(ast, id, fields)
+    }
+
+    fn visit_for_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &For) -> Json {
+        let fields = vec![
+            ("initializer", stmt.initializer.map_or(Json::Null, |initializer| initializer.accept(ast, self))),
+            ("condition", self.optional_expr(ast, stmt.condition)),
+            ("increment", self.optional_expr(ast, stmt.increment)),
+            ("body", stmt.body.accept(ast, self)),
+        ];
+        self.stmt_node(ast, id, fields)
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Function) -> Json {
+        let fields = self.function_fields(ast, stmt);
+        self.stmt_node(ast, id, fields)
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Return) -> Json {
+        let fields = vec![("keyword", token_to_json(&stmt.keyword)), ("value", self.optional_expr(ast, stmt.value))];
+        self.stmt_node(ast, id, fields)
+    }
+
+    fn visit_break_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Break) -> Json {
+        self.stmt_node(ast, id, vec![("keyword", token_to_json(&stmt.keyword))])
+    }
+}
+
+// 语法树的大纲：每个节点一个标签，TreePrinter和DotPrinter用不同的方式画出来
+struct Outline {
+    // 节点类型加上运算符、名字这样的关键信息
+    label: String,
+    span: Option<Span>,
+    children: Vec<Outline>,
+}
+
+impl Outline {
+    fn leaf(label: String) -> Outline {
+        Outline { label, span: None, children: vec![] }
+    }
+
+    fn program(ast: &Ast, stmts: &[StmtId]) -> Outline {
+        let children = stmts.iter().map(|stmt| stmt.accept(ast, &mut OutlineBuilder)).collect();
+        Outline { label: "Program".to_string(), span: None, children }
+    }
+
+    // `Binary + 0..5`，手工构造的树没有span
+    fn text(&self) -> String {
+        match self.span {
+            Some(span) => format!("{} {}..{}", self.label, span.start, span.end),
+            None => self.label.clone(),
+        }
+    }
+}
+
+struct OutlineBuilder;
+
+impl OutlineBuilder {
+    fn expr(&mut self, ast: &Ast, id: ExprId, label: String, children: &[ExprId]) -> Outline {
+        let children = children.iter().map(|child| child.accept(ast, self)).collect();
+        Outline { label, span: ast.expr_spans.get(id).copied(), children }
+    }
+
+    fn stmt(&mut self, ast: &Ast, id: StmtId, label: String, exprs: &[ExprId], stmts: &[StmtId]) -> Outline {
+        let mut children: Vec<Outline> = exprs.iter().map(|child| child.accept(ast, self)).collect();
+        children.extend(stmts.iter().map(|child| child.accept(ast, self)));
+        Outline { label, span: ast.stmt_spans.get(id).copied(), children }
+    }
+
+    // `Function add(a: number, b): number`，匿名函数没有名字
+    fn function_label(function: &Function) -> String {
+        let params: Vec<String> = function
+            .params
+            .iter()
+            .zip(&function.param_annotations)
+            .map(|(param, annotation)| format!("{}{}", param.lexeme, OutlineBuilder::annotation(annotation)))
+            .collect();
+        let name = function.name.as_ref().map_or("", |name| name.lexeme.as_str());
+        format!("Function {}({}){}", name, params.join(", "), OutlineBuilder::annotation(&function.return_annotation))
+    }
+
+    // `: number`，和源码里的写法一样
+    fn annotation(annotation: &Option<TypeAnnotation>) -> String {
+        annotation.as_ref().map_or(String::new(), |annotation| format!(": {}", annotation))
+    }
+}
+
+impl Visitor<Outline> for OutlineBuilder {
+    fn visit_binary(&mut self, ast: &Ast, id: ExprId, expr: &Binary) -> Outline {
+        self.expr(ast, id, format!("Binary {}", expr.op.lexeme), &[expr.left, expr.right])
+    }
+
+    fn visit_grouping(&mut self, ast: &Ast, id: ExprId, expr: &Grouping) -> Outline {
+        self.expr(ast, id, "Grouping".to_string(), &[expr.expr])
+    }
+
+    fn visit_literal(&mut self, ast: &Ast, id: ExprId, expr: &Literal) -> Outline {
+        let value = AstPrinter::new().visit_literal(ast, id, expr);
+        self.expr(ast, id, format!("Literal {}", value), &[])
+    }
+
+    fn visit_unary(&mut self, ast: &Ast, id: ExprId, expr: &Unary) -> Outline {
+        self.expr(ast, id, format!("Unary {}", expr.op.lexeme), &[expr.right])
+    }
+
+    fn visit_interpolation(&mut self, ast: &Ast, id: ExprId, expr: &Interpolation) -> Outline {
+        let mut outline = self.expr(ast, id, "Interpolation".to_string(), &[]);
+        for part in &expr.parts {
+            outline.children.push(match part {
+                TemplatePart::Chunk(chunk) => Outline::leaf(format!("Chunk {:?}", chunk)),
+                TemplatePart::Expr(expr) => expr.accept(ast, self),
+            });
+        }
+        outline
+    }
+
+    fn visit_variable(&mut self, ast: &Ast, id: ExprId, expr: &Variable) -> Outline {
+        self.expr(ast, id, format!("Variable {}", expr.name.lexeme), &[])
+    }
+
+    fn visit_assign(&mut self, ast: &Ast, id: ExprId, expr: &Assign) -> Outline {
+        self.expr(ast, id, format!("Assign {}", expr.name.lexeme), &[expr.value])
+    }
+
+    fn visit_compound_assign(&mut self, ast: &Ast, id: ExprId, expr: &CompoundAssign) -> Outline {
+        self.expr(ast, id, format!("CompoundAssign {} {}", expr.name.lexeme, expr.op.lexeme), &[expr.value])
+    }
+
+    fn visit_logical(&mut self, ast: &Ast, id: ExprId, expr: &Logical) -> Outline {
+        self.expr(ast, id, format!("Logical {}", expr.op.lexeme), &[expr.left, expr.right])
+    }
+
+    fn visit_call(&mut self, ast: &Ast, id: ExprId, expr: &Call) -> Outline {
+        let mut exprs = vec![expr.callee];
+        exprs.extend(expr.arguments.iter());
+        self.expr(ast, id, "Call".to_string(), &exprs)
+    }
+
+    fn visit_function(&mut self, ast: &Ast, id: ExprId, expr: &Function) -> Outline {
+        let mut outline = self.expr(ast, id, OutlineBuilder::function_label(expr), &[]);
+        outline.children = expr.body.iter().map(|stmt| stmt.accept(ast, self)).collect();
+        outline
+    }
+
+    fn visit_list(&mut self, ast: &Ast, id: ExprId, expr: &List) -> Outline {
+        self.expr(ast, id, "List".to_string(), &expr.elements)
+    }
+
+    fn visit_index(&mut self, ast: &Ast, id: ExprId, expr: &Index) -> Outline {
+        self.expr(ast, id, "Index".to_string(), &[expr.object, expr.index])
+    }
+}
+
+impl StmtVisitor<Outline> for OutlineBuilder {
+    fn visit_expression_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Expression) -> Outline {
+        self.stmt(ast, id, "Expression".to_string(), &[stmt.expr], &[])
+    }
+
+    fn visit_print_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Print) -> Outline {
+        self.stmt(ast, id, "Print".to_string(), &[stmt.expr], &[])
+    }
+
+    fn visit_let_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Let) -> Outline {
+        let initializer: Vec<ExprId> = stmt.initializer.into_iter().collect();
+        self.stmt(ast, id, format!("Let {}{}", stmt.name.lexeme, OutlineBuilder::annotation(&stmt.annotation)), &initializer, &[])
+    }
+
+    fn visit_block_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Block) -> Outline {
+        self.stmt(ast, id, "Block".to_string(), &[], &stmt.stmts)
+    }
+
+    fn visit_if_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &If) -> Outline {
+        let mut branches = vec![stmt.then_branch];
+        branches.extend(stmt.else_branch);
+        self.stmt(ast, id, "If".to_string(), &[stmt.condition], &branches)
+    }
+
+    fn visit_while_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &While) -> Outline {
+        self.stmt(ast, id, "While".to_string(), &[stmt.condition], &[stmt.body])
+    }
+
+    // 子节点按源码的顺序，省略的部分不画
+    fn visit_for_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &For) -> Outline {
+        let mut outline = self.stmt(ast, id, "For".to_string(), &[], &[]);
+        if let Some(initializer) = stmt.initializer {
+            outline.children.push(initializer.accept(ast, self));
+        }
+        for expr in stmt.condition.iter().chain(&stmt.increment) {
+            outline.children.push(expr.accept(ast, self));
+        }
+        outline.children.push(stmt.body.accept(ast, self));
+        outline
+    }
+
+    fn visit_function_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Function) -> Outline {
+        self.stmt(ast, id, OutlineBuilder::function_label(stmt), &[], &stmt.body)
+    }
+
+    fn visit_return_stmt(&mut self, ast: &Ast, id: StmtId, stmt: &Return) -> Outline {
+        let value: Vec<ExprId> = stmt.value.into_iter().collect();
+        self.stmt(ast, id, "Return".to_string(), &value, &[])
+    }
+
+    fn visit_break_stmt(&mut self, ast: &Ast, id: StmtId, _stmt: &Break) -> Outline {
+        self.stmt(ast, id, "Break".to_string(), &[], &[])
+    }
+}
+
+// 缩进的树，每个节点一行：
+// Program
+// └── Let x 0..10
+//     └── Literal 1 8..9
+#[derive(Default)]
+pub struct TreePrinter { }
+
+impl TreePrinter {
+    pub fn new() -> TreePrinter {
+        TreePrinter { }
+    }
+
+    pub fn print_expr(&mut self, ast: &Ast, expr: ExprId) -> String {
+        let mut lines = vec![];
+        TreePrinter::draw(&expr.accept(ast, &mut OutlineBuilder), "", "", &mut lines);
+        lines.join("\n")
+    }
+
+    pub fn print_program(&mut self, ast: &Ast, stmts: &[StmtId]) -> String {
+        let mut lines = vec![];
+        TreePrinter::draw(&Outline::program(ast, stmts), "", "", &mut lines);
+        lines.join("\n")
+    }
+
+    // first是节点所在行的前缀，rest是子节点所在行的前缀
+    fn draw(outline: &Outline, first: &str, rest: &str, lines: &mut Vec<String>) {
+        lines.push(format!("{}{}", first, outline.text()));
+        for (i, child) in outline.children.iter().enumerate() {
+            if i + 1 == outline.children.len() {
+                TreePrinter::draw(child, &format!("{}└── ", rest), &format!("{}    ", rest), lines);
+            } else {
+                TreePrinter::draw(child, &format!("{}├── ", rest), &format!("{}│   ", rest), lines);
+            }
+        }
+    }
+}
+
+// Graphviz的有向图，`dot -Tsvg`可以画出来，节点按先序编号
+#[derive(Default)]
+pub struct DotPrinter {
+    lines: Vec<String>,
+    count: usize,
+}
+
+impl DotPrinter {
+    pub fn new() -> DotPrinter {
+        DotPrinter { lines: vec![], count: 0 }
+    }
+
+    pub fn print_expr(&mut self, ast: &Ast, expr: ExprId) -> String {
+        let outline = expr.accept(ast, &mut OutlineBuilder);
+        self.graph(&outline)
+    }
+
+    pub fn print_program(&mut self, ast: &Ast, stmts: &[StmtId]) -> String {
+        let outline = Outline::program(ast, stmts);
+        self.graph(&outline)
+    }
+
+    fn graph(&mut self, outline: &Outline) -> String {
+        self.lines = vec!["digraph ast {".to_string(), "    node [shape=box];".to_string()];
+        self.count = 0;
+        self.node(outline);
+        self.lines.push("}".to_string());
+        self.lines.join("\n")
+    }
+
+    // 返回节点的名字，子节点的边跟在节点后面
+    fn node(&mut self, outline: &Outline) -> String {
+        let name = format!("n{}", self.count);
+        self.count += 1;
+        let mut label = outline.label.clone();
+        if let Some(span) = outline.span {
+            label.push_str(&format!("\n{}..{}", span.start, span.end));
+        }
+        self.lines.push(format!("    {} [label={}];", name, dot_string(&label)));
+        for child in &outline.children {
+            let child = self.node(child);
+            self.lines.push(format!("    {} -> {};", name, child));
+        }
+        name
+    }
+}
+
+// DOT的字符串，换行用\n表示
+fn dot_string(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::compiler_core::expr::Expr;
+    use crate::compiler_core::parser::Parser;
+    use crate::compiler_core::token::Token;
+    use crate::compiler_core::token::TokenType;
 
     #[test]
     fn test_ast_printer() {
-        let expr = Binary::new(
-            Box::new(Unary::new(
-                Token::new(TokenType::MINUS, "-".to_string(),  1),
-                Box::new(Literal::new(Box::new(123.0)))
-            )),
+        let mut ast = Ast::new();
+        let number = ast.exprs.alloc(Expr::Literal(Literal::new(LiteralValue::Number(123.0))));
+        let unary = ast.exprs.alloc(Expr::Unary(Unary::new(
+            Token::new(TokenType::MINUS, "-".to_string(),  1),
+            number
+        )));
+        let number = ast.exprs.alloc(Expr::Literal(Literal::new(LiteralValue::Number(45.67))));
+        let grouping = ast.exprs.alloc(Expr::Grouping(Grouping::new(number)));
+        let expr = ast.exprs.alloc(Expr::Binary(Binary::new(
+            unary,
             Token::new(TokenType::ASTERISK, "*".to_string(), 1),
-            Box::new(Grouping::new(
-                Box::new(Literal::new(Box::new(45.67)))
-            ))
-        );
+            grouping
+        )));
         let mut printer = AstPrinter::new();
-        let result = expr.accept(&mut printer);
+        let result = printer.print_expr(&ast, expr);
         println!("{}", result);
         assert_eq!(result, "(* (- 123) (group 45.67))");
     }
+
+    #[test]
+    fn tree_printer_test() {
+        let mut parser = Parser::new("let x = -1;\nif x { print \"a${x}\"; } else { f(x, 2); }");
+        let program = parser.parse_program();
+        assert_eq!(
+            TreePrinter::new().print_program(parser.ast(), &program),
+            "Program
+├── Let x 0..11
+│   └── Unary - 8..10
+│       └── Literal 1 9..10
+└── If 12..53
+    ├── Variable x 15..16
+    ├── Block 17..35
+    │   └── Print 19..33
+    │       └── Interpolation 25..32
+    │           ├── Chunk \"a\"
+    │           └── Variable x 29..30
+    └── Block 41..53
+        └── Expression 43..51
+            └── Call 43..50
+                ├── Variable f 43..44
+                ├── Variable x 45..46
+                └── Literal 2 48..49"
+        );
+
+        // 标注写在标签里，和源码一样
+        let mut parser = Parser::new("let xs: list<a>; fn f(g: fn(number): bool) : map<string, a> { }");
+        let program = parser.parse_program();
+        assert_eq!(
+            TreePrinter::new().print_program(parser.ast(), &program),
+            "Program
+├── Let xs: list<a> 0..16
+└── Function f(g: fn(number): bool): map<string, a> 17..63"
+        );
+    }
+
+    #[test]
+    fn dot_printer_test() {
+        let mut parser = Parser::new("fn f(a) { return \"a\" + a; }");
+        let program = parser.parse_program();
+        assert_eq!(
+            DotPrinter::new().print_program(parser.ast(), &program),
+            r#"digraph ast {
+    node [shape=box];
+    n0 [label="Program"];
+    n1 [label="Function f(a)\n0..27"];
+    n2 [label="Return\n10..25"];
+    n3 [label="Binary +\n17..24"];
+    n4 [label="Literal \"a\"\n17..20"];
+    n3 -> n4;
+    n5 [label="Variable a\n23..24"];
+    n3 -> n5;
+    n2 -> n3;
+    n1 -> n2;
+    n0 -> n1;
+}"#
+        );
+    }
     
 }
 
@@ -0,0 +1,270 @@
+use super::ast::Ast;
+use super::ast::ExprId;
+use super::ast::MapNodes;
+use super::ast::Node;
+use super::ast::StmtId;
+use super::ast::Walk;
+use super::expr::ExprWalker;
+use super::stmt::StmtWalker;
+use super::token::Token;
+
+// 三种遍历语法树的方式：
+// - Walker: 只读，默认访问所有节点，pass只覆盖关心的节点(比如收集变量、lint)
+// - Fold: 把树重建到一个新的Ast里，默认原样复制，适合把一种语法换成另一种(去语法糖)
+// - MutVisitor: 直接改写Ast里的节点，默认先访问子节点，适合常量折叠这样的局部替换
+
+// 同时实现了ExprWalker和StmtWalker的类型，两个trait的方法都有默认实现，所以只需要
+//     impl ExprWalker for Pass { fn visit_variable(...) { ... } }
+//     impl StmtWalker for Pass {}
+pub trait Walker: ExprWalker + StmtWalker {}
+
+impl<T: ExprWalker + StmtWalker> Walker for T {}
+
+// 按顺序访问node的所有直接子节点
+pub fn walk_children<W: Walker>(walker: &mut W, ast: &Ast, node: &impl Walk) {
+    node.walk(&mut |child| match child {
+        Node::Expr(id) => walker.visit_expr(ast, id),
+        Node::Stmt(id) => walker.visit_stmt(ast, id),
+    });
+}
+
+pub fn walk_program<W: Walker>(walker: &mut W, ast: &Ast, stmts: &[StmtId]) {
+    for &stmt in stmts {
+        walker.visit_stmt(ast, stmt);
+    }
+}
+
+// 把ast里的节点重建到output()里，新节点保留原来的span，报错的时候还能找到源码位置
+// 覆盖fold_expr/fold_stmt改写需要的节点，其他的交给同名的函数原样复制
+pub trait Fold {
+    fn output(&mut self) -> &mut Ast;
+
+    fn fold_expr(&mut self, ast: &Ast, id: ExprId) -> ExprId {
+        fold_expr(self, ast, id)
+    }
+
+    fn fold_stmt(&mut self, ast: &Ast, id: StmtId) -> StmtId {
+        fold_stmt(self, ast, id)
+    }
+
+    fn fold_token(&mut self, token: &Token) -> Token {
+        token.clone()
+    }
+}
+
+// 复制一个节点，子节点先递归地fold
+pub fn fold_expr<F: Fold + ?Sized>(folder: &mut F, ast: &Ast, id: ExprId) -> ExprId {
+    let expr = ast[id].map(&mut Folder { folder, ast });
+    folder.output().add_expr(expr, ast.expr_span(id))
+}
+
+pub fn fold_stmt<F: Fold + ?Sized>(folder: &mut F, ast: &Ast, id: StmtId) -> StmtId {
+    let stmt = ast[id].map(&mut Folder { folder, ast });
+    folder.output().add_stmt(stmt, ast.stmt_span(id))
+}
+
+pub fn fold_program<F: Fold + ?Sized>(folder: &mut F, ast: &Ast, stmts: &[StmtId]) -> Vec<StmtId> {
+    stmts.iter().map(|&stmt| folder.fold_stmt(ast, stmt)).collect()
+}
+
+struct Folder<'a, F: ?Sized> {
+    folder: &'a mut F,
+    ast: &'a Ast,
+}
+
+impl<F: Fold + ?Sized> MapNodes for Folder<'_, F> {
+    fn map_expr(&mut self, id: ExprId) -> ExprId {
+        self.folder.fold_expr(self.ast, id)
+    }
+
+    fn map_stmt(&mut self, id: StmtId) -> StmtId {
+        self.folder.fold_stmt(self.ast, id)
+    }
+
+    fn map_token(&mut self, token: &Token) -> Token {
+        self.folder.fold_token(token)
+    }
+}
+
+// 原地改写节点：`ast.exprs[id] = ...`，id和span都不变，其他节点对它的引用也不用改
+// 默认先访问子节点，覆盖的方法里调用walk_expr_mut就是先处理子节点再处理自己
+pub trait MutVisitor {
+    fn visit_expr_mut(&mut self, ast: &mut Ast, id: ExprId) {
+        walk_expr_mut(self, ast, id)
+    }
+
+    fn visit_stmt_mut(&mut self, ast: &mut Ast, id: StmtId) {
+        walk_stmt_mut(self, ast, id)
+    }
+}
+
+pub fn walk_expr_mut<V: MutVisitor + ?Sized>(visitor: &mut V, ast: &mut Ast, id: ExprId) {
+    let children = children(&ast[id]);
+    walk_nodes_mut(visitor, ast, children);
+}
+
+pub fn walk_stmt_mut<V: MutVisitor + ?Sized>(visitor: &mut V, ast: &mut Ast, id: StmtId) {
+    let children = children(&ast[id]);
+    walk_nodes_mut(visitor, ast, children);
+}
+
+pub fn walk_program_mut<V: MutVisitor + ?Sized>(visitor: &mut V, ast: &mut Ast, stmts: &[StmtId]) {
+    for &stmt in stmts {
+        visitor.visit_stmt_mut(ast, stmt);
+    }
+}
+
+// 访问子节点的时候要修改ast，所以先把子节点的id取出来
+fn children(node: &impl Walk) -> Vec<Node> {
+    let mut children = vec![];
+    node.walk(&mut |child| children.push(child));
+    children
+}
+
+fn walk_nodes_mut<V: MutVisitor + ?Sized>(visitor: &mut V, ast: &mut Ast, nodes: Vec<Node>) {
+    for node in nodes {
+        match node {
+            Node::Expr(id) => visitor.visit_expr_mut(ast, id),
+            Node::Stmt(id) => visitor.visit_stmt_mut(ast, id),
+        }
+    }
+}
+
+// 两棵树的结构一样：忽略token的位置、节点的span和id
+pub fn same_program(a: &Ast, a_stmts: &[StmtId], b: &Ast, b_stmts: &[StmtId]) -> bool {
+    let mut a_folder = StripPositions { output: Ast::new() };
+    let mut b_folder = StripPositions { output: Ast::new() };
+    let a_stmts = fold_program(&mut a_folder, a, a_stmts);
+    let b_stmts = fold_program(&mut b_folder, b, b_stmts);
+    a_stmts == b_stmts && a_folder.same_nodes(&b_folder)
+}
+
+pub fn same_expr(a: &Ast, a_expr: ExprId, b: &Ast, b_expr: ExprId) -> bool {
+    let mut a_folder = StripPositions { output: Ast::new() };
+    let mut b_folder = StripPositions { output: Ast::new() };
+    let a_expr = a_folder.fold_expr(a, a_expr);
+    let b_expr = b_folder.fold_expr(b, b_expr);
+    a_expr == b_expr && a_folder.same_nodes(&b_folder)
+}
+
+// 复制到新的Ast里，节点按照子节点在前的顺序重新分配id，token只保留种类和文本
+struct StripPositions {
+    output: Ast,
+}
+
+impl StripPositions {
+    // span不比较，只比较节点本身
+    fn same_nodes(&self, other: &StripPositions) -> bool {
+        self.output.exprs == other.output.exprs && self.output.stmts == other.output.stmts
+    }
+}
+
+impl Fold for StripPositions {
+    fn output(&mut self) -> &mut Ast {
+        &mut self.output
+    }
+
+    fn fold_token(&mut self, token: &Token) -> Token {
+        Token::new(token.token_type, token.lexeme.clone(), 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler_core::expr::Expr;
+    use crate::compiler_core::expr::Function;
+    use crate::compiler_core::expr::Literal;
+    use crate::compiler_core::expr::LiteralValue;
+    use crate::compiler_core::expr::Variable;
+    use crate::compiler_core::parser::Parser;
+    use crate::compiler_core::visitors::AstPrinter;
+
+    use super::*;
+
+    // 只关心变量，函数体里的不算
+    #[derive(Default)]
+    struct Variables {
+        names: Vec<String>,
+    }
+
+    impl ExprWalker for Variables {
+        fn visit_variable(&mut self, _ast: &Ast, _id: ExprId, expr: &Variable) {
+            self.names.push(expr.name.lexeme.clone());
+        }
+
+        fn visit_function(&mut self, _ast: &Ast, _id: ExprId, _expr: &Function) {}
+    }
+
+    impl StmtWalker for Variables {
+        fn visit_function_stmt(&mut self, _ast: &Ast, _id: StmtId, _stmt: &Function) {}
+    }
+
+    #[test]
+    fn walker_test() {
+        let mut parser = Parser::new("let a = b + c(d); fn f() { return e; } if a { print \"${g}\"; } let h = fn() { i; };");
+        let program = parser.parse_program();
+        let mut variables = Variables::default();
+        walk_program(&mut variables, parser.ast(), &program);
+        assert_eq!(variables.names, ["b", "c", "d", "a", "g"]);
+    }
+
+    // 去掉所有的括号
+    struct RemoveGrouping {
+        output: Ast,
+    }
+
+    impl Fold for RemoveGrouping {
+        fn output(&mut self) -> &mut Ast {
+            &mut self.output
+        }
+
+        fn fold_expr(&mut self, ast: &Ast, id: ExprId) -> ExprId {
+            match &ast[id] {
+                Expr::Grouping(grouping) => self.fold_expr(ast, grouping.expr),
+                _ => fold_expr(self, ast, id),
+            }
+        }
+    }
+
+    #[test]
+    fn fold_test() {
+        let source = "print ((1 + 2)) * 3; fn f(a) { return (a); }";
+        let mut parser = Parser::new(source);
+        let program = parser.parse_program();
+        let mut folder = RemoveGrouping { output: Ast::new() };
+        let program = fold_program(&mut folder, parser.ast(), &program);
+        let ast = folder.output;
+        assert_eq!(AstPrinter::new().print_program(&ast, &program), "(print (* (+ 1 2) 3))\n(fn f (a) (return a))");
+        // 原来的树没有变，新节点的span还指向原来的源码
+        assert_eq!(parser.ast().exprs.len(), 9);
+        assert_eq!(ast.exprs.len(), 6);
+        let spans: Vec<&str> = ast.exprs.iter().map(|(id, _)| &source[ast.expr_span(id).start..ast.expr_span(id).end]).collect();
+        assert_eq!(spans, ["1", "2", "1 + 2", "3", "((1 + 2)) * 3", "a"]);
+    }
+
+    // 把变量pi换成常量
+    struct InlinePi;
+
+    impl MutVisitor for InlinePi {
+        fn visit_expr_mut(&mut self, ast: &mut Ast, id: ExprId) {
+            walk_expr_mut(self, ast, id);
+            if matches!(&ast[id], Expr::Variable(variable) if variable.name.lexeme == "pi") {
+                ast.exprs[id] = Expr::Literal(Literal::new(LiteralValue::Number(3.5)));
+            }
+        }
+    }
+
+    #[test]
+    fn mut_visitor_test() {
+        let mut parser = Parser::new("print pi * 2; fn f(r) { return pi * r * r; }");
+        let program = parser.parse_program();
+        let mut ast = parser.into_ast();
+        let nodes = ast.exprs.len();
+        walk_program_mut(&mut InlinePi, &mut ast, &program);
+        assert_eq!(ast.exprs.len(), nodes);
+        assert_eq!(
+            AstPrinter::new().print_program(&ast, &program),
+            "(print (* 3.5 2))\n(fn f (r) (return (* (* 3.5 r) r)))"
+        );
+    }
+}
@@ -0,0 +1 @@
+pub mod compiler_core;